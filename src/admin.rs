@@ -0,0 +1,118 @@
+use crate::api::{AdminUser, ImmichClient};
+use crate::config::{Config, UserConfig};
+use anyhow::{Context, Result, bail};
+
+/// Name of the API keys created for provisioned users.
+const API_KEY_NAME: &str = "rimmich-uploader";
+
+/// Prints every account on the server with its role, status and storage use.
+pub async fn list_users(client: &ImmichClient) -> Result<()> {
+    let mut users = client
+        .admin_list_users()
+        .await
+        .context("Failed to list users (is this an admin's API key?)")?;
+    users.sort_by(|a, b| a.email.cmp(&b.email));
+    for user in &users {
+        let mut notes = Vec::new();
+        if user.is_admin {
+            notes.push("admin".to_string());
+        }
+        if user.deleted_at.is_some() {
+            notes.push("disabled".to_string());
+        }
+        let usage = user.quota_usage_in_bytes.unwrap_or(0);
+        notes.push(match user.quota_size_in_bytes {
+            Some(quota) => format!(
+                "{} of {} used",
+                indicatif::HumanBytes(usage),
+                indicatif::HumanBytes(quota)
+            ),
+            None => format!("{} used", indicatif::HumanBytes(usage)),
+        });
+        println!("{} ({}): {}", user.email, user.name, notes.join(", "));
+    }
+    Ok(())
+}
+
+/// Creates an account. With `save_as`, also logs in as the new user, creates an API key
+/// for them and stores it as a configured user of that name, ready for uploads.
+pub async fn create_user(
+    client: &ImmichClient,
+    config: &mut Config,
+    email: &str,
+    name: &str,
+    password: &str,
+    quota_gib: Option<u64>,
+    save_as: Option<&str>,
+) -> Result<()> {
+    if let Some(save_as) = save_as
+        && config.users.contains_key(save_as)
+    {
+        bail!("User '{}' is already configured", save_as);
+    }
+    let user = client
+        .admin_create_user(email, name, password, quota_gib.map(|gib| gib << 30))
+        .await
+        .context("Failed to create user (is this an admin's API key?)")?;
+    println!("Created {} ({}).", user.email, user.id);
+
+    let Some(save_as) = save_as else {
+        return Ok(());
+    };
+    let session = client
+        .login(email, password)
+        .await
+        .context("Failed to log in as the new user")?;
+    let api_key = session
+        .create_api_key(API_KEY_NAME)
+        .await
+        .context("Failed to create an API key for the new user")?;
+    config.users.insert(
+        save_as.to_string(),
+        UserConfig {
+            api_key,
+            server_url: client.server_url().to_string(),
+            album: None,
+            archive: false,
+            device_id: None,
+        },
+    );
+    config.save()?;
+    println!(
+        "Configured user '{}'; upload as them with --user {}.",
+        save_as, save_as
+    );
+    Ok(())
+}
+
+/// Disables an account by deleting it, which the server keeps restorable until the
+/// deleted account is purged; or restores it with `enable`.
+pub async fn set_disabled(client: &ImmichClient, email: &str, disabled: bool) -> Result<()> {
+    let user = find_user(client, email).await?;
+    match (disabled, user.deleted_at.is_some()) {
+        (true, true) => println!("{} is already disabled.", email),
+        (false, false) => println!("{} is already enabled.", email),
+        (true, false) => {
+            client.admin_delete_user(&user.id).await?;
+            println!(
+                "Disabled {}; `admin user enable` restores it until the server purges it.",
+                email
+            );
+        }
+        (false, true) => {
+            client.admin_restore_user(&user.id).await?;
+            println!("Enabled {}.", email);
+        }
+    }
+    Ok(())
+}
+
+async fn find_user(client: &ImmichClient, email: &str) -> Result<AdminUser> {
+    client
+        .admin_list_users()
+        .await
+        .context("Failed to list users (is this an admin's API key?)")?
+        .into_iter()
+        .find(|user| user.email.eq_ignore_ascii_case(email))
+        .with_context(|| format!("No user with email {}", email))
+}
@@ -0,0 +1,118 @@
+use crate::api::{Album, Asset, ImmichClient};
+use anyhow::{Context, Result};
+use serde_json::json;
+
+/// Order assets are shown in within an album.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum AlbumOrder {
+    OldestFirst,
+    NewestFirst,
+}
+
+impl AlbumOrder {
+    fn as_str(self) -> &'static str {
+        match self {
+            AlbumOrder::OldestFirst => "asc",
+            AlbumOrder::NewestFirst => "desc",
+        }
+    }
+}
+
+/// Finds the album with this name, without its assets.
+async fn find(client: &ImmichClient, name: &str) -> Result<Album> {
+    client
+        .list_albums()
+        .await
+        .context("Failed to list albums")?
+        .into_iter()
+        .find(|a| a.album_name == name)
+        .with_context(|| format!("Album '{}' not found", name))
+}
+
+/// Fetches the album with this name, including its assets.
+pub async fn fetch(client: &ImmichClient, name: &str) -> Result<Album> {
+    let album = find(client, name).await?;
+    client
+        .get_album(&album.id)
+        .await
+        .with_context(|| format!("Failed to fetch album '{}'", name))
+}
+
+/// Names of the albums to change: the given ones, or with `all` every album.
+pub async fn names(client: &ImmichClient, names: Vec<String>, all: bool) -> Result<Vec<String>> {
+    if !all {
+        return Ok(names);
+    }
+    let mut names: Vec<String> = client
+        .list_albums()
+        .await
+        .context("Failed to list albums")?
+        .into_iter()
+        .map(|a| a.album_name)
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Sets an album's cover to `asset_id`, or else to its earliest photo (or video, in an
+/// album without photos).
+pub async fn set_cover(client: &ImmichClient, name: &str, asset_id: Option<&str>) -> Result<()> {
+    let album = fetch(client, name).await?;
+    let cover = match asset_id {
+        Some(id) => album
+            .assets
+            .iter()
+            .find(|a| a.id == id)
+            .with_context(|| format!("Asset {} is not in album '{}'", id, name))?,
+        None => {
+            let photos: Vec<&Asset> = album
+                .assets
+                .iter()
+                .filter(|a| a.asset_type.as_deref() == Some("IMAGE"))
+                .collect();
+            let candidates = match photos.is_empty() {
+                true => album.assets.iter().collect(),
+                false => photos,
+            };
+            candidates
+                .into_iter()
+                .min_by_key(|a| a.file_created_at)
+                .with_context(|| format!("Album '{}' is empty", name))?
+        }
+    };
+    client
+        .update_album(&album.id, json!({ "albumThumbnailAssetId": cover.id }))
+        .await
+        .with_context(|| format!("Failed to set the cover of album '{}'", name))?;
+    println!(
+        "Set the cover of album '{}' to {} ({}).",
+        name, cover.original_file_name, cover.id
+    );
+    Ok(())
+}
+
+/// Replaces an album's description ("" clears it).
+pub async fn describe(client: &ImmichClient, name: &str, description: &str) -> Result<()> {
+    let album = find(client, name).await?;
+    client
+        .update_album(&album.id, json!({ "description": description }))
+        .await
+        .with_context(|| format!("Failed to describe album '{}'", name))?;
+    println!("Updated the description of album '{}'.", name);
+    Ok(())
+}
+
+/// Sets the order an album shows its assets in.
+pub async fn sort(client: &ImmichClient, name: &str, order: AlbumOrder) -> Result<()> {
+    let album = find(client, name).await?;
+    client
+        .update_album(&album.id, json!({ "order": order.as_str() }))
+        .await
+        .with_context(|| format!("Failed to sort album '{}'", name))?;
+    let order = match order {
+        AlbumOrder::OldestFirst => "oldest",
+        AlbumOrder::NewestFirst => "newest",
+    };
+    println!("Album '{}' now shows its {} assets first.", name, order);
+    Ok(())
+}
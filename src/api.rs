@@ -0,0 +1,1074 @@
+use crate::config::HttpConfig;
+use crate::progress;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Method, multipart};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Scheme of server URLs pointing at a Unix domain socket, e.g. "unix:///run/immich.sock".
+pub const UNIX_SCHEME: &str = "unix://";
+
+/// Thin wrapper around the Immich REST API for a single server and set of credentials.
+pub struct ImmichClient {
+    client: reqwest::Client,
+    server_url: String,
+    credentials: Credentials,
+    /// Set when the server predates v1.106 and only knows the old singular routes.
+    legacy: AtomicBool,
+}
+
+/// How requests authenticate with the server.
+enum Credentials {
+    /// A user's API key, giving access to the whole API.
+    ApiKey(String),
+    /// The key of an upload-enabled shared link, only allowing uploads into its album.
+    SharedLink(String),
+    /// A session token from logging in with a password.
+    AccessToken(String),
+}
+
+/// Result of an asset upload as reported by the server.
+#[derive(Deserialize, Debug)]
+pub struct UploadResponse {
+    /// ID of the created (or already existing) asset.
+    pub id: String,
+    /// "created" or "duplicate"; missing on servers before v1.106.
+    pub status: Option<String>,
+    /// What servers before v1.106 report instead of the status.
+    #[serde(default)]
+    duplicate: bool,
+}
+
+impl UploadResponse {
+    /// Whether the upload created a new asset rather than matching an existing one.
+    pub fn is_created(&self) -> bool {
+        !self.duplicate && self.status.as_deref() != Some("duplicate")
+    }
+}
+
+/// Jobs of one server queue, as reported by the jobs API.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct JobQueue {
+    pub job_counts: JobCounts,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct JobCounts {
+    pub active: u64,
+    pub waiting: u64,
+    #[serde(default)]
+    pub delayed: u64,
+}
+
+/// A tag as returned by the Immich tags API.
+#[derive(Deserialize, Debug)]
+pub struct Tag {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// Full hierarchical name, e.g. "Places/Paris".
+    #[serde(default)]
+    pub value: String,
+}
+
+/// A person recognized by the server's face detection.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Person {
+    pub id: String,
+    /// Empty until the person is named.
+    #[serde(default)]
+    pub name: String,
+}
+
+/// An album as returned by the Immich albums API.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Album {
+    pub id: String,
+    pub album_name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Only filled in when fetching a single album.
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+/// An asset as returned by the Immich assets and search APIs.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Asset {
+    pub id: String,
+    pub original_file_name: String,
+    /// Where the server keeps the original file.
+    pub original_path: Option<String>,
+    pub file_created_at: DateTime<Utc>,
+    pub file_modified_at: DateTime<Utc>,
+    #[serde(default)]
+    pub is_favorite: bool,
+    /// Archive flag of servers before the `visibility` field was introduced.
+    #[serde(default)]
+    pub is_archived: bool,
+    /// "timeline", "archive", "hidden" or "locked" on current servers.
+    pub visibility: Option<String>,
+    pub live_photo_video_id: Option<String>,
+    /// Placeholder hash, set once the server generated the thumbnail.
+    pub thumbhash: Option<String>,
+    /// "IMAGE", "VIDEO", "AUDIO" or "OTHER".
+    #[serde(rename = "type")]
+    pub asset_type: Option<String>,
+    pub exif_info: Option<ExifInfo>,
+    /// Device that uploaded the asset.
+    pub device_id: Option<String>,
+    /// Base64-encoded SHA-1 of the original file.
+    pub checksum: Option<String>,
+    /// Recognized people; only filled in when requested.
+    #[serde(default)]
+    pub people: Vec<Person>,
+    /// Tags; only filled in when fetching a single asset.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+/// EXIF details of an asset; only requested fields are used.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExifInfo {
+    pub description: Option<String>,
+    pub file_size_in_byte: Option<u64>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Star rating (1-5, or -1 for rejected).
+    pub rating: Option<i32>,
+}
+
+impl Asset {
+    /// Whether the asset is archived, on both old and new servers.
+    pub fn archived(&self) -> bool {
+        self.is_archived || self.visibility.as_deref() == Some("archive")
+    }
+
+    /// Size of the original file, when the server reported it.
+    pub fn file_size(&self) -> Option<u64> {
+        self.exif_info.as_ref().and_then(|e| e.file_size_in_byte)
+    }
+
+    /// The user-entered description, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.exif_info
+            .as_ref()
+            .and_then(|e| e.description.as_deref())
+            .filter(|d| !d.is_empty())
+    }
+}
+
+/// A user account as seen by an administrator.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUser {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    #[serde(default)]
+    pub is_admin: bool,
+    /// Set while the account is deleted but can still be restored.
+    pub deleted_at: Option<String>,
+    pub quota_size_in_bytes: Option<u64>,
+    pub quota_usage_in_bytes: Option<u64>,
+}
+
+/// A user of the server, as listed for sharing.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    /// Storage quota, only reported for the current user; unlimited when unset.
+    pub quota_size_in_bytes: Option<u64>,
+    pub quota_usage_in_bytes: Option<u64>,
+}
+
+/// Disk usage of the server's upload location.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStorage {
+    pub disk_available_raw: u64,
+}
+
+/// An API key as described by the server, without its secret.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub name: String,
+    /// Granted permissions, e.g. "asset.upload", or "all".
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+/// Whether the server already has a file, as answered by the bulk upload check.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadCheck {
+    /// The ID the file was submitted with.
+    pub id: String,
+    /// "accept" when the file should be uploaded, "reject" otherwise.
+    pub action: String,
+    /// Why the file was rejected, e.g. "duplicate".
+    pub reason: Option<String>,
+    /// The existing asset, for duplicates.
+    pub asset_id: Option<String>,
+}
+
+/// Assets the server considers visually similar.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PeopleResponse {
+    people: Vec<Person>,
+}
+
+#[derive(Deserialize, Debug)]
+struct UploadCheckResponse {
+    results: Vec<UploadCheck>,
+}
+
+/// One page of metadata search results.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SearchResponse {
+    assets: SearchPage,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SearchPage {
+    items: Vec<Asset>,
+    next_page: Option<String>,
+}
+
+impl ImmichClient {
+    /// Creates a client for the given server, ignoring any trailing slash in the URL.
+    pub fn new(client: reqwest::Client, server_url: &str, api_key: &str) -> Self {
+        ImmichClient {
+            client,
+            server_url: server_url.trim_end_matches('/').to_string(),
+            credentials: Credentials::ApiKey(api_key.to_string()),
+            legacy: AtomicBool::new(false),
+        }
+    }
+
+    /// Creates a client authenticating with a shared link key instead of an API key.
+    /// Uploads made this way land in the shared link's album.
+    pub fn with_share_key(client: reqwest::Client, server_url: &str, share_key: &str) -> Self {
+        ImmichClient {
+            client,
+            server_url: server_url.trim_end_matches('/').to_string(),
+            credentials: Credentials::SharedLink(share_key.to_string()),
+            legacy: AtomicBool::new(false),
+        }
+    }
+
+    /// Base URL of the server, without a trailing slash.
+    pub fn server_url(&self) -> &str {
+        &self.server_url
+    }
+
+    /// Whether the client is limited to uploading through a shared link.
+    pub fn is_shared_link(&self) -> bool {
+        matches!(self.credentials, Credentials::SharedLink(_))
+    }
+
+    fn url(&self, path: &str) -> String {
+        // Requests over a Unix socket still need an HTTP URL; its host only ends up in the
+        // Host header.
+        let base = match self.server_url.starts_with(UNIX_SCHEME) {
+            true => "http://localhost",
+            false => &self.server_url,
+        };
+        format!("{}/api{}", base, path)
+    }
+
+    /// Starts an authenticated request to an API path, translated for legacy servers.
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let url = if self.legacy.load(Ordering::Relaxed) {
+            self.url(&legacy_path(&method, path))
+        } else {
+            self.url(path)
+        };
+        let builder = self.client.request(method, url);
+        match &self.credentials {
+            Credentials::ApiKey(key) => builder.header("x-api-key", key),
+            Credentials::SharedLink(key) => builder.header("x-immich-share-key", key),
+            Credentials::AccessToken(token) => builder.bearer_auth(token),
+        }
+    }
+
+    /// Pings the Immich server to verify connectivity.
+    /// Servers before v1.106 only answer on the old route; they are then talked to with the
+    /// legacy routes from here on.
+    pub async fn ping(&self) -> Result<()> {
+        let mut resp = self.client.get(self.url("/server/ping")).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            resp = self
+                .client
+                .get(self.url("/server-info/ping"))
+                .send()
+                .await?;
+            if resp.status().is_success() {
+                self.legacy.store(true, Ordering::Relaxed);
+                let version = self.legacy_version().await.unwrap_or_default();
+                progress::note(format!(
+                    "Server {} predates Immich v1.106, using the legacy API.",
+                    version
+                ));
+            }
+        }
+        if !resp.status().is_success() {
+            anyhow::bail!("Server ping failed: {}", resp.status());
+        }
+        let body = resp.text().await?;
+        // Immich ping returns "pong" on success.
+        if !body.contains("pong") {
+            anyhow::bail!("Unexpected response from ping: {}", body);
+        }
+        Ok(())
+    }
+
+    /// Version reported by a legacy server, e.g. "v1.105.1".
+    async fn legacy_version(&self) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Version {
+            major: u32,
+            minor: u32,
+            patch: u32,
+        }
+        let response = self
+            .client
+            .get(self.url("/server-info/version"))
+            .send()
+            .await?;
+        let v: Version = check(response).await?.json().await?;
+        Ok(format!("v{}.{}.{}", v.major, v.minor, v.patch))
+    }
+
+    /// Checks that the server accepts the API key.
+    pub async fn validate_key(&self) -> Result<()> {
+        let response = self
+            .request(Method::POST, "/auth/validateToken")
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Uploads a single asset.
+    /// Returns `None` when the server rejected the asset as already existing without reporting its ID.
+    pub async fn upload_asset(&self, form: multipart::Form) -> Result<Option<UploadResponse>> {
+        self.upload_asset_with_checksum(form, None).await
+    }
+
+    /// Uploads an asset, announcing the base64 SHA-1 of its file in the
+    /// `x-immich-checksum` header. Servers that know the header answer "duplicate" for a
+    /// file they already have without storing the upload; others ignore it.
+    pub async fn upload_asset_with_checksum(
+        &self,
+        form: multipart::Form,
+        checksum: Option<&str>,
+    ) -> Result<Option<UploadResponse>> {
+        let mut request = self.request(Method::POST, "/assets").multipart(form);
+        if let Some(checksum) = checksum {
+            request = request.header("x-immich-checksum", checksum);
+        }
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            // If it's 409 Conflict, it means it's already there (behavior depends on Immich API version).
+            if status == reqwest::StatusCode::CONFLICT || body.contains("already exists") {
+                return Ok(None);
+            }
+            anyhow::bail!("Server returned error {}: {}", status, body);
+        }
+
+        Ok(Some(response.json().await?))
+    }
+
+    /// Creates any missing tags (using "/" for hierarchy) and returns all of them.
+    pub async fn upsert_tags(&self, names: &[String]) -> Result<Vec<Tag>> {
+        let response = self
+            .request(Method::PUT, "/tags")
+            .json(&json!({ "tags": names }))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Applies the given tags to the given assets.
+    pub async fn tag_assets(&self, tag_ids: &[String], asset_ids: &[String]) -> Result<()> {
+        let response = self
+            .request(Method::PUT, "/tags/assets")
+            .json(&json!({ "tagIds": tag_ids, "assetIds": asset_ids }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Lists all of the user's tags.
+    pub async fn list_tags(&self) -> Result<Vec<Tag>> {
+        let response = self.request(Method::GET, "/tags").send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Lists the people recognized in the user's assets, including hidden ones.
+    pub async fn list_people(&self) -> Result<Vec<Person>> {
+        let response = self
+            .request(Method::GET, "/people")
+            .query(&[("withHidden", "true")])
+            .send()
+            .await?;
+        let response: PeopleResponse = check(response).await?.json().await?;
+        Ok(response.people)
+    }
+
+    /// Lists all albums owned by or shared with the user.
+    pub async fn list_albums(&self) -> Result<Vec<Album>> {
+        let response = self.request(Method::GET, "/albums").send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Fetches an album including its assets.
+    pub async fn get_album(&self, album_id: &str) -> Result<Album> {
+        let response = self
+            .request(Method::GET, &format!("/albums/{}", album_id))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Creates an empty album.
+    pub async fn create_album(&self, name: &str, description: Option<&str>) -> Result<Album> {
+        let response = self
+            .request(Method::POST, "/albums")
+            .json(&json!({ "albumName": name, "description": description.unwrap_or_default() }))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Changes an album's settings, given as fields of the update request (such as
+    /// `description` or `albumThumbnailAssetId`).
+    pub async fn update_album(&self, album_id: &str, changes: serde_json::Value) -> Result<()> {
+        let response = self
+            .request(Method::PATCH, &format!("/albums/{}", album_id))
+            .json(&changes)
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Adds assets to an album. Assets already in the album are ignored by the server.
+    pub async fn add_assets_to_album(&self, album_id: &str, asset_ids: &[String]) -> Result<()> {
+        let response = self
+            .request(Method::PUT, &format!("/albums/{}/assets", album_id))
+            .json(&json!({ "ids": asset_ids }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Lists all of the user's timeline and archived assets, including EXIF details.
+    pub async fn all_assets(&self) -> Result<Vec<Asset>> {
+        self.search_assets(json!({})).await
+    }
+
+    /// Lists the timeline and archived assets matching metadata search filters (such as
+    /// `originalFileName` or `takenBefore`), including EXIF details.
+    pub async fn search_assets(&self, filters: serde_json::Value) -> Result<Vec<Asset>> {
+        let mut assets = Vec::new();
+        let mut page = 1;
+        loop {
+            // Older servers exclude archived assets unless asked to include them.
+            let mut body =
+                json!({ "page": page, "size": 1000, "withExif": true, "withArchived": true });
+            if let (Some(body), Some(filters)) = (body.as_object_mut(), filters.as_object()) {
+                body.extend(filters.clone());
+            }
+            let response = self
+                .request(Method::POST, "/search/metadata")
+                .json(&body)
+                .send()
+                .await?;
+            let result: SearchResponse = check(response).await?.json().await?;
+            assets.extend(result.assets.items);
+            match result.assets.next_page.and_then(|p| p.parse().ok()) {
+                Some(next) => page = next,
+                None => break,
+            }
+        }
+        Ok(assets)
+    }
+
+    /// Fetches the motion parts of the Live Photos among `assets` that aren't listed
+    /// themselves, as search leaves them out.
+    pub async fn live_photo_videos(&self, assets: &[Asset]) -> Result<Vec<Asset>> {
+        let listed: HashSet<&str> = assets.iter().map(|a| a.id.as_str()).collect();
+        let mut videos = Vec::new();
+        for id in assets
+            .iter()
+            .filter_map(|a| a.live_photo_video_id.as_deref())
+            .filter(|id| !listed.contains(id))
+        {
+            let video = self
+                .get_asset(id)
+                .await
+                .with_context(|| format!("Failed to fetch Live Photo video {}", id))?;
+            videos.push(video);
+        }
+        Ok(videos)
+    }
+
+    /// Lists the `limit` assets that best match a description, by the server's smart
+    /// search (CLIP), best first. `filters` narrows the search like the metadata search's
+    /// (`personIds`, `takenBefore`, ...).
+    pub async fn smart_search(
+        &self,
+        query: &str,
+        filters: serde_json::Value,
+        limit: usize,
+    ) -> Result<Vec<Asset>> {
+        let mut assets = Vec::new();
+        let mut page = 1;
+        while assets.len() < limit {
+            let mut body = json!({
+                "query": query,
+                "page": page,
+                "size": limit.min(1000),
+                "withArchived": true,
+            });
+            if let (Some(body), Some(filters)) = (body.as_object_mut(), filters.as_object()) {
+                body.extend(filters.clone());
+            }
+            let response = self
+                .request(Method::POST, "/search/smart")
+                .json(&body)
+                .send()
+                .await?;
+            let result: SearchResponse = check(response).await?.json().await?;
+            assets.extend(result.assets.items);
+            match result.assets.next_page.and_then(|p| p.parse().ok()) {
+                Some(next) => page = next,
+                None => break,
+            }
+        }
+        assets.truncate(limit);
+        Ok(assets)
+    }
+
+    /// Asks the server which of the files, given as (id, base64 SHA-1) pairs, it already has.
+    pub async fn bulk_upload_check(&self, files: &[(String, String)]) -> Result<Vec<UploadCheck>> {
+        let assets: Vec<_> = files
+            .iter()
+            .map(|(id, checksum)| json!({ "id": id, "checksum": checksum }))
+            .collect();
+        let response = self
+            .request(Method::POST, "/assets/bulk-upload-check")
+            .json(&json!({ "assets": assets }))
+            .send()
+            .await?;
+        let response: UploadCheckResponse = check(response).await?.json().await?;
+        Ok(response.results)
+    }
+
+    /// Lists groups of similar assets found by the server's duplicate detection.
+    pub async fn duplicates(&self) -> Result<Vec<DuplicateGroup>> {
+        let response = self.request(Method::GET, "/duplicates").send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Fetches a single asset.
+    pub async fn get_asset(&self, asset_id: &str) -> Result<Asset> {
+        let response = self
+            .request(Method::GET, &format!("/assets/{}", asset_id))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Starts downloading an asset's original file.
+    pub async fn download_original(&self, asset_id: &str) -> Result<reqwest::Response> {
+        let response = self
+            .request(Method::GET, &format!("/assets/{}/original", asset_id))
+            .send()
+            .await?;
+        check(response).await
+    }
+
+    /// Starts downloading an asset's original file from byte `offset` on. The response is
+    /// "206 Partial Content" when the server honored the range, "416 Range Not
+    /// Satisfiable" when the range lies beyond the end of the file, and otherwise holds the
+    /// whole file.
+    pub async fn download_original_from(
+        &self,
+        asset_id: &str,
+        offset: u64,
+    ) -> Result<reqwest::Response> {
+        if offset == 0 {
+            return self.download_original(asset_id).await;
+        }
+        let response = self
+            .request(Method::GET, &format!("/assets/{}/original", asset_id))
+            .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(response);
+        }
+        check(response).await
+    }
+
+    /// Starts downloading an asset's server-generated image: "thumbnail" (small, WebP by
+    /// default) or "preview" (large, JPEG by default).
+    pub async fn download_thumbnail(
+        &self,
+        asset_id: &str,
+        size: &str,
+    ) -> Result<reqwest::Response> {
+        let response = self
+            .request(Method::GET, &format!("/assets/{}/thumbnail", asset_id))
+            .query(&[("size", size)])
+            .send()
+            .await?;
+        check(response).await
+    }
+
+    /// Lists the users of the server that albums can be shared with.
+    pub async fn list_users(&self) -> Result<Vec<User>> {
+        let response = self.request(Method::GET, "/users").send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Fetches the user owning the credentials.
+    pub async fn current_user(&self) -> Result<User> {
+        let response = self.request(Method::GET, "/users/me").send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Fetches the free space on the volume the server stores uploads on.
+    pub async fn server_storage(&self) -> Result<ServerStorage> {
+        let response = self.request(Method::GET, "/server/storage").send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Logs in with a password and returns a client using the session, for what API keys
+    /// can't do, such as creating the user's first API key.
+    pub async fn login(&self, email: &str, password: &str) -> Result<ImmichClient> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Login {
+            access_token: String,
+        }
+        let response = self
+            .client
+            .post(self.url("/auth/login"))
+            .json(&json!({ "email": email, "password": password }))
+            .send()
+            .await?;
+        let login: Login = check(response).await?.json().await?;
+        Ok(ImmichClient {
+            client: self.client.clone(),
+            server_url: self.server_url.clone(),
+            credentials: Credentials::AccessToken(login.access_token),
+            legacy: AtomicBool::new(self.legacy.load(Ordering::Relaxed)),
+        })
+    }
+
+    /// Creates an API key with all permissions for the logged-in user and returns its
+    /// secret.
+    pub async fn create_api_key(&self, name: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Created {
+            secret: String,
+        }
+        let response = self
+            .request(Method::POST, "/api-keys")
+            .json(&json!({ "name": name, "permissions": ["all"] }))
+            .send()
+            .await?;
+        let created: Created = check(response).await?.json().await?;
+        Ok(created.secret)
+    }
+
+    /// Lists all user accounts, including deleted ones that can still be restored.
+    /// Needs an admin's API key.
+    pub async fn admin_list_users(&self) -> Result<Vec<AdminUser>> {
+        let response = self
+            .request(Method::GET, "/admin/users")
+            .query(&[("withDeleted", "true")])
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Creates a user account, which has to choose a new password on first login.
+    pub async fn admin_create_user(
+        &self,
+        email: &str,
+        name: &str,
+        password: &str,
+        quota_bytes: Option<u64>,
+    ) -> Result<AdminUser> {
+        let response = self
+            .request(Method::POST, "/admin/users")
+            .json(&json!({
+                "email": email,
+                "name": name,
+                "password": password,
+                "shouldChangePassword": true,
+                "quotaSizeInBytes": quota_bytes,
+            }))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Deletes a user account; the server keeps it restorable until its trash is emptied.
+    pub async fn admin_delete_user(&self, user_id: &str) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, &format!("/admin/users/{}", user_id))
+            .json(&json!({ "force": false }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Restores a deleted user account.
+    pub async fn admin_restore_user(&self, user_id: &str) -> Result<()> {
+        let response = self
+            .request(Method::POST, &format!("/admin/users/{}/restore", user_id))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Queues a job, such as "refresh-metadata" or "regenerate-thumbnail", for each asset.
+    pub async fn run_asset_jobs(&self, asset_ids: &[String], name: &str) -> Result<()> {
+        let response = self
+            .request(Method::POST, "/assets/jobs")
+            .json(&json!({ "assetIds": asset_ids, "name": name }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Starts a server queue (such as "smartSearch") for the assets it hasn't processed
+    /// yet. Needs an admin's API key.
+    pub async fn start_queue(&self, queue: &str) -> Result<()> {
+        let response = self
+            .request(Method::PUT, &format!("/jobs/{}", queue))
+            .json(&json!({ "command": "start", "force": false }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Fetches the job counts of every server queue, by queue name. Needs an admin's API key.
+    pub async fn job_queues(&self) -> Result<HashMap<String, JobQueue>> {
+        let response = self.request(Method::GET, "/jobs").send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Fetches the API key used for requests.
+    pub async fn current_api_key(&self) -> Result<ApiKey> {
+        let response = self.request(Method::GET, "/api-keys/me").send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Shares an album with users, with role "viewer" or "editor".
+    pub async fn share_album(&self, album_id: &str, user_ids: &[String], role: &str) -> Result<()> {
+        let album_users: Vec<_> = user_ids
+            .iter()
+            .map(|id| json!({ "userId": id, "role": role }))
+            .collect();
+        let response = self
+            .request(Method::PUT, &format!("/albums/{}/users", album_id))
+            .json(&json!({ "albumUsers": album_users }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Removes assets from an album (the assets themselves are kept).
+    pub async fn remove_assets_from_album(
+        &self,
+        album_id: &str,
+        asset_ids: &[String],
+    ) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, &format!("/albums/{}/assets", album_id))
+            .json(&json!({ "ids": asset_ids }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Moves assets to the trash, or deletes them permanently with `force`.
+    pub async fn delete_assets(&self, asset_ids: &[String], force: bool) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, "/assets")
+            .json(&json!({ "ids": asset_ids, "force": force }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Applies the same changes (e.g. `isFavorite`, `dateTimeOriginal`) to many assets at once.
+    pub async fn update_assets(
+        &self,
+        asset_ids: &[String],
+        changes: &serde_json::Value,
+    ) -> Result<()> {
+        let mut body = json!({ "ids": asset_ids });
+        if let (Some(body), Some(changes)) = (body.as_object_mut(), changes.as_object()) {
+            body.extend(changes.clone());
+        }
+        let response = self
+            .request(Method::PUT, "/assets")
+            .json(&body)
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Sets the description of an asset.
+    pub async fn update_asset_description(&self, asset_id: &str, description: &str) -> Result<()> {
+        let response = self
+            .request(Method::PUT, &format!("/assets/{}", asset_id))
+            .json(&json!({ "description": description }))
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(())
+    }
+}
+
+/// Maps a current API route to its pre-v1.106 form, where resources were singular and
+/// uploads had their own route.
+fn legacy_path(method: &Method, path: &str) -> String {
+    if *method == Method::POST && path == "/assets" {
+        return "/asset/upload".to_string();
+    }
+    if path == "/assets" {
+        return "/asset".to_string();
+    }
+    if let Some(rest) = path.strip_prefix("/assets/") {
+        if let Some(id) = rest.strip_suffix("/thumbnail") {
+            return format!("/asset/thumbnail/{}", id);
+        }
+        return match rest.strip_suffix("/original") {
+            Some(id) => format!("/asset/file/{}", id),
+            None => format!("/asset/{}", rest),
+        };
+    }
+    for (current, legacy) in [
+        ("/albums", "/album"),
+        ("/users", "/user"),
+        ("/tags", "/tag"),
+        ("/people", "/person"),
+        ("/api-keys", "/api-key"),
+        ("/server", "/server-info"),
+    ] {
+        if let Some(rest) = path.strip_prefix(current)
+            && (rest.is_empty() || rest.starts_with('/'))
+        {
+            return format!("{}{}", legacy, rest);
+        }
+    }
+    path.to_string()
+}
+
+/// Builds the HTTP client used to talk to a server.
+/// HTTP/1.1 is used unless HTTP/2 is requested; over plain HTTP there is no negotiation,
+/// so HTTP/2 is then assumed to be supported.
+/// A `unix://` server URL connects to the Unix domain socket at its path instead of TCP.
+pub fn http_client(settings: &HttpConfig, server_url: &str) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    let socket = server_url.strip_prefix(UNIX_SCHEME);
+    builder = if !settings.http2 {
+        builder.http1_only()
+    } else if server_url.starts_with("http://") || socket.is_some() {
+        builder.http2_prior_knowledge()
+    } else {
+        builder
+    };
+    if let Some(seconds) = settings.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(std::time::Duration::from_secs(seconds));
+    }
+    if let Some(max) = settings.pool_max_idle {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if let Some(proxy) = &settings.proxy {
+        builder = builder.proxy(
+            reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy {:?}", proxy))?,
+        );
+    }
+    if settings.ipv4 && settings.ipv6 {
+        anyhow::bail!("Only one of ipv4 and ipv6 can be set");
+    }
+    if settings.ipv4 || settings.ipv6 {
+        builder = builder.dns_resolver(Arc::new(FamilyResolver {
+            ipv6: settings.ipv6,
+        }));
+    }
+    let port = url::Url::parse(server_url)
+        .ok()
+        .and_then(|url| url.port_or_known_default());
+    for entry in &settings.resolve {
+        let (host, entry_port, address) = parse_resolve(entry)?;
+        // Like curl, an entry only applies to its port; reqwest always connects to the
+        // port of the URL.
+        if Some(entry_port) == port {
+            builder = builder.resolve(host, SocketAddr::new(address, entry_port));
+        }
+    }
+    if let Some(path) = socket {
+        #[cfg(unix)]
+        {
+            builder = builder.unix_socket(path);
+        }
+        #[cfg(not(unix))]
+        anyhow::bail!(
+            "Cannot connect to {}: Unix sockets need Linux or macOS",
+            path
+        );
+    }
+    Ok(builder.build()?)
+}
+
+/// Looks up host names with the system resolver, keeping only IPv4 or IPv6 addresses.
+struct FamilyResolver {
+    ipv6: bool,
+}
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let ipv6 = self.ipv6;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| addr.is_ipv6() == ipv6)
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!(
+                    "{} has no IPv{} address",
+                    name.as_str(),
+                    if ipv6 { 6 } else { 4 }
+                )
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Splits a `--resolve` entry "HOST:PORT:ADDRESS"; IPv6 addresses may be bracketed.
+fn parse_resolve(entry: &str) -> Result<(&str, u16, IpAddr)> {
+    let parsed = entry.split_once(':').and_then(|(host, rest)| {
+        let (port, address) = rest.split_once(':')?;
+        let address = address.trim_start_matches('[').trim_end_matches(']');
+        Some((host, port.parse().ok()?, address.parse().ok()?))
+    });
+    parsed.with_context(|| {
+        format!(
+            "Invalid --resolve entry {:?}, expected HOST:PORT:ADDRESS",
+            entry
+        )
+    })
+}
+
+/// Turns a non-success response into an error carrying the status and body.
+async fn check(response: reqwest::Response) -> Result<reqwest::Response> {
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Server returned error {}: {}", status, body);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_routes_to_legacy_ones() {
+        let cases = [
+            (Method::POST, "/assets", "/asset/upload"),
+            (Method::GET, "/assets", "/asset"),
+            (Method::DELETE, "/assets", "/asset"),
+            (Method::GET, "/assets/abc", "/asset/abc"),
+            (Method::GET, "/assets/abc/original", "/asset/file/abc"),
+            (Method::GET, "/assets/abc/thumbnail", "/asset/thumbnail/abc"),
+            (Method::GET, "/albums", "/album"),
+            (Method::PUT, "/albums/abc/assets", "/album/abc/assets"),
+            (Method::GET, "/users/me", "/user/me"),
+            (Method::PUT, "/tags/assets", "/tag/assets"),
+            (Method::GET, "/people", "/person"),
+            (Method::GET, "/api-keys", "/api-key"),
+            (Method::DELETE, "/api-keys/abc", "/api-key/abc"),
+            (Method::GET, "/server/version", "/server-info/version"),
+        ];
+        for (method, path, legacy) in cases {
+            assert_eq!(legacy_path(&method, path), legacy, "{} {}", method, path);
+        }
+    }
+
+    #[test]
+    fn reads_legacy_upload_responses() {
+        let cases = [
+            (r#"{"id": "a", "status": "created"}"#, true),
+            (r#"{"id": "a", "status": "duplicate"}"#, false),
+            (r#"{"id": "a", "duplicate": false}"#, true),
+            (r#"{"id": "a", "duplicate": true}"#, false),
+        ];
+        for (body, created) in cases {
+            let response: UploadResponse = serde_json::from_str(body).unwrap();
+            assert_eq!(response.is_created(), created, "{}", body);
+        }
+    }
+
+    #[test]
+    fn leaves_other_routes_alone() {
+        for path in [
+            "/search/metadata",
+            "/auth/validateToken",
+            "/albumsx",
+            "/server-info/version",
+            "/jobs",
+        ] {
+            assert_eq!(legacy_path(&Method::GET, path), path);
+        }
+    }
+}
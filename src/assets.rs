@@ -0,0 +1,397 @@
+use crate::albums;
+use crate::api::{Asset, ImmichClient};
+use crate::upload::DEVICE_ID;
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use reqwest::multipart;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+
+/// Assets changed per bulk request.
+const BATCH_SIZE: usize = 500;
+
+/// Which assets a command applies to. All given filters have to match.
+#[derive(clap::Args)]
+#[group(required = true, multiple = true)]
+pub struct Selection {
+    /// Asset ID, or text to look for in file names.
+    pub query: Option<String>,
+    /// Only assets in this album.
+    #[arg(long)]
+    pub album: Option<String>,
+    /// Only assets in which this person was recognized (by name).
+    #[arg(long)]
+    pub person: Option<String>,
+    /// Only assets with this tag (by name or full path, e.g. "Places/Paris").
+    #[arg(long)]
+    pub tag: Option<String>,
+    /// Only assets taken before this date (RFC 3339 or local time, e.g. "2019-07-14").
+    #[arg(long, value_parser = parse_date)]
+    pub before: Option<DateTime<FixedOffset>>,
+    /// Only assets taken at or after this date.
+    #[arg(long, value_parser = parse_date)]
+    pub after: Option<DateTime<FixedOffset>>,
+    /// Only assets whose original path on the server matches this pattern, where `*`
+    /// matches within a folder name and `**` across folders (e.g. "**/2019/*.jpg").
+    #[arg(long)]
+    pub path_glob: Option<String>,
+    /// Only the assets that best match this description, found by the server's smart
+    /// search (e.g. "red bicycle at the beach"); needs machine learning enabled.
+    #[arg(long = "query", value_name = "DESCRIPTION")]
+    pub smart: Option<String>,
+    /// How many smart search matches to keep, best first.
+    #[arg(long, default_value_t = 100, requires = "smart")]
+    pub limit: usize,
+}
+
+impl Selection {
+    /// The filters the metadata search can apply on the server, given the IDs of the
+    /// selected person and tag.
+    fn search_filters(&self, person_ids: &[String], tag_ids: &[String]) -> Value {
+        let mut filters = serde_json::Map::new();
+        if !person_ids.is_empty() {
+            filters.insert("personIds".into(), json!(person_ids));
+        }
+        if !tag_ids.is_empty() {
+            filters.insert("tagIds".into(), json!(tag_ids));
+        }
+        if let Some(name) = &self.query {
+            filters.insert("originalFileName".into(), json!(name));
+        }
+        if let Some(before) = self.before {
+            filters.insert("takenBefore".into(), json!(before.to_rfc3339()));
+        }
+        if let Some(after) = self.after {
+            filters.insert("takenAfter".into(), json!(after.to_rfc3339()));
+        }
+        Value::Object(filters)
+    }
+
+    /// Whether an asset passes every filter, checked locally.
+    fn matches(&self, asset: &Asset) -> bool {
+        self.query.as_ref().is_none_or(|name| {
+            let name = name.to_lowercase();
+            asset.original_file_name.to_lowercase().contains(&name)
+        }) && self
+            .before
+            .is_none_or(|before| asset.file_created_at < before)
+            && self
+                .after
+                .is_none_or(|after| asset.file_created_at >= after)
+            && self.path_glob.as_ref().is_none_or(|pattern| {
+                let path = asset.original_path.as_deref().unwrap_or_default();
+                glob_match(pattern.as_bytes(), path.as_bytes())
+            })
+    }
+}
+
+/// Metadata to set on assets; fields left empty are not changed.
+#[derive(Default)]
+pub struct AssetChanges {
+    pub description: Option<String>,
+    pub date: Option<DateTime<FixedOffset>>,
+    /// Latitude and longitude.
+    pub location: Option<(f64, f64)>,
+    pub favorite: Option<bool>,
+    pub archived: Option<bool>,
+}
+
+impl AssetChanges {
+    /// The changes as fields of a bulk update. The description is not among them, as
+    /// only the single-asset update sets it on every server version.
+    fn bulk_fields(&self) -> Value {
+        let mut fields = serde_json::Map::new();
+        if let Some(date) = self.date {
+            fields.insert("dateTimeOriginal".into(), json!(date.to_rfc3339()));
+        }
+        if let Some((latitude, longitude)) = self.location {
+            fields.insert("latitude".into(), json!(latitude));
+            fields.insert("longitude".into(), json!(longitude));
+        }
+        if let Some(favorite) = self.favorite {
+            fields.insert("isFavorite".into(), json!(favorite));
+        }
+        if let Some(archived) = self.archived {
+            // Servers before `visibility` only know the flag; each ignores the other's field.
+            fields.insert("isArchived".into(), json!(archived));
+            let visibility = if archived { "archive" } else { "timeline" };
+            fields.insert("visibility".into(), json!(visibility));
+        }
+        Value::Object(fields)
+    }
+}
+
+/// Finds the assets a selection refers to: a single asset by ID, or the results of a
+/// metadata search (or an album's assets) narrowed down by the remaining filters.
+pub async fn select(client: &ImmichClient, selection: &Selection) -> Result<Vec<Asset>> {
+    if let Some(id) = selection.query.as_deref().filter(|q| is_asset_id(q)) {
+        let asset = client
+            .get_asset(id)
+            .await
+            .with_context(|| format!("Failed to fetch asset {}", id))?;
+        return Ok(vec![asset]);
+    }
+    let album = match &selection.album {
+        Some(name) => Some(album_assets(client, name).await?),
+        None => None,
+    };
+    let person_ids = match &selection.person {
+        Some(name) => person_ids(client, name).await?,
+        None => Vec::new(),
+    };
+    let tag_ids = match &selection.tag {
+        Some(name) => vec![tag_id(client, name).await?],
+        None => Vec::new(),
+    };
+
+    // People and tags can only be filtered by the server; albums by either.
+    let mut assets = match album {
+        Some(album) if person_ids.is_empty() && tag_ids.is_empty() && selection.smart.is_none() => {
+            album
+        }
+        album => {
+            let filters = selection.search_filters(&person_ids, &tag_ids);
+            let mut assets = match &selection.smart {
+                Some(query) => smart_search(client, query, filters, selection.limit).await?,
+                None => client
+                    .search_assets(filters)
+                    .await
+                    .context("Failed to search assets")?,
+            };
+            if let Some(album) = album {
+                let in_album: HashSet<String> = album.into_iter().map(|a| a.id).collect();
+                assets.retain(|asset| in_album.contains(&asset.id));
+            }
+            assets
+        }
+    };
+    assets.retain(|asset| selection.matches(asset));
+    Ok(assets)
+}
+
+/// Runs a smart search. The file name filter is left to the local check, as only the
+/// metadata search takes it.
+async fn smart_search(
+    client: &ImmichClient,
+    query: &str,
+    mut filters: Value,
+    limit: usize,
+) -> Result<Vec<Asset>> {
+    if let Some(filters) = filters.as_object_mut() {
+        filters.remove("originalFileName");
+    }
+    client
+        .smart_search(query, filters, limit)
+        .await
+        .context("Smart search failed (is machine learning enabled on the server?)")
+}
+
+async fn album_assets(client: &ImmichClient, name: &str) -> Result<Vec<Asset>> {
+    Ok(albums::fetch(client, name).await?.assets)
+}
+
+/// IDs of every person with this name; the same person is sometimes recognized twice.
+async fn person_ids(client: &ImmichClient, name: &str) -> Result<Vec<String>> {
+    let people = client
+        .list_people()
+        .await
+        .context("Failed to list people")?;
+    let ids: Vec<String> = people
+        .into_iter()
+        .filter(|p| p.name.eq_ignore_ascii_case(name))
+        .map(|p| p.id)
+        .collect();
+    if ids.is_empty() {
+        anyhow::bail!("Person '{}' not found", name);
+    }
+    Ok(ids)
+}
+
+async fn tag_id(client: &ImmichClient, name: &str) -> Result<String> {
+    let tags = client.list_tags().await.context("Failed to list tags")?;
+    tags.into_iter()
+        .find(|t| t.value.eq_ignore_ascii_case(name) || t.name.eq_ignore_ascii_case(name))
+        .map(|t| t.id)
+        .with_context(|| format!("Tag '{}' not found", name))
+}
+
+/// Applies `changes` to the selected assets.
+pub async fn update(
+    client: &ImmichClient,
+    selection: &Selection,
+    changes: &AssetChanges,
+) -> Result<()> {
+    let assets = select(client, selection).await?;
+    if assets.is_empty() {
+        println!("No matching assets.");
+        return Ok(());
+    }
+    let ids: Vec<String> = assets.into_iter().map(|a| a.id).collect();
+    println!("Updating {} assets...", ids.len());
+
+    let fields = changes.bulk_fields();
+    if fields.as_object().is_some_and(|f| !f.is_empty()) {
+        for batch in ids.chunks(BATCH_SIZE) {
+            client
+                .update_assets(batch, &fields)
+                .await
+                .context("Failed to update assets")?;
+        }
+    }
+    if let Some(description) = &changes.description {
+        for id in &ids {
+            client
+                .update_asset_description(id, description)
+                .await
+                .with_context(|| format!("Failed to set the description of asset {}", id))?;
+        }
+    }
+    println!("Updated {} assets.", ids.len());
+    Ok(())
+}
+
+/// Moves the selected assets to the trash, or deletes them for good with `permanent`.
+/// Without `confirmed` only shows what would be deleted.
+pub async fn delete(
+    client: &ImmichClient,
+    selection: &Selection,
+    confirmed: bool,
+    permanent: bool,
+) -> Result<()> {
+    const PREVIEW: usize = 10;
+
+    let assets = select(client, selection).await?;
+    if assets.is_empty() {
+        println!("No matching assets.");
+        return Ok(());
+    }
+    let action = if permanent {
+        "permanently delete them"
+    } else {
+        "move them to the trash"
+    };
+    println!("{} matching assets:", assets.len());
+    for asset in assets.iter().take(PREVIEW) {
+        println!(
+            "  {}  {}  {}",
+            asset.id,
+            asset.file_created_at.format("%Y-%m-%d %H:%M"),
+            asset.original_file_name
+        );
+    }
+    if assets.len() > PREVIEW {
+        println!("  ... and {} more", assets.len() - PREVIEW);
+    }
+    if !confirmed {
+        println!("Run again with --yes to {}.", action);
+        return Ok(());
+    }
+
+    let ids: Vec<String> = assets.into_iter().map(|a| a.id).collect();
+    for batch in ids.chunks(BATCH_SIZE) {
+        client
+            .delete_assets(batch, permanent)
+            .await
+            .context("Failed to delete assets")?;
+    }
+    if permanent {
+        println!("Deleted {} assets.", ids.len());
+    } else {
+        println!("Moved {} assets to the trash.", ids.len());
+    }
+    Ok(())
+}
+
+/// Builds the upload form of an asset copied from another server or a backup, keeping
+/// its dates, favorite and archive flags and Live Photo link.
+pub fn copy_form(
+    part: multipart::Part,
+    device_asset_id: String,
+    (created_at, modified_at): (DateTime<Utc>, DateTime<Utc>),
+    favorite: bool,
+    archived: bool,
+    live_photo_video_id: Option<String>,
+) -> multipart::Form {
+    let mut form = multipart::Form::new()
+        .part("assetData", part)
+        .text("deviceAssetId", device_asset_id)
+        .text("deviceId", DEVICE_ID)
+        .text("fileCreatedAt", created_at.to_rfc3339())
+        .text("fileModifiedAt", modified_at.to_rfc3339())
+        .text("isFavorite", favorite.to_string());
+    if archived {
+        // Servers ignore whichever of the two fields they don't know.
+        form = form
+            .text("visibility", "archive")
+            .text("isArchived", "true");
+    }
+    if let Some(video_id) = live_photo_video_id {
+        form = form.text("livePhotoVideoId", video_id);
+    }
+    form
+}
+
+/// Whether a query is an asset ID (a UUID) rather than text to search for.
+fn is_asset_id(query: &str) -> bool {
+    query.len() == 36
+        && query.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Matches a path against a glob pattern: `**` matches anything, `*` anything but `/`,
+/// and `?` a single character other than `/`.
+pub fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            // "**/" also matches no folder at all.
+            let rest_without_slash = rest.strip_prefix(b"/").unwrap_or(rest);
+            glob_match(rest_without_slash, path)
+                || (0..=path.len()).any(|i| glob_match(rest, &path[i..]))
+        }
+        [b'*', rest @ ..] => {
+            let folder_end = path.iter().position(|&c| c == b'/').unwrap_or(path.len());
+            (0..=folder_end).any(|i| glob_match(rest, &path[i..]))
+        }
+        [b'?', rest @ ..] => {
+            path.first().is_some_and(|&c| c != b'/') && glob_match(rest, &path[1..])
+        }
+        [c, rest @ ..] => path.first() == Some(c) && glob_match(rest, &path[1..]),
+    }
+}
+
+/// Parses a capture date given as RFC 3339, or as local time in the form
+/// "2019-07-14 18:30[:00]" or "2019-07-14".
+pub fn parse_date(value: &str) -> Result<DateTime<FixedOffset>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date);
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| format!("invalid date '{}', expected e.g. 2019-07-14 18:30", value))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|date| date.fixed_offset())
+        .ok_or_else(|| format!("'{}' does not exist in the local time zone", value))
+}
+
+/// Parses a location given as "latitude,longitude" in decimal degrees.
+pub fn parse_location(value: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("invalid location '{}', expected e.g. 48.8584,2.2945", value);
+    let (latitude, longitude) = value.split_once(',').ok_or_else(invalid)?;
+    let latitude: f64 = latitude.trim().parse().map_err(|_| invalid())?;
+    let longitude: f64 = longitude.trim().parse().map_err(|_| invalid())?;
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(invalid());
+    }
+    Ok((latitude, longitude))
+}
@@ -0,0 +1,191 @@
+use crate::api::{Asset, ImmichClient};
+use crate::download;
+use crate::exit;
+use crate::progress::Progress;
+use crate::sidecar::{self, SidecarFormat};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Name of the manifest file in a backup directory.
+pub const MANIFEST: &str = "manifest.json";
+
+/// Everything needed to rebuild a library besides the files themselves.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub created_at: DateTime<Utc>,
+    pub assets: Vec<BackupAsset>,
+    pub albums: Vec<BackupAlbum>,
+}
+
+/// An asset in a backup, with the metadata that lives only on the server.
+#[derive(Serialize, Deserialize)]
+pub struct BackupAsset {
+    /// ID on the server the backup was taken from.
+    pub id: String,
+    /// Path of the original, relative to the backup directory.
+    pub file: PathBuf,
+    pub original_file_name: String,
+    pub file_created_at: DateTime<Utc>,
+    pub file_modified_at: DateTime<Utc>,
+    /// Base64-encoded SHA-1 of the original.
+    pub checksum: Option<String>,
+    pub favorite: bool,
+    pub archived: bool,
+    pub description: Option<String>,
+    /// ID of the motion part, for Live Photos.
+    pub live_photo_video_id: Option<String>,
+    /// Names of the people recognized in the asset.
+    pub people: Vec<String>,
+}
+
+/// An album in a backup.
+#[derive(Serialize, Deserialize)]
+pub struct BackupAlbum {
+    pub name: String,
+    pub description: String,
+    /// IDs of the album's assets on the server the backup was taken from.
+    pub asset_ids: Vec<String>,
+}
+
+/// Downloads every original into `destination/originals/<asset id>/` and writes a manifest
+/// of albums, favorites, archive state, descriptions and people next to them. Files from an
+/// earlier backup into the same directory are kept, so later runs only fetch new assets.
+/// With `sidecar`, the metadata of each asset is also written next to its original.
+pub async fn backup(
+    client: &ImmichClient,
+    destination: &Path,
+    concurrent: usize,
+    sidecar: Option<SidecarFormat>,
+) -> Result<()> {
+    println!("Listing assets...");
+    let mut assets = client
+        .search_assets(json!({ "withPeople": true }))
+        .await
+        .context("Failed to list assets")?;
+    let videos = client.live_photo_videos(&assets).await?;
+    assets.extend(videos);
+
+    println!("Listing albums...");
+    let mut albums = Vec::new();
+    for album in client
+        .list_albums()
+        .await
+        .context("Failed to list albums")?
+    {
+        let album = client
+            .get_album(&album.id)
+            .await
+            .with_context(|| format!("Failed to fetch album '{}'", album.album_name))?;
+        albums.push(BackupAlbum {
+            name: album.album_name,
+            description: album.description,
+            asset_ids: album.assets.into_iter().map(|a| a.id).collect(),
+        });
+    }
+
+    std::fs::create_dir_all(destination)
+        .with_context(|| format!("Failed to create {:?}", destination))?;
+    let mut needed = 0;
+    for asset in &assets {
+        let path = destination.join(original_path(asset));
+        if !download::is_downloaded(&path, asset.file_size()).await {
+            needed += asset.file_size().unwrap_or(0);
+        }
+    }
+    download::ensure_space(destination, needed)?;
+    println!(
+        "Backing up {} assets and {} albums to {:?}...",
+        assets.len(),
+        albums.len(),
+        destination
+    );
+    let total = assets.len();
+    let pb = Progress::new(total)?;
+    let failed = AtomicUsize::new(0);
+    let mut downloads = futures::stream::iter(&assets)
+        .map(|asset| {
+            let pb = pb.clone();
+            let failed = &failed;
+            async move {
+                if let Err(e) = backup_asset(client, asset, destination, sidecar).await {
+                    pb.warn(format!(
+                        "Failed to back up {}: {:#}",
+                        asset.original_file_name, e
+                    ));
+                    failed.fetch_add(1, Ordering::Relaxed);
+                }
+                pb.inc();
+            }
+        })
+        .buffer_unordered(concurrent);
+    while downloads.next().await.is_some() {}
+    drop(downloads);
+    pb.finish("Backup complete");
+
+    let manifest = Manifest {
+        created_at: Utc::now(),
+        assets: assets.iter().map(backup_entry).collect(),
+        albums,
+    };
+    let path = destination.join(MANIFEST);
+    let temp = destination.join(format!("{}.part", MANIFEST));
+    std::fs::write(&temp, serde_json::to_string_pretty(&manifest)?)
+        .and_then(|()| std::fs::rename(&temp, &path))
+        .with_context(|| format!("Failed to write {:?}", path))?;
+
+    exit::check_failures(failed.into_inner(), total)
+}
+
+/// Where an asset's original is kept, relative to the backup directory.
+fn original_path(asset: &Asset) -> PathBuf {
+    let name = Path::new(&asset.original_file_name)
+        .file_name()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(&asset.id));
+    Path::new("originals").join(&asset.id).join(name)
+}
+
+async fn backup_asset(
+    client: &ImmichClient,
+    asset: &Asset,
+    destination: &Path,
+    sidecar: Option<SidecarFormat>,
+) -> Result<()> {
+    let path = destination.join(original_path(asset));
+    if !download::is_downloaded(&path, asset.file_size()).await {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        download::save_original(client, asset, &path).await?;
+    }
+    match sidecar {
+        Some(format) => sidecar::write(client, &asset.id, &path, format).await,
+        None => Ok(()),
+    }
+}
+
+fn backup_entry(asset: &Asset) -> BackupAsset {
+    BackupAsset {
+        id: asset.id.clone(),
+        file: original_path(asset),
+        original_file_name: asset.original_file_name.clone(),
+        file_created_at: asset.file_created_at,
+        file_modified_at: asset.file_modified_at,
+        checksum: asset.checksum.clone(),
+        favorite: asset.is_favorite,
+        archived: asset.archived(),
+        description: asset.description().map(str::to_string),
+        live_photo_video_id: asset.live_photo_video_id.clone(),
+        people: asset
+            .people
+            .iter()
+            .filter(|p| !p.name.is_empty())
+            .map(|p| p.name.clone())
+            .collect(),
+    }
+}
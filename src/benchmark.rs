@@ -0,0 +1,202 @@
+use crate::api::ImmichClient;
+use crate::upload::DEVICE_ID;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use futures::StreamExt;
+use reqwest::multipart;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::{Duration, Instant};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// A concurrency level within this share of the best throughput is preferred when lower,
+/// as it loads the server less for about the same speed.
+const GOOD_ENOUGH: f64 = 0.95;
+
+/// Throughput of one round of uploads.
+struct Round {
+    files: usize,
+    bytes: usize,
+    elapsed: Duration,
+}
+
+impl Round {
+    fn mib_per_sec(&self) -> f64 {
+        self.bytes as f64 / MIB / self.elapsed.as_secs_f64()
+    }
+
+    fn files_per_sec(&self) -> f64 {
+        self.files as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Measures upload throughput: first at each concurrency level with the middle payload
+/// size, then at the best level with each size. Uploaded assets are deleted permanently
+/// after every round.
+pub async fn benchmark(
+    client: &ImmichClient,
+    sizes: &[f64],
+    levels: &[usize],
+    count: usize,
+) -> Result<()> {
+    if sizes.is_empty() || levels.is_empty() {
+        anyhow::bail!("At least one size and one concurrency level are needed");
+    }
+    let mut sizes = sizes.to_vec();
+    sizes.sort_by(f64::total_cmp);
+    let probe_size = sizes[sizes.len() / 2];
+
+    println!("Concurrency (with {} MiB files):", probe_size);
+    let mut by_level = Vec::new();
+    for &level in levels {
+        let round = run_round(client, probe_size, level, count.max(level)).await?;
+        println!(
+            "  {:>3}: {:>8.1} MiB/s {:>8.1} files/s",
+            level,
+            round.mib_per_sec(),
+            round.files_per_sec()
+        );
+        by_level.push((level, round.mib_per_sec()));
+    }
+    let best = by_level.iter().map(|(_, speed)| *speed).fold(0.0, f64::max);
+    let (concurrent, speed) = by_level
+        .iter()
+        .filter(|(_, speed)| *speed >= best * GOOD_ENOUGH)
+        .min_by_key(|(level, _)| *level)
+        .copied()
+        .unwrap_or(by_level[0]);
+
+    println!("File size (at concurrency {}):", concurrent);
+    for &size in &sizes {
+        let round = run_round(client, size, concurrent, count.max(concurrent)).await?;
+        println!(
+            "  {:>7} MiB: {:>8.1} MiB/s {:>8.1} files/s",
+            size,
+            round.mib_per_sec(),
+            round.files_per_sec()
+        );
+    }
+
+    println!(
+        "Recommended: --concurrent {} (about {:.1} MiB/s)",
+        concurrent, speed
+    );
+    Ok(())
+}
+
+/// Uploads `files` payloads of `size_mib` with `concurrent` uploads in flight, then deletes them.
+async fn run_round(
+    client: &ImmichClient,
+    size_mib: f64,
+    concurrent: usize,
+    files: usize,
+) -> Result<Round> {
+    let size = (size_mib * MIB) as usize;
+    let rng = SystemRandom::new();
+    let mut filler = vec![0u8; size];
+    rng.fill(&mut filler)
+        .map_err(|_| anyhow::anyhow!("No secure random source available"))?;
+    // Payloads are built up front so that only the upload is timed.
+    let payloads = (0..files)
+        .map(|_| {
+            let mut id = [0u8; 16];
+            rng.fill(&mut id)
+                .map_err(|_| anyhow::anyhow!("No secure random source available"))?;
+            Ok((hex(&id), synthetic_jpeg(&id, &filler)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let bytes = payloads.iter().map(|(_, data)| data.len()).sum();
+
+    let start = Instant::now();
+    let results: Vec<Result<Option<String>>> = futures::stream::iter(payloads)
+        .map(|(id, data)| upload_synthetic(client, "benchmark", id, data))
+        .buffer_unordered(concurrent)
+        .collect()
+        .await;
+    let elapsed = start.elapsed();
+
+    let mut asset_ids = Vec::new();
+    let mut error = None;
+    for result in results {
+        match result {
+            Ok(id) => asset_ids.extend(id),
+            Err(e) => error = Some(e),
+        }
+    }
+    if !asset_ids.is_empty() {
+        client
+            .delete_assets(&asset_ids, true)
+            .await
+            .context("Failed to delete benchmark uploads")?;
+    }
+    if let Some(e) = error {
+        return Err(e.context("Benchmark upload failed"));
+    }
+    Ok(Round {
+        files,
+        bytes,
+        elapsed,
+    })
+}
+
+/// Uploads a synthetic JPEG named `<prefix>-<id>.jpg` and returns its asset ID, which is
+/// missing when the server already had it.
+pub async fn upload_synthetic(
+    client: &ImmichClient,
+    prefix: &str,
+    id: String,
+    data: Vec<u8>,
+) -> Result<Option<String>> {
+    let part = multipart::Part::bytes(data)
+        .file_name(format!("{}-{}.jpg", prefix, id))
+        .mime_str("image/jpeg")?;
+    let now = Utc::now().to_rfc3339();
+    let form = multipart::Form::new()
+        .part("assetData", part)
+        .text("deviceAssetId", format!("{}-{}", prefix, id))
+        .text("deviceId", DEVICE_ID)
+        .text("fileCreatedAt", now.clone())
+        .text("fileModifiedAt", now)
+        .text("isFavorite", "false");
+    Ok(client.upload_asset(form).await?.map(|asset| asset.id))
+}
+
+/// A random hex ID and a synthetic JPEG of about `size` bytes carrying it.
+pub fn synthetic_payload(size: usize) -> Result<(String, Vec<u8>)> {
+    let mut id = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| anyhow::anyhow!("No secure random source available"))?;
+    Ok((hex(&id), synthetic_jpeg(&id, &vec![0; size])))
+}
+
+/// A valid 8x8 grey JPEG padded to about the filler's size with comment segments, the
+/// first of which holds `id` so that every payload has its own checksum.
+fn synthetic_jpeg(id: &[u8], filler: &[u8]) -> Vec<u8> {
+    // Frame: 8x8, one component, then DC and AC Huffman tables with a single one-bit
+    // code for symbol 0, then a scan of DC difference 0 and end of block.
+    const FRAME: &[u8] = &[
+        0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x08, 0x00, 0x08, 0x01, 0x01, 0x11, 0x00, //
+        0xFF, 0xC4, 0x00, 0x14, 0x00, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, //
+        0xFF, 0xC4, 0x00, 0x14, 0x10, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, //
+        0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00, 0x3F, 0xFF, 0xD9,
+    ];
+    const MAX_SEGMENT: usize = 0xFFFF - 2;
+
+    let mut jpeg = Vec::with_capacity(filler.len() + filler.len() / MAX_SEGMENT * 4 + 160);
+    jpeg.extend_from_slice(&[0xFF, 0xD8]);
+    for chunk in std::iter::once(id).chain(filler.chunks(MAX_SEGMENT)) {
+        jpeg.extend_from_slice(&[0xFF, 0xFE]);
+        jpeg.extend_from_slice(&((chunk.len() + 2) as u16).to_be_bytes());
+        jpeg.extend_from_slice(chunk);
+    }
+    // Quantization table of all ones.
+    jpeg.extend_from_slice(&[0xFF, 0xDB, 0x00, 0x43, 0x00]);
+    jpeg.extend_from_slice(&[1; 64]);
+    jpeg.extend_from_slice(FRAME);
+    jpeg
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,113 @@
+use crate::config::Config;
+use anyhow::Result;
+use base64::Engine;
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// SHA-1 checksums of local files, kept in ~/.immich/checksums.json so that files which
+/// haven't changed since they were last hashed are not read again.
+pub struct ChecksumCache {
+    path: Option<PathBuf>,
+    /// File identity (device, inode, size, mtime) -> base64 SHA-1.
+    entries: HashMap<String, String>,
+    changed: bool,
+}
+
+impl ChecksumCache {
+    /// Loads the cache, or starts empty when it is missing or unreadable.
+    pub fn load() -> Self {
+        let path = Config::state_dir().ok().map(|d| d.join("checksums.json"));
+        let entries = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        ChecksumCache {
+            path,
+            entries,
+            changed: false,
+        }
+    }
+
+    /// Checksum of a file in the form Immich reports (base64 SHA-1), reading the file
+    /// only when it changed since it was last hashed.
+    pub fn checksum(&mut self, path: &Path) -> std::io::Result<String> {
+        if let Some(checksum) = self.cached(path) {
+            return Ok(checksum);
+        }
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut hasher = sha1_smol::Sha1::new();
+        let mut buffer = vec![0u8; 1 << 20];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        let checksum = encode(&hasher);
+        self.insert(path, checksum.clone());
+        Ok(checksum)
+    }
+
+    /// The checksum recorded for a file, if it hasn't changed since.
+    pub fn cached(&self, path: &Path) -> Option<String> {
+        let metadata = std::fs::metadata(path).ok()?;
+        self.entries.get(&identity(path, &metadata)).cloned()
+    }
+
+    /// Records the checksum of a file as it is now.
+    pub fn insert(&mut self, path: &Path, checksum: String) {
+        if let Ok(metadata) = std::fs::metadata(path) {
+            self.entries.insert(identity(path, &metadata), checksum);
+            self.changed = true;
+        }
+    }
+
+    /// Writes the cache to disk if anything was added.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = self.path.as_ref().filter(|_| self.changed) else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(&self.entries)?)?;
+        Ok(())
+    }
+}
+
+/// Checksum of everything fed into `hasher`, in the form Immich reports.
+pub fn encode(hasher: &sha1_smol::Sha1) -> String {
+    base64::engine::general_purpose::STANDARD.encode(hasher.digest().bytes())
+}
+
+/// What identifies a file's contents without reading it: where it lives on disk, its size
+/// and its modification time. Anything that rewrites the file changes one of them.
+fn identity(path: &Path, metadata: &Metadata) -> String {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = path;
+        format!(
+            "{}:{}:{}:{}",
+            metadata.dev(),
+            metadata.ino(),
+            metadata.len(),
+            mtime
+        )
+    }
+    #[cfg(not(unix))]
+    {
+        // No stable inode; the absolute path takes its place.
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        format!("{}:{}:{}", path.display(), metadata.len(), mtime)
+    }
+}
@@ -1,8 +1,24 @@
+use crate::crypto;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Alternate locations set from the command line.
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+struct Paths {
+    config_file: Option<PathBuf>,
+    state_dir: Option<PathBuf>,
+}
+
+/// Environment variable holding the passphrase of an encrypted config file.
+pub const PASSPHRASE_ENV: &str = "IMMICH_CONFIG_PASSPHRASE";
+
+/// Environment variable holding the passphrase of encrypted user exports.
+pub const EXPORT_PASSPHRASE_ENV: &str = "IMMICH_EXPORT_PASSPHRASE";
 
 /// Configuration for the Immich uploader, storing multiple users and the current active user.
 #[derive(Serialize, Deserialize, Default, Debug)]
@@ -11,6 +27,159 @@ pub struct Config {
     pub current_user: Option<String>,
     /// A map of user names to their respective configurations.
     pub users: HashMap<String, UserConfig>,
+    /// Connection tuning for the HTTP client.
+    #[serde(default, skip_serializing_if = "HttpConfig::is_unset")]
+    pub http: HttpConfig,
+    /// Named upload presets, selected with `--profile`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, Profile>,
+    /// File name patterns giving capture dates, tried before the built-in ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub date_rules: Vec<DateRule>,
+    /// Globs of file names skipped as temporary or partial files, besides the built-in ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub partial_files: Vec<String>,
+    /// Shell commands run before and after uploads.
+    #[serde(default, skip_serializing_if = "Hooks::is_unset")]
+    pub hooks: Hooks,
+    /// Passphrase the file is encrypted with; it is written back encrypted when set.
+    #[serde(skip)]
+    pub passphrase: Option<String>,
+}
+
+/// A named preset of upload settings. Each value applies unless the matching flag is given.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct Profile {
+    /// Configured user to upload as.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Number of concurrent uploads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrent: Option<usize>,
+    /// Album every uploaded file is added to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// Mirror the folder tree as albums.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub album_mirror: bool,
+    /// Separator between folder names in mirrored album names.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_separator: Option<String>,
+    /// Minimum star rating for favorites.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favorite_rating: Option<i32>,
+    /// Apply embedded keywords as tags.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub import_tags: bool,
+    /// Users albums created by a run are shared with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub share_with: Vec<String>,
+    /// "viewer" or "editor".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share_role: Option<String>,
+    /// "timeline", "hidden" or "locked".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    /// Verify checksums after upload.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verify: bool,
+    /// Upload Motion Photo videos separately, as Live Photos.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub split_motion_photos: bool,
+    /// "tag", "archive" or "skip".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screenshots: Option<String>,
+    /// Album screenshots are routed to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screenshot_album: Option<String>,
+    /// Folder levels below the scanned directory to descend into at most.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    /// Stay on the filesystem of the scanned directory.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub one_file_system: bool,
+    /// Minimum age of files to upload, e.g. "30s".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_age: Option<String>,
+    /// Leave files other processes have open for a later run.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_open: bool,
+    /// "path", "mtime", "exif-date" or "size".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<String>,
+    /// "smallest-first", "newest-first" or "images-first".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    /// Ask before uploading.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interactive: bool,
+    /// Wait for the server to come back when it becomes unreachable.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub wait_for_server: bool,
+}
+
+/// A file name pattern the capture date of matching files is read from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DateRule {
+    /// Regex with named groups `year`, `month`, `day` and optionally `hour`, `minute`,
+    /// `second` and `ampm`, matched against the file name.
+    pub pattern: String,
+    /// Glob the file's full path has to match, e.g. "**/Scans/**"; any file when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Whether the time in the name is UTC rather than local time.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub utc: bool,
+}
+
+/// Shell commands run around uploads, with details in `IMMICH_*` environment variables.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Hooks {
+    /// Run before each file; the file is failed instead of uploaded when it fails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_file: Option<String>,
+    /// Run after each file, whether it was uploaded or not.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_file: Option<String>,
+    /// Run once all files of a run are done.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_run: Option<String>,
+    /// Run once before uploading to decide which files to upload, and their albums and
+    /// tags.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+}
+
+impl Hooks {
+    fn is_unset(&self) -> bool {
+        *self == Hooks::default()
+    }
+}
+
+/// Connection settings of the HTTP client talking to the Immich server.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct HttpConfig {
+    /// Negotiate HTTP/2 instead of HTTP/1.1.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub http2: bool,
+    /// Seconds an idle connection is kept open for reuse.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_idle_timeout: Option<u64>,
+    /// Maximum number of idle connections kept open per host.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle: Option<usize>,
+    /// Proxy all requests go through, e.g. "socks5h://localhost:1080".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Addresses to use for host names instead of DNS, as "HOST:PORT:ADDRESS".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolve: Vec<String>,
+    /// Only connect over IPv4.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ipv4: bool,
+    /// Only connect over IPv6.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ipv6: bool,
 }
 
 /// Configuration details for a specific Immich user.
@@ -20,41 +189,95 @@ pub struct UserConfig {
     pub api_key: String,
     /// Base URL of the Immich server.
     pub server_url: String,
+    /// Album uploads go to when no `--album` is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// Upload straight into the archive unless `--visibility` says otherwise.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archive: bool,
+    /// Device ID reported to the server instead of the default one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+}
+
+impl HttpConfig {
+    fn is_unset(&self) -> bool {
+        *self == HttpConfig::default()
+    }
 }
 
 impl Config {
     /// Loads the configuration from the default path (~/.immich/config.toml).
-    /// Returns default config if the file does not exist.
+    /// Returns default config if the file does not exist. An encrypted file is decrypted
+    /// with the passphrase from IMMICH_CONFIG_PASSPHRASE, or one asked for on the terminal.
     pub fn load() -> Result<Self> {
-        let path = Self::config_path()?;
+        let path = Self::path()?;
         if !path.exists() {
             return Ok(Config::default());
         }
         let content = fs::read_to_string(path)?;
+        if crypto::is_encrypted(&content) {
+            let passphrase = crypto::passphrase(PASSPHRASE_ENV, "Config passphrase")?;
+            let decrypted =
+                crypto::decrypt(&content, &passphrase).context("Failed to decrypt config")?;
+            let mut config: Config = toml::from_str(&decrypted)?;
+            config.passphrase = Some(passphrase);
+            return Ok(config);
+        }
         let config: Config = toml::from_str(&content)?;
         Ok(config)
     }
 
-    /// Saves the current configuration to the default path.
+    /// Saves the current configuration to the default path, encrypted if it has a passphrase.
     /// Creates parent directories if they don't exist.
     pub fn save(&self) -> Result<()> {
-        let path = Self::config_path()?;
+        let path = Self::path()?;
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let content = toml::to_string_pretty(self)?;
+        let mut content = toml::to_string_pretty(self)?;
+        if let Some(passphrase) = &self.passphrase {
+            content = crypto::encrypt(&content, passphrase)? + "\n";
+        }
         fs::write(path, content)?;
         Ok(())
     }
 
+    /// Points the configuration file and state directory somewhere else for this process.
+    /// Without an explicit state directory, state is kept next to an alternate config file.
+    pub fn set_paths(config_file: Option<PathBuf>, state_dir: Option<PathBuf>) {
+        let state_dir = state_dir.or_else(|| {
+            config_file
+                .as_ref()
+                .and_then(|f| f.parent())
+                .map(|p| p.to_path_buf())
+        });
+        let _ = PATHS.set(Paths {
+            config_file,
+            state_dir,
+        });
+    }
+
     /// Determines the configuration file path.
     /// Typically ~/.immich/config.toml on Unix systems.
-    fn config_path() -> Result<PathBuf> {
+    pub fn path() -> Result<PathBuf> {
+        if let Some(file) = PATHS.get().and_then(|p| p.config_file.clone()) {
+            return Ok(file);
+        }
+        Ok(Self::state_dir()?.join("config.toml"))
+    }
+
+    /// Directory holding the configuration and state kept between runs: the one given with
+    /// `--state-dir` (e.g. a writable volume in a container), otherwise ~/.immich.
+    pub fn state_dir() -> Result<PathBuf> {
+        if let Some(dir) = PATHS.get().and_then(|p| p.state_dir.clone()) {
+            return Ok(dir);
+        }
         let home = std::env::var("HOME").map(PathBuf::from).or_else(|_| {
             #[allow(deprecated)]
             std::env::home_dir().context("Could not find home directory")
         })?;
-        Ok(home.join(".immich").join("config.toml"))
+        Ok(home.join(".immich"))
     }
 
     /// Retrieves the current active user from the configuration map.
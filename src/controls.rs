@@ -0,0 +1,239 @@
+use crate::progress::{self, Progress, ProgressMode};
+use std::io::{IsTerminal, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use tokio::sync::Notify;
+
+/// Highest number of parallel uploads `+` raises the concurrency to (unless more were
+/// asked for with `--concurrent`).
+pub const MAX_CONCURRENCY: usize = 64;
+
+/// Keys understood while uploading, shown when the run starts.
+const HELP: &str = "Keys: p pause, r resume, +/- concurrency, q stop.";
+
+/// Control over a running upload: how many files upload at once, pausing, and stopping.
+/// Uploads take a slot before they start, so changes apply to the next files.
+pub struct Controls {
+    state: Mutex<State>,
+    changed: Notify,
+    /// Upper bound of `limit`.
+    capacity: usize,
+}
+
+struct State {
+    paused: bool,
+    stopped: bool,
+    /// Number of uploads allowed at once.
+    limit: usize,
+    /// Number of uploads running.
+    active: usize,
+}
+
+/// A running upload; frees its place when dropped.
+pub struct Slot(Arc<Controls>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().active -= 1;
+        self.0.changed.notify_waiters();
+    }
+}
+
+impl Controls {
+    /// Allows `concurrent` uploads at once.
+    pub fn new(concurrent: usize) -> Arc<Self> {
+        Arc::new(Controls {
+            state: Mutex::new(State {
+                paused: false,
+                stopped: false,
+                limit: concurrent.max(1),
+                active: 0,
+            }),
+            changed: Notify::new(),
+            capacity: concurrent.max(MAX_CONCURRENCY),
+        })
+    }
+
+    /// Most uploads that can ever run at once.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Waits until another upload may start, or returns `None` once the run was stopped.
+    pub async fn acquire(self: &Arc<Self>) -> Option<Slot> {
+        loop {
+            let changed = self.changed.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.stopped {
+                    return None;
+                }
+                if !state.paused && state.active < state.limit {
+                    state.active += 1;
+                    return Some(Slot(Arc::clone(self)));
+                }
+            }
+            changed.await;
+        }
+    }
+
+    /// Starts no further uploads; returns false if already stopped.
+    pub fn stop(&self) -> bool {
+        let first = !std::mem::replace(&mut self.state.lock().unwrap().stopped, true);
+        self.changed.notify_waiters();
+        first
+    }
+
+    /// Whether the run was stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.state.lock().unwrap().stopped
+    }
+
+    /// Pauses or resumes starting uploads; returns the number still running.
+    pub fn set_paused(&self, paused: bool) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.paused = paused;
+        self.changed.notify_waiters();
+        state.active
+    }
+
+    /// Changes the number of parallel uploads by `delta` and returns the new number.
+    fn adjust(&self, delta: isize) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.limit = state
+            .limit
+            .saturating_add_signed(delta)
+            .clamp(1, self.capacity);
+        self.changed.notify_waiters();
+        state.limit
+    }
+}
+
+/// Reads keypresses in the background while the progress bar is shown.
+pub struct KeyListener {
+    done: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl KeyListener {
+    /// Starts listening when the progress bar is drawn on a terminal the user can type
+    /// into (Unix only); returns `None` otherwise.
+    pub fn start(controls: &Arc<Controls>, pb: &Progress) -> Option<Self> {
+        if progress::mode() != ProgressMode::Bar || !std::io::stdin().is_terminal() {
+            return None;
+        }
+        let tty = terminal::enter()?;
+        pb.println(HELP);
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let done = Arc::clone(&done);
+            let controls = Arc::clone(controls);
+            let pb = pb.clone();
+            std::thread::spawn(move || listen(tty, &controls, &pb, &done))
+        };
+        Some(KeyListener { done, thread })
+    }
+
+    /// Stops listening and gives the terminal back its usual settings.
+    pub fn finish(self) {
+        self.done.store(true, Ordering::SeqCst);
+        let _ = self.thread.join();
+    }
+}
+
+fn listen(mut tty: std::fs::File, controls: &Controls, pb: &Progress, done: &AtomicBool) {
+    let mut key = [0u8; 1];
+    while !done.load(Ordering::SeqCst) {
+        // Reads time out after a moment, so `done` is checked regularly.
+        match tty.read(&mut key) {
+            Ok(1) => {}
+            Ok(_) => continue,
+            Err(_) => break,
+        }
+        match key[0] {
+            b'p' => {
+                let active = controls.set_paused(true);
+                pb.println(format!(
+                    "Paused: {} uploads in progress will finish; press r to resume.",
+                    active
+                ));
+            }
+            b'r' => {
+                controls.set_paused(false);
+                pb.println("Resumed.");
+            }
+            b'+' | b'=' => {
+                let limit = controls.adjust(1);
+                pb.println(format!("Concurrency: {}", limit));
+            }
+            b'-' | b'_' => {
+                let limit = controls.adjust(-1);
+                pb.println(format!("Concurrency: {}", limit));
+            }
+            b'q' if controls.stop() => {
+                pb.println("Stopping: waiting for uploads in progress to finish.");
+            }
+            _ => {}
+        }
+    }
+    terminal::restore();
+}
+
+/// Puts the terminal into a mode where keys are read one at a time without echo, through
+/// `stty` so Ctrl-C keeps working as usual.
+#[cfg(unix)]
+mod terminal {
+    use std::fs::File;
+    use std::process::{Command, Stdio};
+    use std::sync::Mutex;
+
+    /// Settings to restore, as printed by `stty -g`.
+    static SAVED: Mutex<Option<String>> = Mutex::new(None);
+
+    /// Runs `stty` on the controlling terminal and returns what it printed, if it worked.
+    pub fn stty(args: &[&str]) -> Option<String> {
+        let output = Command::new("stty")
+            .args(args)
+            .stdin(File::open("/dev/tty").ok()?)
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Switches to key-at-a-time input, where reads return after 0.2s without a key.
+    pub fn enter() -> Option<File> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "min", "0", "time", "2"])?;
+        *SAVED.lock().unwrap() = Some(saved);
+        File::open("/dev/tty").ok()
+    }
+
+    /// Restores the settings from before `enter`, if it was called.
+    pub fn restore() {
+        if let Some(saved) = SAVED.lock().unwrap().take() {
+            stty(&[&saved]);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    pub fn enter() -> Option<std::fs::File> {
+        None
+    }
+
+    pub fn restore() {}
+}
+
+#[cfg(unix)]
+pub use terminal::stty;
+
+/// Gives the terminal back its usual settings before the process exits early.
+pub fn restore_terminal() {
+    terminal::restore();
+}
@@ -0,0 +1,152 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::aead::{CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io::Write;
+use std::num::NonZeroU32;
+
+/// Marks text produced by `encrypt`.
+const HEADER: &str = "rimmich-encrypted:v1:";
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// Whether text was produced by `encrypt`.
+pub fn is_encrypted(text: &str) -> bool {
+    text.trim_start().starts_with(HEADER)
+}
+
+/// Encrypts text with a passphrase (PBKDF2-SHA256 and ChaCha20-Poly1305) into a single
+/// printable line.
+pub fn encrypt(plaintext: &str, passphrase: &str) -> Result<String> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| anyhow::anyhow!("No secure random source available"))?;
+
+    let mut data = plaintext.as_bytes().to_vec();
+    derive_key(passphrase, &salt)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            ring::aead::Aad::empty(),
+            &mut data,
+        )
+        .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+
+    let mut out = salt.to_vec();
+    out.extend_from_slice(&nonce);
+    out.extend(data);
+    Ok(format!("{}{}", HEADER, STANDARD.encode(out)))
+}
+
+/// Decrypts text produced by `encrypt`.
+pub fn decrypt(text: &str, passphrase: &str) -> Result<String> {
+    let encoded = text
+        .trim()
+        .strip_prefix(HEADER)
+        .context("Not an encrypted file")?;
+    let data = STANDARD.decode(encoded).context("Corrupt encrypted data")?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        anyhow::bail!("Corrupt encrypted data");
+    }
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = derive_key(passphrase, salt)?
+        .open_in_place(
+            Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow::anyhow!("Bad nonce"))?,
+            ring::aead::Aad::empty(),
+            &mut buffer,
+        )
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase or corrupt data"))?;
+    Ok(String::from_utf8(plaintext.to_vec())?)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey> {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+        .map_err(|_| anyhow::anyhow!("Invalid key length"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Reads a passphrase from the environment variable `env`, or asks for it on the terminal.
+pub fn passphrase(env: &str, prompt: &str) -> Result<String> {
+    if let Ok(value) = std::env::var(env) {
+        return Ok(value);
+    }
+    read_hidden(&format!("{} (or set {}): ", prompt, env))
+}
+
+/// Like `passphrase`, but asks twice on the terminal: a typo in a passphrase that is being
+/// set would leave what it encrypts unrecoverable.
+pub fn new_passphrase(env: &str, prompt: &str) -> Result<String> {
+    if let Ok(value) = std::env::var(env) {
+        return Ok(value);
+    }
+    let passphrase = read_hidden(&format!("{} (or set {}): ", prompt, env))?;
+    if read_hidden("Repeat to confirm: ")? != passphrase {
+        anyhow::bail!("The passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+/// Reads a line from stdin without showing it on the terminal.
+fn read_hidden(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+    std::io::stderr().flush()?;
+    let echo = terminal::hide_input();
+    let mut line = String::new();
+    let read = std::io::stdin().read_line(&mut line);
+    if let Some(saved) = echo {
+        terminal::restore(&saved);
+        // The newline typed at the end wasn't echoed either.
+        eprintln!();
+    }
+    read?;
+    let passphrase = line.trim_end_matches(['\r', '\n']).to_string();
+    if passphrase.is_empty() {
+        anyhow::bail!("No passphrase given");
+    }
+    Ok(passphrase)
+}
+
+/// Turns off echo through `stty`.
+#[cfg(unix)]
+mod terminal {
+    use crate::controls::stty;
+
+    /// Stops echoing typed keys and returns the settings to restore, when stdin is the
+    /// terminal; piped input has nothing to hide.
+    pub fn hide_input() -> Option<String> {
+        if !std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+            return None;
+        }
+        let saved = stty(&["-g"])?;
+        stty(&["-echo"])?;
+        Some(saved)
+    }
+
+    pub fn restore(saved: &str) {
+        stty(&[saved]);
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    pub fn hide_input() -> Option<String> {
+        None
+    }
+
+    pub fn restore(_saved: &str) {}
+}
@@ -0,0 +1,114 @@
+use crate::api::{Asset, ImmichClient};
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use std::collections::{HashMap, HashSet};
+
+/// A set of assets of which all but one could be deleted.
+struct Cluster<'a> {
+    assets: Vec<&'a Asset>,
+}
+
+impl Cluster<'_> {
+    /// Size of an asset, or of a byte-identical copy in the cluster when the server didn't
+    /// report it.
+    fn size(&self, asset: &Asset) -> Option<u64> {
+        asset.file_size().or_else(|| {
+            self.assets
+                .iter()
+                .filter(|copy| asset.checksum.is_some() && copy.checksum == asset.checksum)
+                .find_map(|copy| copy.file_size())
+        })
+    }
+
+    /// Space freed by keeping only the largest asset, counting assets of known size.
+    fn reclaimable(&self) -> u64 {
+        let sizes = self.assets.iter().map(|a| self.size(a).unwrap_or(0));
+        sizes.clone().sum::<u64>() - sizes.max().unwrap_or(0)
+    }
+
+    fn print(&self, title: &str) {
+        println!(
+            "{} ({} reclaimable):",
+            title,
+            HumanBytes(self.reclaimable())
+        );
+        for asset in &self.assets {
+            println!(
+                "  {}  {}  {:>10}  {}",
+                asset.id,
+                asset.file_created_at.format("%Y-%m-%d %H:%M"),
+                self.size(asset)
+                    .map(|size| HumanBytes(size).to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+                asset.original_file_name
+            );
+        }
+    }
+}
+
+/// Prints the user's duplicate assets: byte-identical ones (same checksum) and, unless
+/// `exact_only`, the visually similar groups found by the server's duplicate detection.
+pub async fn report(client: &ImmichClient, exact_only: bool) -> Result<()> {
+    println!("Listing assets...");
+    let assets = client.all_assets().await.context("Failed to list assets")?;
+
+    let mut by_checksum: HashMap<&str, Vec<&Asset>> = HashMap::new();
+    for asset in &assets {
+        if let Some(checksum) = &asset.checksum {
+            by_checksum.entry(checksum).or_default().push(asset);
+        }
+    }
+    let mut exact: Vec<Cluster> = by_checksum
+        .into_values()
+        .filter(|assets| assets.len() > 1)
+        .map(|assets| Cluster { assets })
+        .collect();
+    exact.sort_by_key(|c| std::cmp::Reverse(c.reclaimable()));
+
+    let mut total = 0;
+    for cluster in &exact {
+        cluster.print(&format!("{} identical copies", cluster.assets.len()));
+        total += cluster.reclaimable();
+    }
+
+    let similar = if exact_only {
+        Vec::new()
+    } else {
+        match client.duplicates().await {
+            Ok(groups) => groups,
+            Err(e) => {
+                println!(
+                    "Skipping similar assets, the server did not list them: {:#}",
+                    e
+                );
+                Vec::new()
+            }
+        }
+    };
+    // Identical copies are usually reported again as similar; show only what is new.
+    let exact_ids: HashSet<&str> = exact
+        .iter()
+        .flat_map(|c| c.assets.iter().map(|a| a.id.as_str()))
+        .collect();
+    let mut similar: Vec<Cluster> = similar
+        .iter()
+        .map(|group| Cluster {
+            assets: group.assets.iter().collect(),
+        })
+        .filter(|c| !c.assets.iter().all(|a| exact_ids.contains(a.id.as_str())))
+        .collect();
+    similar.sort_by_key(|c| std::cmp::Reverse(c.reclaimable()));
+    for cluster in &similar {
+        cluster.print(&format!("{} similar assets", cluster.assets.len()));
+        total += cluster.reclaimable();
+    }
+
+    println!(
+        "{} groups of identical and {} groups of similar assets among {} assets; about {} reclaimable.",
+        exact.len(),
+        similar.len(),
+        assets.len(),
+        HumanBytes(total)
+    );
+    Ok(())
+}
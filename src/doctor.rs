@@ -0,0 +1,392 @@
+use crate::api::{self, ImmichClient};
+use crate::benchmark;
+use crate::config::{Config, HttpConfig};
+use crate::exit::ExitKind;
+use crate::parse_profile_value;
+use crate::upload::{ShareRole, Visibility};
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long connecting to each of the server's addresses may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Verifies a stored key and describes its owner and permissions.
+pub async fn check_user(client: &ImmichClient) -> Result<String> {
+    client.ping().await.context("server unreachable")?;
+    client.validate_key().await.context("key rejected")?;
+    // Keys restricted to some permissions may not be allowed to read these.
+    let owner = match client.current_user().await {
+        Ok(user) => format!("{} <{}>", user.name, user.email),
+        Err(_) => "owner not readable".to_string(),
+    };
+    let permissions = match client.current_api_key().await {
+        Ok(key) if key.permissions.iter().any(|p| p == "all") => {
+            format!("key '{}' with all permissions", key.name)
+        }
+        Ok(key) => format!("key '{}' with {}", key.name, key.permissions.join(", ")),
+        Err(_) => "permissions not readable".to_string(),
+    };
+    Ok(format!("valid, {}, {}", owner, permissions))
+}
+
+/// Tally of the checks that passed and failed, printed as they are made.
+#[derive(Default)]
+pub struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn check(&mut self, ok: bool, message: String) {
+        if ok {
+            println!("  ok     {}", message);
+        } else {
+            self.problems += 1;
+            println!("  error  {}", message);
+        }
+    }
+
+    /// Prints the verdict, failing when any check did.
+    pub fn finish(self) -> Result<()> {
+        if self.problems > 0 {
+            return Err(anyhow::anyhow!(
+                "{} problem{} found",
+                self.problems,
+                if self.problems == 1 { "" } else { "s" }
+            )
+            .context(ExitKind::Config));
+        }
+        println!("No problems found.");
+        Ok(())
+    }
+}
+
+/// Checks everything a run depends on and prints what is wrong and how to fix it.
+pub async fn run() -> Result<()> {
+    let mut report = Report::default();
+    let Some(config) = check_config(&mut report)? else {
+        return report.finish();
+    };
+
+    println!("Users:");
+    let mut names: Vec<&String> = config.users.keys().collect();
+    names.sort();
+    if names.is_empty() {
+        println!("  none configured; add one with `user add`");
+    }
+    for name in names {
+        let user = &config.users[name];
+        if let Err(e) = url::Url::parse(&user.server_url) {
+            report.check(
+                false,
+                format!(
+                    "{}: server URL {:?} is invalid ({}); fix it with `user edit {} --server <URL>`",
+                    name, user.server_url, e, name
+                ),
+            );
+            continue;
+        }
+        let client = ImmichClient::new(
+            api::http_client(&config.http, &user.server_url)?,
+            &user.server_url,
+            &user.api_key,
+        );
+        match check_user(&client).await {
+            Ok(summary) => report.check(true, format!("{}: {}", name, summary)),
+            Err(e) => {
+                let hint = if e.to_string() == "key rejected" {
+                    format!("replace the key with `user edit {} --key <KEY>`", name)
+                } else {
+                    format!(
+                        "check the server is up, or fix its URL with `user edit {} --server <URL>`",
+                        name
+                    )
+                };
+                report.check(false, format!("{}: {:#}; {}", name, e, hint));
+            }
+        }
+    }
+    report.finish()
+}
+
+/// Checks the config file and state directory, returning the config unless it doesn't load.
+pub fn check_config(report: &mut Report) -> Result<Option<Config>> {
+    println!("Config file:");
+    let path = Config::path()?;
+    let config = if !path.exists() {
+        report.check(
+            true,
+            format!("{:?} does not exist yet (created by `user add`)", path),
+        );
+        Config::default()
+    } else {
+        match Config::load() {
+            Ok(config) => {
+                report.check(true, format!("{:?} loads", path));
+                config
+            }
+            Err(e) => {
+                report.check(
+                    false,
+                    format!(
+                        "{:?} does not load: {:#}. Fix the file or remove it.",
+                        path, e
+                    ),
+                );
+                return Ok(None);
+            }
+        }
+    };
+
+    if let Some(current) = &config.current_user
+        && !config.users.contains_key(current)
+    {
+        report.check(
+            false,
+            format!(
+                "default user '{}' does not exist; run `user default <name>`",
+                current
+            ),
+        );
+    }
+    for (name, profile) in &config.profiles {
+        if let Some(user) = profile
+            .user
+            .as_ref()
+            .filter(|u| !config.users.contains_key(*u))
+        {
+            report.check(
+                false,
+                format!("profile '{}' refers to unknown user '{}'", name, user),
+            );
+        }
+        if let Err(e) = parse_profile_value::<Visibility>(profile.visibility.as_deref()).and(
+            parse_profile_value::<ShareRole>(profile.share_role.as_deref()),
+        ) {
+            report.check(false, format!("profile '{}': {:#}", name, e));
+        }
+    }
+
+    println!("State directory:");
+    let state_dir = Config::state_dir()?;
+    match check_writable(&state_dir) {
+        Ok(()) => report.check(true, format!("{:?} is writable", state_dir)),
+        Err(e) => report.check(
+            false,
+            format!(
+                "{:?} is not writable ({:#}); fix its permissions or pass --state-dir",
+                state_dir, e
+            ),
+        ),
+    }
+    Ok(Some(config))
+}
+
+/// Checks each step of talking to the server in turn: name resolution, TCP connection,
+/// TLS handshake, ping, the key, uploading, and whether a reverse proxy lets a body of
+/// `body_size` bytes through. Probe uploads are deleted permanently. Stops at the first
+/// step that fails, as the later ones would fail with it.
+pub async fn check_connection(
+    report: &mut Report,
+    client: &ImmichClient,
+    http: &HttpConfig,
+    body_size: u64,
+) -> Result<()> {
+    println!("Server {}:", client.server_url());
+    if let Some(path) = client.server_url().strip_prefix(api::UNIX_SCHEME) {
+        let exists = Path::new(path).exists();
+        report.check(
+            exists,
+            match exists {
+                true => format!("socket {} exists", path),
+                false => format!(
+                    "socket {} does not exist; check the path and that the proxy listens on it",
+                    path
+                ),
+            },
+        );
+        if !exists {
+            return Ok(());
+        }
+    } else {
+        let url = match url::Url::parse(client.server_url()) {
+            Ok(url) => url,
+            Err(e) => {
+                report.check(
+                    false,
+                    format!("the URL is invalid ({}); use e.g. http://host:2283", e),
+                );
+                return Ok(());
+            }
+        };
+        if http.proxy.is_some() {
+            println!("  skip   name resolution and TCP, as requests go through the proxy");
+        } else if !check_network(report, &url, &http.resolve).await {
+            return Ok(());
+        }
+        if url.scheme() == "https" {
+            let response = api::http_client(http, client.server_url())?
+                .get(client.server_url())
+                .send()
+                .await;
+            match response {
+                Ok(_) => report.check(true, "TLS handshake succeeded".to_string()),
+                Err(e) => {
+                    report.check(
+                        false,
+                        format!(
+                            "TLS handshake failed ({:#}); check the certificate is valid for \
+                             the host name and issued by a trusted authority",
+                            anyhow::Error::new(e)
+                        ),
+                    );
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    if let Err(e) = client.ping().await {
+        report.check(
+            false,
+            format!(
+                "ping failed ({:#}); check the URL points at Immich itself, without /api",
+                e
+            ),
+        );
+        return Ok(());
+    }
+    report.check(true, "Immich answers ping".to_string());
+    if client.is_shared_link() {
+        println!("  skip   key and upload checks, which need an API key");
+        return Ok(());
+    }
+    match check_user(client).await {
+        Ok(summary) => report.check(true, format!("key {}", summary)),
+        Err(e) => {
+            report.check(
+                false,
+                format!(
+                    "{:#}; create a new key under Account Settings > API Keys",
+                    e
+                ),
+            );
+            return Ok(());
+        }
+    }
+
+    let (id, data) = benchmark::synthetic_payload(0)?;
+    match probe_upload(client, id, data).await {
+        Ok(()) => report.check(true, "uploads are accepted".to_string()),
+        Err(e) => {
+            report.check(
+                false,
+                format!(
+                    "upload failed ({:#}); the key needs the asset.upload permission",
+                    e
+                ),
+            );
+            return Ok(());
+        }
+    }
+    let (id, data) = benchmark::synthetic_payload(body_size as usize)?;
+    let size = HumanBytes(data.len() as u64);
+    match probe_upload(client, id, data).await {
+        Ok(()) => report.check(true, format!("a {} upload goes through", size)),
+        Err(e) if e.to_string().contains("413") => report.check(
+            false,
+            format!(
+                "a {} upload is rejected as too large; raise the reverse proxy's body size \
+                 limit (e.g. client_max_body_size 0; for nginx)",
+                size
+            ),
+        ),
+        Err(e) => report.check(
+            false,
+            format!(
+                "a {} upload failed ({:#}); a reverse proxy may limit body size or time",
+                size, e
+            ),
+        ),
+    }
+    Ok(())
+}
+
+/// Resolves the server's host name and connects to it, reporting each step; returns
+/// whether both worked.
+async fn check_network(report: &mut Report, url: &url::Url, resolve: &[String]) -> bool {
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        report.check(false, "the URL has no host".to_string());
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if resolve
+        .iter()
+        .any(|r| r.starts_with(&format!("{}:{}:", host, port)))
+    {
+        println!(
+            "  skip   name resolution and TCP, as --resolve maps {}",
+            host
+        );
+        return true;
+    }
+    let addresses: Vec<SocketAddr> = match tokio::net::lookup_host((host, port)).await {
+        Ok(addresses) => addresses.collect(),
+        Err(e) => {
+            report.check(
+                false,
+                format!(
+                    "{} does not resolve ({}); check the host name and DNS, or map it with --resolve",
+                    host, e
+                ),
+            );
+            return false;
+        }
+    };
+    let listed: Vec<String> = addresses.iter().map(|a| a.ip().to_string()).collect();
+    report.check(true, format!("{} resolves to {}", host, listed.join(", ")));
+
+    let mut last_error = None;
+    for address in &addresses {
+        match tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::TcpStream::connect(address)).await {
+            Ok(Ok(_)) => {
+                report.check(true, format!("TCP connection to {} succeeded", address));
+                return true;
+            }
+            Ok(Err(e)) => last_error = Some(e.to_string()),
+            Err(_) => last_error = Some("timed out".to_string()),
+        }
+    }
+    report.check(
+        false,
+        format!(
+            "no TCP connection to port {} ({}); check the server is running and no firewall \
+             blocks the port",
+            port,
+            last_error.unwrap_or_default()
+        ),
+    );
+    false
+}
+
+/// Uploads a probe and deletes it permanently.
+async fn probe_upload(client: &ImmichClient, id: String, data: Vec<u8>) -> Result<()> {
+    if let Some(asset_id) = benchmark::upload_synthetic(client, "doctor", id, data).await? {
+        client
+            .delete_assets(&[asset_id], true)
+            .await
+            .context("Failed to delete the probe upload")?;
+    }
+    Ok(())
+}
+
+/// Checks that a directory can be created and written to.
+fn check_writable(dir: &std::path::Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(".write-test");
+    std::fs::write(&probe, b"").context("cannot create files")?;
+    std::fs::remove_file(&probe)?;
+    Ok(())
+}
@@ -0,0 +1,636 @@
+use crate::albums;
+use crate::api::{Asset, ImmichClient};
+use crate::assets::{self, Selection};
+use crate::exit;
+use crate::progress::{self, Progress};
+use crate::sidecar::{self, SidecarFormat};
+use anyhow::{Context, Result, bail};
+use chrono::{Datelike, Local};
+use futures::StreamExt;
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::io::AsyncWriteExt;
+
+/// File in the destination recording which asset each downloaded file holds.
+pub const DOWNLOAD_MANIFEST: &str = "downloads.json";
+
+/// Attempts at a download whose connection keeps dropping.
+const RESUME_ATTEMPTS: u32 = 5;
+
+/// Pause before resuming an interrupted download.
+const RESUME_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Directory name for assets in no album with `--layout album`.
+const NO_ALBUM: &str = "No album";
+
+/// How downloaded originals are arranged in the destination directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Layout {
+    /// Every file directly in the destination.
+    #[default]
+    Flat,
+    /// A directory per album. Assets in several albums are linked into each; those in
+    /// none go to "No album".
+    Album,
+    /// `YYYY/MM` directories by capture date, in local time.
+    Date,
+}
+
+/// What to do when an asset's file name is already taken by another file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Collision {
+    /// Append the start of the asset ID, e.g. `IMG_0001-3f1c9a2e.jpg`.
+    #[default]
+    Suffix,
+    /// Prepend the capture date, e.g. `20240101-093000-IMG_0001.jpg`, or append the ID
+    /// when that is taken too.
+    Date,
+    /// Leave the asset out.
+    Skip,
+}
+
+/// Which asset each file in a download directory holds.
+#[derive(Serialize, Deserialize, Default)]
+struct DownloadManifest {
+    files: Vec<DownloadedFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DownloadedFile {
+    /// Path relative to the download directory.
+    path: PathBuf,
+    asset_id: String,
+    original_file_name: String,
+}
+
+impl DownloadManifest {
+    fn load(destination: &Path) -> Result<Self> {
+        let path = destination.join(DOWNLOAD_MANIFEST);
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {:?}", path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        }
+    }
+
+    fn save(&self, destination: &Path) -> Result<()> {
+        let path = destination.join(DOWNLOAD_MANIFEST);
+        let temp = destination.join(format!("{}.part", DOWNLOAD_MANIFEST));
+        std::fs::write(&temp, serde_json::to_string_pretty(self)?)
+            .and_then(|()| std::fs::rename(&temp, &path))
+            .with_context(|| format!("Failed to write {:?}", path))
+    }
+}
+
+/// Where an asset is saved, and whether it already is.
+struct Target {
+    path: PathBuf,
+    downloaded: bool,
+}
+
+/// Downloads the originals of the selected assets into `destination` under their original
+/// file names, arranged by `layout`, and records in `downloads.json` which asset each file
+/// holds. Files that are already there are skipped; names taken by other files are
+/// resolved by `collision`. With `sidecar`, the server's metadata of each asset is written
+/// next to it.
+pub async fn download(
+    client: &ImmichClient,
+    selection: &Selection,
+    destination: &Path,
+    concurrent: usize,
+    sidecar: Option<SidecarFormat>,
+    layout: Layout,
+    collision: Collision,
+) -> Result<()> {
+    let assets = assets::select(client, selection).await?;
+    if assets.is_empty() {
+        println!("No matching assets.");
+        return Ok(());
+    }
+    tokio::fs::create_dir_all(destination)
+        .await
+        .with_context(|| format!("Failed to create {:?}", destination))?;
+    let assets = arrange(client, assets, selection, destination, layout).await?;
+    let mut manifest = DownloadManifest::load(destination)?;
+    let assets = plan(assets, destination, collision, &manifest).await;
+    let needed = assets
+        .iter()
+        .filter(|(_, targets)| !targets[0].downloaded)
+        .map(|(asset, _)| asset.file_size().unwrap_or(0))
+        .sum();
+    ensure_space(destination, needed)?;
+    println!(
+        "Downloading {} assets to {:?}...",
+        assets.len(),
+        destination
+    );
+
+    let total = assets.len();
+    let pb = Progress::new(total)?;
+    let failed = AtomicUsize::new(0);
+    let mut downloads = futures::stream::iter(&assets)
+        .map(|(asset, targets)| {
+            let pb = pb.clone();
+            let failed = &failed;
+            async move {
+                if let Err(e) = download_asset(client, asset, targets, sidecar).await {
+                    pb.warn(format!(
+                        "Failed to download {}: {:#}",
+                        asset.original_file_name, e
+                    ));
+                    failed.fetch_add(1, Ordering::Relaxed);
+                }
+                pb.inc();
+            }
+        })
+        .buffer_unordered(concurrent);
+    while downloads.next().await.is_some() {}
+    drop(downloads);
+    pb.finish("Download complete");
+
+    // Files of this run replace what was recorded for their paths.
+    let mut files = Vec::new();
+    for (asset, targets) in &assets {
+        for target in targets {
+            if tokio::fs::metadata(&target.path).await.is_ok() {
+                files.push(DownloadedFile {
+                    path: target.path.strip_prefix(destination)?.to_path_buf(),
+                    asset_id: asset.id.clone(),
+                    original_file_name: asset.original_file_name.clone(),
+                });
+            }
+        }
+    }
+    let written: HashSet<&Path> = files.iter().map(|f| f.path.as_path()).collect();
+    manifest
+        .files
+        .retain(|f| !written.contains(f.path.as_path()));
+    manifest.files.extend(files);
+    manifest.files.sort_by(|a, b| a.path.cmp(&b.path));
+    manifest.save(destination)?;
+
+    exit::check_failures(failed.into_inner(), total)
+}
+
+/// Picks the file each asset is saved as in each of its directories, before any download
+/// starts so that assets of the same name never get the same path. Assets left without a
+/// path by `Collision::Skip` are reported and left out.
+async fn plan(
+    assets: Vec<(Asset, Vec<PathBuf>)>,
+    destination: &Path,
+    collision: Collision,
+    manifest: &DownloadManifest,
+) -> Vec<(Asset, Vec<Target>)> {
+    let recorded: HashMap<PathBuf, &str> = manifest
+        .files
+        .iter()
+        .map(|f| (destination.join(&f.path), f.asset_id.as_str()))
+        .collect();
+    let mut claimed = HashSet::new();
+    let mut planned = Vec::new();
+    let mut skipped = 0;
+    for (asset, directories) in assets {
+        let mut targets = Vec::new();
+        for directory in &directories {
+            match target(&asset, directory, collision, &recorded, &claimed).await {
+                Some(target) => {
+                    claimed.insert(target.path.clone());
+                    targets.push(target);
+                }
+                None => {
+                    progress::note(format!(
+                        "Skipping {}: {:?} holds another file of that name.",
+                        asset.id,
+                        directory.join(file_name(&asset))
+                    ));
+                    skipped += 1;
+                }
+            }
+        }
+        if !targets.is_empty() {
+            planned.push((asset, targets));
+        }
+    }
+    if skipped > 0 {
+        progress::note(format!(
+            "Skipped {} files whose names were taken (--on-collision skip).",
+            skipped
+        ));
+    }
+    planned
+}
+
+/// Pairs each asset with the directories it is saved in: one, or one per album.
+async fn arrange(
+    client: &ImmichClient,
+    assets: Vec<Asset>,
+    selection: &Selection,
+    destination: &Path,
+    layout: Layout,
+) -> Result<Vec<(Asset, Vec<PathBuf>)>> {
+    let album_names: HashMap<String, Vec<String>> = match (layout, &selection.album) {
+        // Only the selected album matters when there is one.
+        (Layout::Album, Some(name)) => {
+            let album = albums::fetch(client, name).await?;
+            assets
+                .iter()
+                .map(|a| (a.id.clone(), vec![album.album_name.clone()]))
+                .collect()
+        }
+        (Layout::Album, None) => {
+            let mut names: HashMap<String, Vec<String>> = HashMap::new();
+            for album in client
+                .list_albums()
+                .await
+                .context("Failed to list albums")?
+            {
+                let album = client
+                    .get_album(&album.id)
+                    .await
+                    .with_context(|| format!("Failed to fetch album '{}'", album.album_name))?;
+                for asset in album.assets {
+                    names
+                        .entry(asset.id)
+                        .or_default()
+                        .push(album.album_name.clone());
+                }
+            }
+            names
+        }
+        _ => HashMap::new(),
+    };
+    Ok(assets
+        .into_iter()
+        .map(|asset| {
+            let directories = match layout {
+                Layout::Flat => vec![destination.to_path_buf()],
+                Layout::Date => {
+                    let taken = asset.file_created_at.with_timezone(&Local);
+                    vec![
+                        destination
+                            .join(format!("{:04}", taken.year()))
+                            .join(format!("{:02}", taken.month())),
+                    ]
+                }
+                Layout::Album => match album_names.get(&asset.id) {
+                    Some(names) => names
+                        .iter()
+                        .map(|name| destination.join(directory_name(name)))
+                        .collect(),
+                    None => vec![destination.join(NO_ALBUM)],
+                },
+            };
+            (asset, directories)
+        })
+        .collect())
+}
+
+/// An album name usable as a directory name on every platform.
+fn directory_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Server-generated image of an asset.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ThumbnailSize {
+    /// The small image shown in the timeline.
+    Thumbnail,
+    /// The large image shown when viewing the asset.
+    Preview,
+}
+
+impl ThumbnailSize {
+    fn as_str(self) -> &'static str {
+        match self {
+            ThumbnailSize::Thumbnail => "thumbnail",
+            ThumbnailSize::Preview => "preview",
+        }
+    }
+}
+
+/// Saves the server-generated thumbnails or previews of the selected assets into
+/// `destination` as `<name>-<size>.<format>`, printing the path of each, or writes the
+/// image of a single asset to stdout when `destination` is `-`.
+pub async fn thumbnails(
+    client: &ImmichClient,
+    selection: &Selection,
+    destination: &Path,
+    size: ThumbnailSize,
+) -> Result<()> {
+    let assets = assets::select(client, selection).await?;
+    if assets.is_empty() {
+        bail!("No matching assets");
+    }
+    if destination == Path::new("-") {
+        let [asset] = &assets[..] else {
+            bail!(
+                "{} assets match, but only one image can be written to stdout",
+                assets.len()
+            );
+        };
+        let image = client
+            .download_thumbnail(&asset.id, size.as_str())
+            .await?
+            .bytes()
+            .await?;
+        std::io::stdout().write_all(&image)?;
+        return Ok(());
+    }
+
+    tokio::fs::create_dir_all(destination)
+        .await
+        .with_context(|| format!("Failed to create {:?}", destination))?;
+    for asset in &assets {
+        let response = client
+            .download_thumbnail(&asset.id, size.as_str())
+            .await
+            .with_context(|| format!("Failed to fetch the {} of {}", size.as_str(), asset.id))?;
+        let extension = match response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+        {
+            Some("image/webp") => "webp",
+            Some("image/png") => "png",
+            _ => "jpg",
+        };
+        let stem = Path::new(&asset.original_file_name)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| asset.id.clone());
+        let path = destination.join(format!("{}-{}.{}", stem, size.as_str(), extension));
+        tokio::fs::write(&path, response.bytes().await?)
+            .await
+            .with_context(|| format!("Failed to write {:?}", path))?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// Checks that the volume holding `destination` has room for `needed` more bytes. When it
+/// doesn't, asks whether to start anyway, or refuses without a terminal to ask on.
+pub fn ensure_space(destination: &Path, needed: u64) -> Result<()> {
+    let Some(available) = free_space(destination) else {
+        return Ok(());
+    };
+    if needed <= available {
+        return Ok(());
+    }
+    let message = format!(
+        "{} to download, but only {} free on the volume holding {:?}",
+        HumanBytes(needed),
+        HumanBytes(available),
+        destination
+    );
+    if !std::io::stdin().is_terminal() {
+        bail!("{}", message);
+    }
+    eprint!("{}. Start anyway? [y/N] ", message);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        bail!("Not enough space for the download");
+    }
+    Ok(())
+}
+
+/// Bytes available to unprivileged users on the volume holding `path`, if known.
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stats` is a valid statvfs to fill in.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    #[allow(clippy::useless_conversion)]
+    let (blocks, block_size) = (u64::from(stats.f_bavail), u64::from(stats.f_frsize));
+    Some(blocks.saturating_mul(block_size))
+}
+
+#[cfg(not(unix))]
+fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Downloads one asset to the first of its targets unless it is already there, links or
+/// copies it to the others, and writes its sidecars.
+async fn download_asset(
+    client: &ImmichClient,
+    asset: &Asset,
+    targets: &[Target],
+    sidecar: Option<SidecarFormat>,
+) -> Result<()> {
+    let mut first: Option<&Path> = None;
+    for target in targets {
+        let path = &target.path;
+        if let Some(directory) = path.parent() {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .with_context(|| format!("Failed to create {:?}", directory))?;
+        }
+        if !target.downloaded {
+            match first {
+                Some(original) => link_or_copy(original, path).await?,
+                None => save_original(client, asset, path).await?,
+            }
+        }
+        if let Some(format) = sidecar {
+            sidecar::write(client, &asset.id, path, format).await?;
+        }
+        first.get_or_insert(path);
+    }
+    Ok(())
+}
+
+/// Hard-links `original` to `path` so that a file in several albums takes its space once,
+/// or copies it where links are not possible (e.g. across volumes).
+async fn link_or_copy(original: &Path, path: &Path) -> Result<()> {
+    if tokio::fs::hard_link(original, path).await.is_err() {
+        tokio::fs::copy(original, path)
+            .await
+            .with_context(|| format!("Failed to copy {:?} to {:?}", original, path))?;
+    }
+    Ok(())
+}
+
+/// Streams an asset's original to `path`, through a `.partial` file so an interrupted
+/// download never looks complete. A `.partial` file left by an earlier attempt is resumed
+/// with a range request, and a connection dropped midway is resumed a few times before
+/// giving up, so large videos don't start over.
+pub async fn save_original(client: &ImmichClient, asset: &Asset, path: &Path) -> Result<()> {
+    let mut partial = path.to_path_buf().into_os_string();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match fetch_into(client, asset, &partial).await {
+            // A resumed download of an asset that changed on the server since the partial
+            // file was written can come out at the wrong size; it is done again from the
+            // start.
+            Ok(true) if !is_downloaded(&partial, asset.file_size()).await => {
+                progress::note(format!(
+                    "Download of {} does not match the server's size, starting over...",
+                    asset.original_file_name
+                ));
+                tokio::fs::remove_file(&partial).await?;
+            }
+            Ok(_) => break,
+            // Only dropped connections are worth another attempt, not server errors.
+            Err(e) if attempt < RESUME_ATTEMPTS && e.downcast_ref::<reqwest::Error>().is_some() => {
+                progress::note(format!(
+                    "Download of {} interrupted ({:#}), resuming...",
+                    asset.original_file_name, e
+                ));
+                tokio::time::sleep(RESUME_DELAY).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    // Keep the capture date visible to file browsers and other tools.
+    let file = std::fs::File::options().append(true).open(&partial)?;
+    let _ = file.set_modified(asset.file_created_at.into());
+    drop(file);
+    tokio::fs::rename(&partial, path).await?;
+    Ok(())
+}
+
+/// Appends the rest of an asset's original to `partial`, or writes it from the start when
+/// the server ignores the range or can't satisfy it. Returns whether it appended.
+async fn fetch_into(client: &ImmichClient, asset: &Asset, partial: &Path) -> Result<bool> {
+    let mut offset = tokio::fs::metadata(partial)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+    match asset.file_size() {
+        Some(size) if offset == size && offset > 0 => return Ok(false),
+        // Left from an earlier version of the asset: there is nothing to resume.
+        Some(size) if offset > size => offset = 0,
+        _ => {}
+    }
+    let mut response = client.download_original_from(&asset.id, offset).await?;
+    if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        response = client.download_original_from(&asset.id, 0).await?;
+    }
+    let resumed = offset > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut file = tokio::fs::File::options()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(partial)
+        .await
+        .with_context(|| format!("Failed to create {:?}", partial))?;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(resumed)
+}
+
+/// Whether `path` holds a complete download of an asset of `size` bytes (any size when
+/// the server didn't report one).
+pub async fn is_downloaded(path: &Path, size: Option<u64>) -> bool {
+    tokio::fs::metadata(path)
+        .await
+        .is_ok_and(|existing| size.is_none_or(|size| size == existing.len()))
+}
+
+/// The original file name of an asset, without any directories.
+fn file_name(asset: &Asset) -> PathBuf {
+    Path::new(&asset.original_file_name)
+        .file_name()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(&asset.id))
+}
+
+/// Where to save an asset in `directory`: where it was saved before, else under its own
+/// name, else under the name `collision` gives it. `None` when `collision` is to skip.
+async fn target(
+    asset: &Asset,
+    directory: &Path,
+    collision: Collision,
+    recorded: &HashMap<PathBuf, &str>,
+    claimed: &HashSet<PathBuf>,
+) -> Option<Target> {
+    let name = file_name(asset);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = name
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let short_id = &asset.id[..asset.id.len().min(8)];
+    let suffixed = directory.join(format!("{}-{}{}", stem, short_id, extension));
+    let taken = asset.file_created_at.with_timezone(&Local);
+    let dated = directory.join(format!(
+        "{}-{}",
+        taken.format("%Y%m%d-%H%M%S"),
+        name.display()
+    ));
+
+    let mut candidates = vec![directory.join(&name)];
+    match collision {
+        Collision::Suffix => candidates.push(suffixed.clone()),
+        Collision::Date => candidates.extend([dated, suffixed.clone()]),
+        Collision::Skip => {}
+    }
+    // A file recorded for this asset wins, whatever its name.
+    if let Some(path) = recorded
+        .iter()
+        .filter(|(path, id)| **id == asset.id && path.parent() == Some(directory))
+        .map(|(path, _)| path)
+        .find(|path| !claimed.contains(*path))
+        && is_downloaded(path, asset.file_size()).await
+    {
+        return Some(Target {
+            path: path.clone(),
+            downloaded: true,
+        });
+    }
+    for path in candidates {
+        let other_asset = recorded.get(&path).is_some_and(|id| *id != asset.id);
+        if claimed.contains(&path) || other_asset {
+            continue;
+        }
+        if is_downloaded(&path, asset.file_size()).await {
+            return Some(Target {
+                path,
+                downloaded: true,
+            });
+        }
+        if tokio::fs::metadata(&path).await.is_err() {
+            return Some(Target {
+                path,
+                downloaded: false,
+            });
+        }
+    }
+    // Every name holds another file; the suffixed one can only be a stale copy of this asset.
+    (collision != Collision::Skip && !claimed.contains(&suffixed)).then_some(Target {
+        path: suffixed,
+        downloaded: false,
+    })
+}
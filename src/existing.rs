@@ -0,0 +1,236 @@
+use crate::api::{Asset, ImmichClient};
+use crate::metadata;
+use crate::progress;
+use crate::upload::{self, UploadItem, UploadOptions};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use futures::StreamExt;
+use serde_json::json;
+use std::io::{IsTerminal, Write};
+
+/// What to do with a file the server already has (same checksum) with another capture
+/// date or other metadata. File names are left out, as the server can't rename assets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Leave the existing asset as it is.
+    #[default]
+    Skip,
+    /// Set the existing asset's capture date, description, favorite flag and tags from
+    /// the local file.
+    Update,
+    /// Ask for each such file.
+    Ask,
+}
+
+/// Assets fetched at the same time to compare them with local files.
+const FETCHES: usize = 8;
+
+/// How a local file differs from the asset with its checksum.
+pub struct Difference<'a> {
+    item: &'a UploadItem,
+    asset: Asset,
+    /// The local capture date, when it differs from the asset's.
+    date: Option<DateTime<Utc>>,
+    /// The local description, when it differs from the asset's.
+    description: Option<String>,
+    /// The local favorite flag, when it differs from the asset's.
+    favorite: Option<bool>,
+    /// Local tags the asset doesn't have.
+    tags: Vec<String>,
+}
+
+/// Goes through the files the server already has, given with the IDs of their assets,
+/// and finds those whose asset has a different capture date or metadata, when the
+/// duplicate policy of `options` may change them.
+pub async fn differences<'a>(
+    client: &ImmichClient,
+    existing: &'a [(UploadItem, Option<String>)],
+    options: &UploadOptions,
+) -> Result<Vec<Difference<'a>>> {
+    let policy = options.on_duplicate;
+    if policy == DuplicatePolicy::Skip {
+        return Ok(Vec::new());
+    }
+    if policy == DuplicatePolicy::Ask && !std::io::stdin().is_terminal() {
+        bail!("--on-duplicate ask needs a terminal to ask on; use skip or update in scripts");
+    }
+    // Assets that can't be fetched are left as they are.
+    let differences: Vec<Difference> = futures::stream::iter(existing)
+        .map(|(item, asset_id)| async move {
+            let asset = client.get_asset(asset_id.as_deref()?).await.ok()?;
+            difference(item, asset, options)
+        })
+        .buffered(FETCHES)
+        .filter_map(std::future::ready)
+        .collect()
+        .await;
+    if !differences.is_empty() {
+        progress::note(format!(
+            "{} files already on the server have a different date or metadata there.",
+            differences.len()
+        ));
+    }
+    Ok(differences)
+}
+
+/// Applies `policy` to the assets found by `differences`.
+pub async fn resolve(
+    client: &ImmichClient,
+    differences: &[Difference<'_>],
+    policy: DuplicatePolicy,
+) -> Result<()> {
+    let mut answer_all = None;
+    let mut updated = 0;
+    for difference in differences {
+        let update = match (policy, answer_all) {
+            (DuplicatePolicy::Update, _) => true,
+            (_, Some(all)) => all,
+            _ => match ask(difference)? {
+                Answer::Once(update) => update,
+                Answer::All(update) => {
+                    answer_all = Some(update);
+                    update
+                }
+            },
+        };
+        if update {
+            apply(client, difference)
+                .await
+                .with_context(|| format!("Failed to update asset {}", difference.asset.id))?;
+            updated += 1;
+        }
+    }
+    if updated > 0 {
+        progress::note(format!("Updated {} existing assets.", updated));
+    }
+    Ok(())
+}
+
+/// How the asset differs from the local file, if it does.
+fn difference<'a>(
+    item: &'a UploadItem,
+    asset: Asset,
+    options: &UploadOptions,
+) -> Option<Difference<'a>> {
+    let date = local_capture_date(item).filter(|local| {
+        // Compared to the second, as EXIF dates have no fractions.
+        (*local - asset.file_created_at).num_seconds().abs() > 1
+    });
+    let carried = upload::carried_metadata(item, options);
+    let description = carried.description.filter(|description| {
+        asset
+            .exif_info
+            .as_ref()
+            .and_then(|e| e.description.as_ref())
+            != Some(description)
+    });
+    let favorite = carried
+        .favorite
+        .filter(|favorite| *favorite != asset.is_favorite);
+    let tags: Vec<String> = carried
+        .tags
+        .into_iter()
+        .filter(|tag| !asset.tags.iter().any(|t| &t.value == tag || &t.name == tag))
+        .collect();
+    let differs = date.is_some() || description.is_some() || favorite.is_some() || !tags.is_empty();
+    differs.then_some(Difference {
+        item,
+        asset,
+        date,
+        description,
+        favorite,
+        tags,
+    })
+}
+
+/// The capture date of a local file: the one given for the item, or else its EXIF date
+/// in local time. File system dates are left out, as they rarely survive copies.
+fn local_capture_date(item: &UploadItem) -> Option<DateTime<Utc>> {
+    item.taken_at.or_else(|| {
+        let taken = metadata::exif_capture_date(&item.path)?;
+        Local
+            .from_local_datetime(&taken)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+    })
+}
+
+enum Answer {
+    Once(bool),
+    All(bool),
+}
+
+/// Asks whether to update one asset; true means update.
+fn ask(difference: &Difference) -> Result<Answer> {
+    let item = difference.item;
+    let asset = &difference.asset;
+    eprintln!(
+        "{} is already on the server as {} ({}, taken {}).",
+        item.path.display(),
+        asset.original_file_name,
+        asset.id,
+        asset
+            .file_created_at
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+    );
+    if let Some(date) = difference.date {
+        eprintln!(
+            "  The local file was taken {}.",
+            date.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        );
+    }
+    if let Some(description) = &difference.description {
+        eprintln!("  The local file's description is \"{}\".", description);
+    }
+    if let Some(favorite) = difference.favorite {
+        let flag = if favorite { "is" } else { "is not" };
+        eprintln!("  The local file {} a favorite.", flag);
+    }
+    if !difference.tags.is_empty() {
+        eprintln!("  The local file is tagged {}.", difference.tags.join(", "));
+    }
+    loop {
+        eprint!("[s]kip, [u]pdate the asset, [S]kip all, [U]pdate all? ");
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer)? == 0 {
+            bail!("No answer given");
+        }
+        match answer.trim() {
+            "s" | "" => return Ok(Answer::Once(false)),
+            "u" => return Ok(Answer::Once(true)),
+            "S" => return Ok(Answer::All(false)),
+            "U" => return Ok(Answer::All(true)),
+            _ => {}
+        }
+    }
+}
+
+/// Sets the capture date, description, favorite flag and tags of the local file on the
+/// existing asset.
+async fn apply(client: &ImmichClient, difference: &Difference<'_>) -> Result<()> {
+    let ids = [difference.asset.id.clone()];
+    let mut changes = serde_json::Map::new();
+    if let Some(date) = difference.date {
+        changes.insert("dateTimeOriginal".into(), json!(date.to_rfc3339()));
+    }
+    if let Some(favorite) = difference.favorite {
+        changes.insert("isFavorite".into(), json!(favorite));
+    }
+    if !changes.is_empty() {
+        client.update_assets(&ids, &changes.into()).await?;
+    }
+    // Only the single-asset update sets the description on every server version.
+    if let Some(description) = &difference.description {
+        client
+            .update_asset_description(&difference.asset.id, description)
+            .await?;
+    }
+    if !difference.tags.is_empty() {
+        let tags = client.upsert_tags(&difference.tags).await?;
+        let tag_ids: Vec<String> = tags.into_iter().map(|t| t.id).collect();
+        client.tag_assets(&tag_ids, &ids).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+use std::process::ExitCode;
+
+/// Category of a failed run, determining the process exit code.
+/// Attached to errors as context so `main` can find it in the error chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitKind {
+    /// The configuration file or selected user is missing or invalid.
+    Config,
+    /// The server rejected the API key or shared link key.
+    Auth,
+    /// The server could not be reached.
+    Connection,
+    /// Some uploads failed.
+    PartialFailure,
+    /// Every upload failed.
+    AllFailed,
+    /// The run was stopped with Ctrl-C or SIGTERM.
+    Interrupted,
+}
+
+impl ExitKind {
+    /// Exit code reported for this kind of failure. 1 is used for any other error and
+    /// 2 for invalid command-line usage.
+    pub fn code(self) -> u8 {
+        match self {
+            ExitKind::Config => 3,
+            ExitKind::Auth => 4,
+            ExitKind::Connection => 5,
+            ExitKind::PartialFailure => 6,
+            ExitKind::AllFailed => 7,
+            ExitKind::Interrupted => 130,
+        }
+    }
+
+    /// Kind for a run in which `failed` of `total` items failed, if any did.
+    pub fn for_failures(failed: usize, total: usize) -> Option<Self> {
+        match failed {
+            0 => None,
+            _ if failed >= total => Some(ExitKind::AllFailed),
+            _ => Some(ExitKind::PartialFailure),
+        }
+    }
+}
+
+impl fmt::Display for ExitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExitKind::Config => "Configuration error",
+            ExitKind::Auth => "Authentication failed",
+            ExitKind::Connection => "Connection failed",
+            ExitKind::PartialFailure => "Some uploads failed",
+            ExitKind::AllFailed => "All uploads failed",
+            ExitKind::Interrupted => "Interrupted",
+        })
+    }
+}
+
+impl std::error::Error for ExitKind {}
+
+/// Exit code for an error, from the `ExitKind` it carries as context or cause.
+pub fn code(error: &anyhow::Error) -> ExitCode {
+    error
+        .downcast_ref::<ExitKind>()
+        .or_else(|| error.chain().find_map(|e| e.downcast_ref::<ExitKind>()))
+        .map_or(ExitCode::FAILURE, |kind| ExitCode::from(kind.code()))
+}
+
+/// Fails with the matching exit kind when any of `total` items failed.
+pub fn check_failures(failed: usize, total: usize) -> anyhow::Result<()> {
+    match ExitKind::for_failures(failed, total) {
+        Some(kind) => {
+            Err(anyhow::Error::new(kind).context(format!("{} of {} failed", failed, total)))
+        }
+        None => Ok(()),
+    }
+}
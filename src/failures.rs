@@ -0,0 +1,288 @@
+use crate::config::Config;
+use crate::upload::UploadItem;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Uploads that failed in earlier runs, kept in ~/.immich/failed.json until they succeed.
+pub struct FailureStore {
+    path: PathBuf,
+    failures: BTreeMap<PathBuf, Failure>,
+    changed: bool,
+}
+
+/// A failed upload with what is needed to attempt it again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Failure {
+    /// Broad cause of the failure.
+    pub class: ErrorClass,
+    /// Error message of the last attempt.
+    pub error: String,
+    /// The item as it was queued, including its albums and metadata.
+    pub item: UploadItem,
+}
+
+/// Broad cause of a failed upload.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorClass {
+    /// The server could not be reached or the connection broke.
+    Network,
+    /// The server refused the API key (401/403).
+    Auth,
+    /// The server failed with a 5xx status.
+    Server,
+    /// The server rejected the file with a 4xx status.
+    Rejected,
+    /// The server doesn't accept this kind of file.
+    Unsupported,
+    /// The local file could not be read.
+    File,
+    /// Anything else.
+    Other,
+}
+
+impl ErrorClass {
+    /// Classifies an upload error.
+    pub fn of(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                return match e.status() {
+                    Some(status) => Self::of_status(status.as_u16(), ""),
+                    None => ErrorClass::Network,
+                };
+            }
+            if cause.is::<std::io::Error>() {
+                return ErrorClass::File;
+            }
+        }
+        // Errors from `api::check` only carry the status in their message, which may be
+        // wrapped in context.
+        error
+            .chain()
+            .find_map(|cause| {
+                let message = cause.to_string();
+                let rest = message.strip_prefix("Server returned error ")?;
+                Some(
+                    rest.get(..3)
+                        .and_then(|code| code.parse().ok())
+                        .map_or(ErrorClass::Rejected, |code| Self::of_status(code, rest)),
+                )
+            })
+            .unwrap_or(ErrorClass::Other)
+    }
+
+    fn of_status(code: u16, body: &str) -> Self {
+        match code {
+            401 | 403 => ErrorClass::Auth,
+            415 => ErrorClass::Unsupported,
+            // Immich answers 400 for file types it doesn't handle.
+            400 if body.to_lowercase().contains("unsupported file type") => ErrorClass::Unsupported,
+            500.. => ErrorClass::Server,
+            _ => ErrorClass::Rejected,
+        }
+    }
+
+    /// Heading of the class in the failure summary.
+    fn description(self) -> &'static str {
+        match self {
+            ErrorClass::Network => "Network error",
+            ErrorClass::Auth => "Not authorized",
+            ErrorClass::Server => "Server error",
+            ErrorClass::Rejected => "Rejected by server",
+            ErrorClass::Unsupported => "Unsupported file type",
+            ErrorClass::File => "Local file error",
+            ErrorClass::Other => "Other error",
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorClass::Network => "network",
+            ErrorClass::Auth => "auth",
+            ErrorClass::Server => "server",
+            ErrorClass::Rejected => "rejected",
+            ErrorClass::Unsupported => "unsupported",
+            ErrorClass::File => "file",
+            ErrorClass::Other => "other",
+        })
+    }
+}
+
+/// Number of example files shown per class in the failure summary.
+const EXAMPLES: usize = 3;
+
+/// Summarizes failed files by cause: a line per class with its number of files, the
+/// first error message and a few of the files.
+pub fn summary(failures: &[(PathBuf, ErrorClass, String)]) -> String {
+    let mut classes: BTreeMap<ErrorClass, Vec<&(PathBuf, ErrorClass, String)>> = BTreeMap::new();
+    for failure in failures {
+        classes.entry(failure.1).or_default().push(failure);
+    }
+    let mut classes: Vec<_> = classes.into_values().collect();
+    for failures in &mut classes {
+        failures.sort_by_key(|(path, _, _)| path);
+    }
+    classes.sort_by_key(|failures| std::cmp::Reverse(failures.len()));
+    let mut lines = vec!["Failed uploads by cause:".to_string()];
+    for failures in classes {
+        let (_, class, error) = failures[0];
+        let error: String = error
+            .lines()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .take(100)
+            .collect();
+        lines.push(format!(
+            "  {:<22} {:>6}  {}",
+            class.description(),
+            failures.len(),
+            error
+        ));
+        for (path, _, _) in failures.iter().take(EXAMPLES) {
+            lines.push(format!("  {:<22} {:>6}  {}", "", "", path.display()));
+        }
+        if failures.len() > EXAMPLES {
+            lines.push(format!(
+                "  {:<22} {:>6}  ... and {} more",
+                "",
+                "",
+                failures.len() - EXAMPLES
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+impl FailureStore {
+    /// Loads the failures of earlier runs, or starts empty.
+    pub fn load() -> Result<Self> {
+        let path = Config::state_dir()?.join("failed.json");
+        let failures = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(FailureStore {
+            path,
+            failures,
+            changed: false,
+        })
+    }
+
+    /// Records a failed upload, replacing an earlier failure of the same file.
+    pub fn record(&mut self, item: &UploadItem, error: &anyhow::Error) {
+        let mut item = item.clone();
+        // Absolute paths keep the entry usable from another working directory.
+        item.path = item.path.canonicalize().unwrap_or(item.path);
+        self.failures.insert(
+            item.path.clone(),
+            Failure {
+                class: ErrorClass::of(error),
+                error: format!("{:#}", error),
+                item,
+            },
+        );
+        self.changed = true;
+    }
+
+    /// Forgets a file once it has been uploaded.
+    pub fn clear(&mut self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.failures.remove(&path).is_some() {
+            self.changed = true;
+        }
+    }
+
+    /// Failures of files below `directory`, or all of them.
+    pub fn failures(&self, directory: Option<&Path>) -> Vec<&Failure> {
+        let directory = directory.map(|d| d.canonicalize().unwrap_or_else(|_| d.to_path_buf()));
+        self.failures
+            .values()
+            .filter(|f| {
+                directory
+                    .as_ref()
+                    .is_none_or(|d| f.item.path.starts_with(d))
+            })
+            .collect()
+    }
+
+    /// Writes the failures to disk if any were recorded or cleared.
+    pub fn save(&self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.failures)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, anyhow};
+
+    fn wrapped(message: &str) -> anyhow::Error {
+        Err::<(), _>(anyhow!(message.to_string()))
+            .context("Failed to upload IMG_0001.JPG")
+            .context("Upload of 1 file failed")
+            .unwrap_err()
+    }
+
+    #[test]
+    fn classifies_status_messages() {
+        let cases = [
+            (
+                "Server returned error 401 Unauthorized: Invalid API key",
+                ErrorClass::Auth,
+            ),
+            (
+                "Server returned error 403 Forbidden: Missing permission",
+                ErrorClass::Auth,
+            ),
+            (
+                "Server returned error 503 Service Unavailable",
+                ErrorClass::Server,
+            ),
+            (
+                "Server returned error 415 Unsupported Media Type",
+                ErrorClass::Unsupported,
+            ),
+            (
+                "Server returned error 400 Bad Request: Unsupported file type",
+                ErrorClass::Unsupported,
+            ),
+            (
+                "Server returned error 400 Bad Request: Invalid date",
+                ErrorClass::Rejected,
+            ),
+            ("Server returned error (no status)", ErrorClass::Rejected),
+            ("Something else went wrong", ErrorClass::Other),
+        ];
+        for (message, expected) in cases {
+            assert_eq!(
+                ErrorClass::of(&anyhow!(message.to_string())),
+                expected,
+                "{}",
+                message
+            );
+            assert_eq!(ErrorClass::of(&wrapped(message)), expected, "{}", message);
+        }
+    }
+
+    #[test]
+    fn classifies_local_file_errors() {
+        let error = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+            .context("Failed to read IMG_0001.JPG")
+            .unwrap_err();
+        assert_eq!(ErrorClass::of(&error), ErrorClass::File);
+    }
+}
@@ -0,0 +1,343 @@
+use crate::assets::glob_match;
+use crate::config::DateRule;
+use anyhow::{Result, bail};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use regex::{Captures, Regex};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// A file name pattern carrying the capture date, with named groups `year`, `month`, `day`
+/// and optionally `hour`, `minute`, `second` and `ampm`.
+struct Pattern {
+    regex: &'static str,
+    /// Whether the time in the name is UTC rather than local time.
+    utc: bool,
+}
+
+const PATTERNS: &[Pattern] = &[
+    // WhatsApp strips EXIF, but names media after the day it was sent or received.
+    Pattern {
+        regex: r"^(?:IMG|VID|AUD|PTT|STK)-(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})-WA\d+",
+        utc: false,
+    },
+    // Google Pixel: PXL_20210714_153012345.jpg, in UTC.
+    Pattern {
+        regex: r"^PXL_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})_(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+        utc: true,
+    },
+    // macOS: "Screenshot 2019-07-14 at 15.30.12", "Screen Shot 2019-07-14 at 3.30.12 PM".
+    Pattern {
+        regex: r"^Screen ?[Ss]hot (?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2}) at (?P<hour>\d{1,2})\.(?P<minute>\d{2})\.(?P<second>\d{2})(?:\s(?P<ampm>[AP]M))?",
+        utc: false,
+    },
+    // Android screenshots: Screenshot_20190714-153012, Screenshot_2019-07-14-15-30-12.
+    Pattern {
+        regex: r"^Screenshot_(?P<year>\d{4})-?(?P<month>\d{2})-?(?P<day>\d{2})-(?P<hour>\d{2})-?(?P<minute>\d{2})-?(?P<second>\d{2})",
+        utc: false,
+    },
+    // Cameras and phone apps: 20190714_153012.jpg, IMG_20190714_153012.jpg,
+    // VID_20190714_153012.mp4, MVIMG_20190714_153012.jpg, signal-2019-07-14-153012.jpg.
+    Pattern {
+        regex: r"(?:^|\D)(?P<year>(?:19|20)\d{2})-?(?P<month>\d{2})-?(?P<day>\d{2})[_-](?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})(?:\d{3})?(?:\D|$)",
+        utc: false,
+    },
+    // Dropbox camera uploads and exports: "2019-07-14 15.30.12.jpg".
+    Pattern {
+        regex: r"^(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2}) (?P<hour>\d{2})\.(?P<minute>\d{2})\.(?P<second>\d{2})",
+        utc: false,
+    },
+];
+
+static COMPILED: OnceLock<Vec<(Regex, bool)>> = OnceLock::new();
+
+/// Rules from the config file, with their compiled patterns.
+static USER_RULES: OnceLock<Vec<(DateRule, Regex)>> = OnceLock::new();
+
+/// A date found in a file name.
+struct NamedDate {
+    time: NaiveDateTime,
+    utc: bool,
+    /// Whether the name includes the time of day, or only the date.
+    has_time: bool,
+}
+
+/// The capture date to report for a file whose source didn't give one. Immich prefers the
+/// EXIF date anyway, so this matters for files without one, such as WhatsApp media and
+/// exported or edited copies: the date in the file name wins over the filesystem time
+/// unless they agree, as copies and downloads reset the latter.
+pub fn capture_date(path: &Path, fs_time: DateTime<Utc>) -> DateTime<Utc> {
+    let Some(named) = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|name| date_from_name(path, name))
+    else {
+        return fs_time;
+    };
+    let start = if named.utc {
+        named.time.and_utc().with_timezone(&Local)
+    } else {
+        match Local.from_local_datetime(&named.time).earliest() {
+            Some(start) => start,
+            None => return fs_time,
+        }
+    };
+    if !named.has_time && fs_time >= start && fs_time < start + Duration::days(1) {
+        return fs_time;
+    }
+    start.with_timezone(&Utc)
+}
+
+/// Compiles the date rules from the config file, which are tried before the built-in
+/// patterns.
+pub fn set_rules(rules: &[DateRule]) -> Result<()> {
+    let mut compiled = Vec::new();
+    for rule in rules {
+        let regex = Regex::new(&rule.pattern)?;
+        for group in ["year", "month", "day"] {
+            if !regex.capture_names().flatten().any(|name| name == group) {
+                bail!(
+                    "Date rule '{}' has no capture group named '{}'",
+                    rule.pattern,
+                    group
+                );
+            }
+        }
+        compiled.push((rule.clone(), regex));
+    }
+    let _ = USER_RULES.set(compiled);
+    Ok(())
+}
+
+fn date_from_name(path: &Path, name: &str) -> Option<NamedDate> {
+    let full_path = path.to_string_lossy();
+    let user_date = USER_RULES
+        .get()
+        .into_iter()
+        .flatten()
+        .find_map(|(rule, regex)| {
+            let applies = rule
+                .path
+                .as_ref()
+                .is_none_or(|glob| glob_match(glob.as_bytes(), full_path.as_bytes()));
+            if !applies {
+                return None;
+            }
+            date_from_captures(&regex.captures(name)?, rule.utc)
+        });
+    if user_date.is_some() {
+        return user_date;
+    }
+    let patterns = COMPILED.get_or_init(|| {
+        PATTERNS
+            .iter()
+            .map(|p| (Regex::new(p.regex).expect("built-in date pattern"), p.utc))
+            .collect()
+    });
+    patterns
+        .iter()
+        .find_map(|(regex, utc)| date_from_captures(&regex.captures(name)?, *utc))
+}
+
+/// Builds the date from a match, rejecting impossible dates and ones before digital
+/// cameras or after today.
+fn date_from_captures(captures: &Captures, utc: bool) -> Option<NamedDate> {
+    let number = |group: &str| -> Option<u32> { captures.name(group)?.as_str().parse().ok() };
+    let date = NaiveDate::from_ymd_opt(
+        captures.name("year")?.as_str().parse().ok()?,
+        number("month")?,
+        number("day")?,
+    )?;
+    let has_time = captures.name("hour").is_some();
+    let mut hour = number("hour").unwrap_or(0);
+    match captures.name("ampm").map(|m| m.as_str()) {
+        Some("AM") if hour == 12 => hour = 0,
+        Some("PM") if hour < 12 => hour += 12,
+        _ => {}
+    }
+    let time = date.and_hms_opt(
+        hour,
+        number("minute").unwrap_or(0),
+        number("second").unwrap_or(0),
+    )?;
+    if date.year() < 1990 || date > Local::now().date_naive() {
+        return None;
+    }
+    Some(NamedDate {
+        time,
+        utc,
+        has_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str) -> Option<(String, bool, bool)> {
+        date_from_name(Path::new(name), name).map(|d| {
+            (
+                d.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                d.utc,
+                d.has_time,
+            )
+        })
+    }
+
+    #[test]
+    fn reads_dates_from_names() {
+        let cases = [
+            (
+                "IMG_20190714_153012.jpg",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+            (
+                "VID_20190714_153012.mp4",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+            (
+                "MVIMG_20190714_153012.jpg",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+            ("20190714_153012.jpg", "2019-07-14 15:30:12", false, true),
+            ("20190714_153012123.jpg", "2019-07-14 15:30:12", false, true),
+            (
+                "signal-2019-07-14-153012.jpg",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+            (
+                "IMG-20190714-WA0003.jpg",
+                "2019-07-14 00:00:00",
+                false,
+                false,
+            ),
+            (
+                "VID-20190714-WA0012.mp4",
+                "2019-07-14 00:00:00",
+                false,
+                false,
+            ),
+            (
+                "PXL_20210714_153012345.jpg",
+                "2021-07-14 15:30:12",
+                true,
+                true,
+            ),
+            (
+                "Screenshot 2019-07-14 at 15.30.12.png",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+            (
+                "Screen Shot 2019-07-14 at 3.30.12 PM.png",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+            (
+                "Screen Shot 2019-07-14 at 12.05.00 AM.png",
+                "2019-07-14 00:05:00",
+                false,
+                true,
+            ),
+            (
+                "Screen Shot 2019-07-14 at 12.05.00 PM.png",
+                "2019-07-14 12:05:00",
+                false,
+                true,
+            ),
+            (
+                "Screenshot_20190714-153012.png",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+            (
+                "Screenshot_2019-07-14-15-30-12.png",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+            (
+                "2019-07-14 15.30.12.jpg",
+                "2019-07-14 15:30:12",
+                false,
+                true,
+            ),
+        ];
+        for (name, time, utc, has_time) in cases {
+            assert_eq!(
+                named(name),
+                Some((time.to_string(), utc, has_time)),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_impossible_dates() {
+        for name in [
+            "IMG_20191314_153012.jpg",
+            "IMG_20190001_153012.jpg",
+            "IMG_20190700_153012.jpg",
+            "IMG_20190732_153012.jpg",
+            "IMG_20190230_153012.jpg",
+            "IMG-20191301-WA0003.jpg",
+            "IMG-20190230-WA0003.jpg",
+            "PXL_20211314_153012345.jpg",
+            "Screenshot_20190714-256012.png",
+            "IMG_20190714_156012.jpg",
+            "Screen Shot 2019-02-30 at 3.30.12 PM.png",
+            "2019-13-14 15.30.12.jpg",
+        ] {
+            assert_eq!(named(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_dates_outside_digital_photography() {
+        let next_year = Local::now().year() + 1;
+        for name in [
+            "IMG_19890714_153012.jpg".to_string(),
+            format!("IMG_{}0714_153012.jpg", next_year),
+            format!("IMG-{}0101-WA0001.jpg", next_year),
+        ] {
+            assert_eq!(named(&name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn leaves_names_without_dates_alone() {
+        for name in [
+            "IMG_1234.JPG",
+            "DSC01234.ARW",
+            "holiday.jpg",
+            "IMG_20190714.jpg",
+            "12345678_123456789012.jpg",
+        ] {
+            assert_eq!(named(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn keeps_the_file_time_within_a_named_day() {
+        let path = Path::new("IMG-20190714-WA0003.jpg");
+        let day = Local
+            .with_ymd_and_hms(2019, 7, 14, 0, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+        let same_day = day + Duration::hours(18);
+        assert_eq!(capture_date(path, same_day), same_day);
+        let copied_later = day + Duration::days(30);
+        assert_eq!(capture_date(path, copied_later), day);
+    }
+}
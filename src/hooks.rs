@@ -0,0 +1,202 @@
+use crate::config::Hooks;
+use crate::metadata::{self, ExifSummary};
+use crate::upload::UploadItem;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+/// A file offered to the `filter` hook, as one JSON line on its stdin.
+#[derive(Serialize)]
+struct Candidate<'a> {
+    path: &'a Path,
+    size: u64,
+    exif: Option<ExifSummary>,
+    albums: &'a [String],
+    tags: &'a [String],
+}
+
+/// The `filter` hook's answer for a file, one JSON line on its stdout. Fields left out
+/// leave the file as it was.
+#[derive(Deserialize)]
+struct Verdict {
+    #[serde(default = "keep_by_default")]
+    keep: bool,
+    albums: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+}
+
+fn keep_by_default() -> bool {
+    true
+}
+
+/// The shell hooks run with.
+fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Runs the hook `name` through the shell with `env` added to its environment. Its output
+/// is discarded unless it fails, when its stderr becomes the error.
+async fn run(name: &str, command: &str, env: &[(&str, String)]) -> Result<()> {
+    let output = shell(command)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} hook", name))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "{} hook '{}' failed with {}: {}",
+            name,
+            command,
+            output.status,
+            stderr.trim()
+        );
+    }
+    Ok(())
+}
+
+/// Runs the `pre_file` hook, if set, for a file about to be uploaded.
+pub async fn pre_file(hooks: &Hooks, path: &Path) -> Result<()> {
+    let Some(command) = &hooks.pre_file else {
+        return Ok(());
+    };
+    run(
+        "pre_file",
+        command,
+        &[("IMMICH_FILE", path.display().to_string())],
+    )
+    .await
+}
+
+/// Runs the `post_file` hook, if set, with the outcome of a file's upload: `uploaded`,
+/// `duplicate` (the server already had it) or `failed`.
+pub async fn post_file(hooks: &Hooks, path: &Path, result: &Result<Option<String>>) -> Result<()> {
+    let Some(command) = &hooks.post_file else {
+        return Ok(());
+    };
+    let outcome = match result {
+        Ok(Some(_)) => "uploaded",
+        Ok(None) => "duplicate",
+        Err(_) => "failed",
+    };
+    let mut env = vec![
+        ("IMMICH_FILE", path.display().to_string()),
+        ("IMMICH_RESULT", outcome.to_string()),
+    ];
+    match result {
+        Ok(Some(asset_id)) => env.push(("IMMICH_ASSET_ID", asset_id.clone())),
+        Ok(None) => {}
+        Err(e) => env.push(("IMMICH_ERROR", format!("{:#}", e))),
+    }
+    run("post_file", command, &env).await
+}
+
+/// Runs the `post_run` hook, if set, with the numbers of the run.
+pub async fn post_run(
+    hooks: &Hooks,
+    total: usize,
+    uploaded: usize,
+    failed: usize,
+    not_started: usize,
+) -> Result<()> {
+    let Some(command) = &hooks.post_run else {
+        return Ok(());
+    };
+    let env = [
+        ("IMMICH_TOTAL", total.to_string()),
+        ("IMMICH_UPLOADED", uploaded.to_string()),
+        ("IMMICH_FAILED", failed.to_string()),
+        ("IMMICH_NOT_STARTED", not_started.to_string()),
+    ];
+    run("post_run", command, &env).await
+}
+
+/// Runs the `filter` hook, if set, over the files to upload: it gets one JSON line per
+/// file and answers each with a line saying whether to keep it and which albums and tags
+/// to use instead. Returns the number of files it left out.
+pub async fn filter(hooks: &Hooks, items: &mut Vec<UploadItem>) -> Result<usize> {
+    let Some(command) = &hooks.filter else {
+        return Ok(0);
+    };
+    let mut input = Vec::new();
+    for item in items.iter() {
+        let candidate = Candidate {
+            path: &item.path,
+            size: std::fs::metadata(&item.path).map_or(0, |m| m.len()),
+            exif: metadata::exif_summary(&item.path),
+            albums: &item.albums,
+            tags: &item.tags,
+        };
+        serde_json::to_writer(&mut input, &candidate)?;
+        input.push(b'\n');
+    }
+
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run filter hook")?;
+    // Written from another task so a hook answering as it reads can't block on a full pipe.
+    let mut stdin = child.stdin.take().context("filter hook has no stdin")?;
+    let writer = tokio::spawn(async move {
+        let _ = stdin.write_all(&input).await;
+    });
+    let stdout = child.stdout.take().context("filter hook has no stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+    let mut verdicts = Vec::with_capacity(items.len());
+    while verdicts.len() < items.len() {
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        let verdict: Verdict = serde_json::from_str(&line).with_context(|| {
+            format!(
+                "filter hook gave an invalid answer for {:?}: {}",
+                items[verdicts.len()].path,
+                line
+            )
+        })?;
+        verdicts.push(verdict);
+    }
+    writer.await?;
+    let status = child.wait().await?;
+    if !status.success() {
+        bail!("filter hook '{}' failed with {}", command, status);
+    }
+    if verdicts.len() < items.len() {
+        bail!(
+            "filter hook answered for {} of {} files",
+            verdicts.len(),
+            items.len()
+        );
+    }
+
+    let before = items.len();
+    let mut verdicts = verdicts.into_iter();
+    items.retain_mut(|item| {
+        let verdict = verdicts.next().expect("one verdict per item");
+        if let Some(albums) = verdict.albums {
+            item.albums = albums;
+        }
+        if let Some(tags) = verdict.tags {
+            item.tags = tags;
+        }
+        verdict.keep
+    });
+    Ok(before - items.len())
+}
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use std::path::Path;
+
+/// Reads an Apple Photos library (.photoslibrary) database.
+pub mod apple_photos;
+/// Reads digiKam databases (digikam4.db).
+pub mod digikam;
+/// Reads Flickr account exports.
+pub mod flickr;
+/// Reads iCloud Photos exports (Apple's data download or icloudpd).
+pub mod icloud;
+/// Reads Lightroom Classic catalogs (.lrcat).
+pub mod lightroom;
+/// Reads Synology Photos / Moments shared folders.
+pub mod synology;
+
+/// Reads a CSV file into rows of fields, honouring double-quoted fields.
+/// Exports from photo services are small and simple enough not to need a full CSV parser.
+fn read_csv(path: &Path) -> Result<Vec<Vec<String>>> {
+    let content = std::fs::read_to_string(path)?;
+    let content = content.trim_start_matches('\u{feff}');
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => row.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Parses a timestamp stored as local time without offset, as most catalogs do.
+fn parse_local_date(value: &str, format: &str) -> Option<DateTime<Utc>> {
+    let naive = NaiveDateTime::parse_from_str(value, format).ok()?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+}
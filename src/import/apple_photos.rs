@@ -0,0 +1,155 @@
+use crate::upload::UploadItem;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Seconds between the Unix epoch and the Core Data epoch (2001-01-01 UTC).
+const CORE_DATA_EPOCH: i64 = 978_307_200;
+
+/// `ZKIND` of user-created albums in `ZGENERICALBUM`.
+const USER_ALBUM_KIND: i64 = 2;
+
+/// Reads originals, albums, favorites, captions and adjusted dates from a Photos library.
+/// Assets whose original is only stored in iCloud are skipped and counted.
+pub fn read_library(library: &Path, include_hidden: bool) -> Result<Vec<UploadItem>> {
+    let db_path = library.join("database").join("Photos.sqlite");
+    if !db_path.is_file() {
+        anyhow::bail!(
+            "{:?} does not look like a Photos library (missing database/Photos.sqlite)",
+            library
+        );
+    }
+    let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {:?} (is Photos still running?)", db_path))?;
+
+    // Photos 5 (macOS 10.15/11) named the asset table ZGENERICASSET.
+    let asset_table = if table_exists(&conn, "ZASSET")? {
+        "ZASSET"
+    } else {
+        "ZGENERICASSET"
+    };
+    // Originals live in "originals" since Photos 5 and in "Masters" before that.
+    let originals = ["originals", "Masters"]
+        .iter()
+        .map(|d| library.join(d))
+        .find(|d| d.is_dir())
+        .context("Photos library has no originals folder")?;
+
+    let mut albums = read_album_memberships(&conn)?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT a.Z_PK, a.ZDIRECTORY, a.ZFILENAME, a.ZFAVORITE, a.ZDATECREATED, a.ZHIDDEN,
+                attr.ZORIGINALFILENAME, d.ZLONGDESCRIPTION
+         FROM {asset_table} a
+         LEFT JOIN ZADDITIONALASSETATTRIBUTES attr ON attr.ZASSET = a.Z_PK
+         LEFT JOIN ZASSETDESCRIPTION d ON d.Z_PK = attr.ZASSETDESCRIPTION
+         WHERE a.ZTRASHEDSTATE = 0"
+    ))?;
+    let mut rows = stmt.query([])?;
+
+    let mut items = Vec::new();
+    let mut missing = 0;
+    while let Some(row) = rows.next()? {
+        let pk: i64 = row.get(0)?;
+        let directory: Option<String> = row.get(1)?;
+        let filename: Option<String> = row.get(2)?;
+        let favorite: Option<i64> = row.get(3)?;
+        let date_created: Option<f64> = row.get(4)?;
+        let hidden: Option<i64> = row.get(5)?;
+        let original_filename: Option<String> = row.get(6)?;
+        let description: Option<String> = row.get(7)?;
+
+        if hidden == Some(1) && !include_hidden {
+            continue;
+        }
+        let (Some(directory), Some(filename)) = (directory, filename) else {
+            continue;
+        };
+        let path = originals.join(directory).join(filename);
+        if !path.is_file() {
+            missing += 1;
+            continue;
+        }
+
+        items.push(UploadItem {
+            filename: original_filename,
+            taken_at: date_created.and_then(core_data_date),
+            favorite: Some(favorite == Some(1)),
+            albums: albums.remove(&pk).unwrap_or_default(),
+            description: description.filter(|d| !d.trim().is_empty()),
+            ..UploadItem::new(path)
+        });
+    }
+
+    if missing > 0 {
+        println!(
+            "Skipped {} assets whose originals are not downloaded locally (iCloud optimized storage).",
+            missing
+        );
+    }
+    Ok(items)
+}
+
+/// Converts a Core Data timestamp (seconds since 2001-01-01 UTC) into a UTC date.
+fn core_data_date(seconds: f64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(CORE_DATA_EPOCH + seconds as i64, 0)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// Maps asset primary keys to the titles of the user albums containing them.
+/// The album/asset join table is named after Core Data entity numbers that change
+/// between Photos versions (e.g. `Z_26ASSETS` with `Z_26ALBUMS`/`Z_3ASSETS`), so it is
+/// located by its column names.
+fn read_album_memberships(conn: &Connection) -> Result<HashMap<i64, Vec<String>>> {
+    let mut memberships: HashMap<i64, Vec<String>> = HashMap::new();
+    let Some((table, albums_col, assets_col)) = find_album_join_table(conn)? else {
+        return Ok(memberships);
+    };
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT j.{assets_col}, al.ZTITLE
+         FROM {table} j
+         JOIN ZGENERICALBUM al ON al.Z_PK = j.{albums_col}
+         WHERE al.ZKIND = ?1 AND al.ZTRASHEDSTATE = 0 AND al.ZTITLE IS NOT NULL"
+    ))?;
+    let rows = stmt.query_map([USER_ALBUM_KIND], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?;
+    for row in rows {
+        let (asset, title) = row?;
+        memberships.entry(asset).or_default().push(title);
+    }
+    Ok(memberships)
+}
+
+fn find_album_join_table(conn: &Connection) -> Result<Option<(String, String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'Z\\_%ASSETS' ESCAPE '\\'",
+    )?;
+    let tables: Vec<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    for table in tables {
+        let mut info = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns: Vec<String> = info
+            .query_map([], |row| row.get(1))?
+            .collect::<rusqlite::Result<_>>()?;
+        let albums_col = columns.iter().find(|c| c.ends_with("ALBUMS"));
+        let assets_col = columns.iter().find(|c| c.ends_with("ASSETS"));
+        if let (Some(albums_col), Some(assets_col)) = (albums_col, assets_col) {
+            return Ok(Some((table, albums_col.clone(), assets_col.clone())));
+        }
+    }
+    Ok(None)
+}
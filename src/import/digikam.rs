@@ -0,0 +1,157 @@
+use crate::upload::UploadItem;
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// `Images.status` of images that are visible in digiKam (not hidden or trashed).
+const VISIBLE_STATUS: i64 = 1;
+
+/// `ImageComments.type` of user captions.
+const CAPTION_COMMENT_TYPE: i64 = 1;
+
+/// Root of digiKam's internal bookkeeping tags (pick labels, color labels, ...).
+const INTERNAL_TAGS_ROOT: &str = "_Digikam_Internal_Tags_";
+
+/// Reads a digiKam SQLite database (digikam4.db): physical albums become Immich albums
+/// named after their path, tags keep their hierarchy and ratings and captions are carried over.
+pub fn read_database(database: &Path) -> Result<Vec<UploadItem>> {
+    let conn = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open digiKam database {:?}", database))?;
+
+    let roots = read_album_roots(&conn)?;
+    let mut tags = read_tags(&conn)?;
+    let mut captions = read_captions(&conn)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT i.id, a.albumRoot, a.relativePath, i.name, info.rating
+         FROM Images i
+         JOIN Albums a ON a.id = i.album
+         LEFT JOIN ImageInformation info ON info.imageid = i.id
+         WHERE i.status = ?1",
+    )?;
+    let mut rows = stmt.query([VISIBLE_STATUS])?;
+
+    let mut items = Vec::new();
+    let mut missing = 0;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let root_id: i64 = row.get(1)?;
+        let relative_path: String = row.get(2)?;
+        let name: String = row.get(3)?;
+        let rating: Option<i64> = row.get(4)?;
+
+        let Some(root) = roots.get(&root_id) else {
+            missing += 1;
+            continue;
+        };
+        let album = relative_path.trim_matches('/');
+        let path = root.join(album).join(&name);
+        if !path.is_file() {
+            missing += 1;
+            continue;
+        }
+
+        items.push(UploadItem {
+            // digiKam stores -1 for "no rating".
+            rating: rating.filter(|r| *r >= 0).map(|r| r as i32),
+            tags: tags.remove(&id).unwrap_or_default(),
+            description: captions.remove(&id),
+            albums: if album.is_empty() {
+                Vec::new()
+            } else {
+                vec![album.to_string()]
+            },
+            ..UploadItem::new(path)
+        });
+    }
+
+    if missing > 0 {
+        println!(
+            "Skipped {} images whose files could not be found (unmounted collection?).",
+            missing
+        );
+    }
+    Ok(items)
+}
+
+/// Resolves album root IDs to directories.
+/// Roots identified by path (`volumeid:?path=/home/me/Pictures`) are used as-is; for
+/// volume-UUID roots the collection is assumed to be mounted at `specificPath`.
+fn read_album_roots(conn: &Connection) -> Result<HashMap<i64, PathBuf>> {
+    let mut stmt = conn.prepare("SELECT id, identifier, specificPath FROM AlbumRoots")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+        ))
+    })?;
+
+    let mut roots = HashMap::new();
+    for row in rows {
+        let (id, identifier, specific_path) = row?;
+        let from_identifier = identifier
+            .as_deref()
+            .and_then(|i| i.split_once("path="))
+            .map(|(_, path)| path.to_string());
+        if let Some(path) = from_identifier.or(specific_path) {
+            roots.insert(id, PathBuf::from(path));
+        }
+    }
+    Ok(roots)
+}
+
+/// Maps image IDs to their tags, expanded to full "Parent/Child" paths.
+/// digiKam's internal tags are left out.
+fn read_tags(conn: &Connection) -> Result<HashMap<i64, Vec<String>>> {
+    let mut stmt = conn.prepare("SELECT id, pid, name FROM Tags")?;
+    let tree: HashMap<i64, (i64, String)> = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, (row.get(1)?, row.get(2)?)))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let full_name = |mut id: i64| {
+        let mut parts = Vec::new();
+        while let Some((parent, name)) = tree.get(&id) {
+            if name == INTERNAL_TAGS_ROOT {
+                return None;
+            }
+            parts.push(name.as_str());
+            id = *parent;
+        }
+        parts.reverse();
+        (!parts.is_empty()).then(|| parts.join("/"))
+    };
+
+    let mut stmt = conn.prepare("SELECT imageid, tagid FROM ImageTags")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (image, tag) = row?;
+        if let Some(name) = full_name(tag) {
+            tags.entry(image).or_default().push(name);
+        }
+    }
+    Ok(tags)
+}
+
+/// Maps image IDs to their caption.
+fn read_captions(conn: &Connection) -> Result<HashMap<i64, String>> {
+    let mut stmt = conn.prepare("SELECT imageid, comment FROM ImageComments WHERE type = ?1")?;
+    let rows = stmt.query_map([CAPTION_COMMENT_TYPE], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?))
+    })?;
+
+    let mut captions = HashMap::new();
+    for row in rows {
+        if let (image, Some(comment)) = row?
+            && !comment.trim().is_empty()
+        {
+            captions.insert(image, comment);
+        }
+    }
+    Ok(captions)
+}
@@ -0,0 +1,116 @@
+use super::parse_local_date;
+use crate::upload::{UploadItem, is_image_or_video};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Per-photo metadata from `photo_<id>.json`.
+#[derive(Deserialize, Default)]
+struct FlickrPhoto {
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    date_taken: String,
+    #[serde(default)]
+    tags: Vec<FlickrTag>,
+}
+
+#[derive(Deserialize)]
+struct FlickrTag {
+    tag: String,
+}
+
+/// `albums.json` listing every album and the IDs of its photos.
+#[derive(Deserialize)]
+struct FlickrAlbums {
+    albums: Vec<FlickrAlbum>,
+}
+
+#[derive(Deserialize)]
+struct FlickrAlbum {
+    title: String,
+    #[serde(default)]
+    photos: Vec<String>,
+}
+
+/// Reads an extracted Flickr account export: the `data-download-*` media folders plus the
+/// account folder holding `photo_<id>.json` files and `albums.json`. All parts can be
+/// extracted under one directory.
+pub fn read_export(root: &Path) -> Result<Vec<UploadItem>> {
+    if !root.is_dir() {
+        anyhow::bail!("Path {:?} is not a directory", root);
+    }
+
+    let mut photos: HashMap<String, FlickrPhoto> = HashMap::new();
+    let mut albums: HashMap<String, Vec<String>> = HashMap::new();
+    let mut media: Vec<PathBuf> = Vec::new();
+
+    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy();
+        if name.starts_with("photo_") && name.ends_with(".json") {
+            let photo: FlickrPhoto = serde_json::from_str(&std::fs::read_to_string(path)?)
+                .with_context(|| format!("Failed to parse {:?}", path))?;
+            photos.insert(photo.id.clone(), photo);
+        } else if name == "albums.json" {
+            let list: FlickrAlbums = serde_json::from_str(&std::fs::read_to_string(path)?)
+                .with_context(|| format!("Failed to parse {:?}", path))?;
+            for album in list.albums {
+                for photo in album.photos {
+                    albums.entry(photo).or_default().push(album.title.clone());
+                }
+            }
+        } else if is_image_or_video(path) {
+            media.push(path.to_path_buf());
+        }
+    }
+
+    let mut items = Vec::new();
+    for path in media {
+        let photo_id = photo_id(&path, &photos);
+        let photo = photo_id
+            .as_ref()
+            .and_then(|id| photos.remove(id))
+            .unwrap_or_default();
+        let description = [photo.name.trim(), photo.description.trim()]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        items.push(UploadItem {
+            taken_at: parse_date_taken(&photo.date_taken),
+            description: (!description.is_empty()).then_some(description),
+            tags: photo.tags.into_iter().map(|t| t.tag).collect(),
+            albums: photo_id
+                .and_then(|id| albums.remove(&id))
+                .unwrap_or_default(),
+            ..UploadItem::new(path)
+        });
+    }
+    Ok(items)
+}
+
+/// Finds the Flickr photo ID in a media filename such as `sunset_51234567890_o.jpg`
+/// or `51234567890_abcdef1234_o.jpg` by matching underscore-separated parts against
+/// the known IDs, right to left.
+fn photo_id(path: &Path, photos: &HashMap<String, FlickrPhoto>) -> Option<String> {
+    let stem = path.file_stem()?.to_string_lossy();
+    stem.rsplit('_')
+        .find(|part| photos.contains_key(*part))
+        .map(str::to_string)
+}
+
+/// Parses Flickr's `date_taken` ("2019-08-12 16:55:01"), which is camera local time.
+fn parse_date_taken(value: &str) -> Option<DateTime<Utc>> {
+    parse_local_date(value, "%Y-%m-%d %H:%M:%S")
+}
@@ -0,0 +1,183 @@
+use super::read_csv;
+use crate::upload::{UploadItem, is_image_or_video};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Per-photo flags from Apple's `Photo Details.csv`.
+#[derive(Default)]
+struct PhotoDetails {
+    favorite: bool,
+    hidden: bool,
+    deleted: bool,
+}
+
+/// Reads an iCloud Photos export: Apple's "Download originals" / privacy.apple.com archive
+/// (with `Photo Details.csv` and `Albums/*.csv`) or a tree downloaded with icloudpd.
+/// Live Photo videos are paired with their stills and edited variants (`IMG_E1234`)
+/// are skipped unless requested.
+pub fn read_export(
+    root: &Path,
+    include_edited: bool,
+    include_hidden: bool,
+) -> Result<Vec<UploadItem>> {
+    if !root.is_dir() {
+        anyhow::bail!("Path {:?} is not a directory", root);
+    }
+
+    let mut media = Vec::new();
+    let mut details: HashMap<String, PhotoDetails> = HashMap::new();
+    let mut albums: HashMap<String, Vec<String>> = HashMap::new();
+
+    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy();
+        let is_csv = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+        let in_albums_dir = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .is_some_and(|n| n == "Albums");
+
+        if is_csv && name.starts_with("Photo Details") {
+            read_photo_details(path, &mut details)?;
+        } else if is_csv && in_albums_dir {
+            let album = path.file_stem().unwrap_or_default().to_string_lossy();
+            for row in read_csv(path)?.into_iter().skip(1) {
+                if let Some(image) = row.into_iter().next().filter(|i| !i.is_empty()) {
+                    albums.entry(image).or_default().push(album.to_string());
+                }
+            }
+        } else if is_image_or_video(path) {
+            media.push(path.to_path_buf());
+        }
+    }
+
+    // Stills by (directory, lowercase stem), used to pair Live Photo videos and edits.
+    let stills: HashSet<(PathBuf, String)> = media
+        .iter()
+        .filter(|p| !is_video(p))
+        .map(|p| media_key(p, &stem(p)))
+        .collect();
+
+    let mut live_videos: HashMap<(PathBuf, String), PathBuf> = HashMap::new();
+    let mut others = Vec::new();
+    for path in media {
+        let stem = stem(&path);
+        if is_video(&path) {
+            // icloudpd names Live Photo videos IMG_1234_HEVC.MOV, Apple exports IMG_1234.MOV.
+            let base = stem.strip_suffix("_hevc").unwrap_or(&stem);
+            let key = media_key(&path, base);
+            if stills.contains(&key) {
+                live_videos.insert(key, path);
+                continue;
+            }
+        }
+        others.push(path);
+    }
+
+    let mut items = Vec::new();
+    let mut skipped_edits = 0;
+    for path in others {
+        let stem = stem(&path);
+        let is_edit = edited_original_stem(&stem).is_some_and(|original| {
+            stills.contains(&media_key(&path, &original))
+                || live_videos.contains_key(&media_key(&path, &original))
+        });
+        if is_edit && !include_edited {
+            skipped_edits += 1;
+            continue;
+        }
+
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let flags = details.remove(&name).unwrap_or_default();
+        if flags.deleted || (flags.hidden && !include_hidden) {
+            continue;
+        }
+
+        let live_photo_video = if is_video(&path) {
+            None
+        } else {
+            live_videos.remove(&media_key(&path, &stem))
+        };
+        items.push(UploadItem {
+            favorite: Some(flags.favorite),
+            albums: albums.remove(&name).unwrap_or_default(),
+            live_photo_video,
+            ..UploadItem::new(path)
+        });
+    }
+
+    if skipped_edits > 0 {
+        println!(
+            "Skipped {} edited variants (use --include-edited to upload them too).",
+            skipped_edits
+        );
+    }
+    Ok(items)
+}
+
+/// Merges the rows of one `Photo Details.csv` into the per-filename flags.
+fn read_photo_details(path: &Path, details: &mut HashMap<String, PhotoDetails>) -> Result<()> {
+    let mut rows = read_csv(path)?.into_iter();
+    let Some(header) = rows.next() else {
+        return Ok(());
+    };
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let Some(name_col) = column("imgName") else {
+        return Ok(());
+    };
+    let (favorite_col, hidden_col, deleted_col) =
+        (column("favorite"), column("hidden"), column("deleted"));
+    let flag = |row: &[String], col: Option<usize>| {
+        col.and_then(|c| row.get(c))
+            .is_some_and(|v| v.eq_ignore_ascii_case("yes"))
+    };
+
+    for row in rows {
+        if let Some(name) = row.get(name_col) {
+            details.insert(
+                name.clone(),
+                PhotoDetails {
+                    favorite: flag(&row, favorite_col),
+                    hidden: flag(&row, hidden_col),
+                    deleted: flag(&row, deleted_col),
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the original's stem for an Apple edited variant (`IMG_E1234` -> `img_1234`).
+fn edited_original_stem(stem: &str) -> Option<String> {
+    let (prefix, rest) = stem.split_once('_')?;
+    let digits = rest.strip_prefix('e')?;
+    (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+        .then(|| format!("{}_{}", prefix, digits))
+}
+
+fn stem(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase()
+}
+
+fn media_key(path: &Path, stem: &str) -> (PathBuf, String) {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    (dir, stem.to_string())
+}
+
+fn is_video(path: &Path) -> bool {
+    mime_guess::from_path(path).first_or_octet_stream().type_() == mime_guess::mime::VIDEO
+}
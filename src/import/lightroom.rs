@@ -0,0 +1,132 @@
+use super::parse_local_date;
+use crate::upload::UploadItem;
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// `creationId` of regular (non-smart) collections.
+const COLLECTION_KIND: &str = "com.adobe.ag.library.collection";
+
+/// Reads a Lightroom Classic catalog (.lrcat): collections become albums, keywords become
+/// tags and star ratings are carried over. Rejected photos and virtual copies are skipped;
+/// with `picked_only`, only photos flagged as picks are imported.
+pub fn read_catalog(catalog: &Path, picked_only: bool) -> Result<Vec<UploadItem>> {
+    let conn = Connection::open_with_flags(catalog, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {:?} (is Lightroom still running?)", catalog))?;
+
+    let mut collections = read_collections(&conn)?;
+    let mut keywords = read_keywords(&conn)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT i.id_local, r.absolutePath, fo.pathFromRoot, f.baseName, f.extension,
+                i.rating, i.pick, i.captureTime
+         FROM Adobe_images i
+         JOIN AgLibraryFile f ON f.id_local = i.rootFile
+         JOIN AgLibraryFolder fo ON fo.id_local = f.folder
+         JOIN AgLibraryRootFolder r ON r.id_local = fo.rootFolder
+         WHERE i.masterImage IS NULL",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut items = Vec::new();
+    let mut missing = 0;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let root: String = row.get(1)?;
+        let folder: String = row.get(2)?;
+        let base_name: String = row.get(3)?;
+        let extension: String = row.get(4)?;
+        let rating: Option<i64> = row.get(5)?;
+        let pick: Option<f64> = row.get(6)?;
+        let capture_time: Option<String> = row.get(7)?;
+
+        let pick = pick.unwrap_or(0.0);
+        if pick < 0.0 || (picked_only && pick <= 0.0) {
+            continue;
+        }
+
+        let path = PathBuf::from(format!("{}{}{}.{}", root, folder, base_name, extension));
+        if !path.is_file() {
+            missing += 1;
+            continue;
+        }
+
+        items.push(UploadItem {
+            taken_at: capture_time
+                .as_deref()
+                .and_then(|t| parse_local_date(t, "%Y-%m-%dT%H:%M:%S%.f")),
+            rating: rating.map(|r| r as i32),
+            tags: keywords.remove(&id).unwrap_or_default(),
+            albums: collections.remove(&id).unwrap_or_default(),
+            ..UploadItem::new(path)
+        });
+    }
+
+    if missing > 0 {
+        println!(
+            "Skipped {} photos whose files are missing (offline drive or moved outside Lightroom).",
+            missing
+        );
+    }
+    Ok(items)
+}
+
+/// Maps image IDs to the names of the regular collections containing them.
+fn read_collections(conn: &Connection) -> Result<HashMap<i64, Vec<String>>> {
+    let mut stmt = conn.prepare(
+        "SELECT ci.image, c.name
+         FROM AgLibraryCollectionImage ci
+         JOIN AgLibraryCollection c ON c.id_local = ci.collection
+         WHERE c.creationId = ?1 AND c.name IS NOT NULL",
+    )?;
+    let rows = stmt.query_map([COLLECTION_KIND], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut collections: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (image, name) = row?;
+        collections.entry(image).or_default().push(name);
+    }
+    Ok(collections)
+}
+
+/// Maps image IDs to their keywords, expanded to full "Parent/Child" paths.
+fn read_keywords(conn: &Connection) -> Result<HashMap<i64, Vec<String>>> {
+    // The keyword tree's root has no name; it is left out of the paths.
+    let mut stmt = conn.prepare("SELECT id_local, name, parent FROM AgLibraryKeyword")?;
+    let tree: HashMap<i64, (Option<String>, Option<i64>)> = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, (row.get(1)?, row.get(2)?)))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let full_name = |mut id: i64| {
+        let mut parts = Vec::new();
+        while let Some((name, parent)) = tree.get(&id) {
+            if let Some(name) = name {
+                parts.push(name.as_str());
+            }
+            match parent {
+                Some(parent) => id = *parent,
+                None => break,
+            }
+        }
+        parts.reverse();
+        parts.join("/")
+    };
+
+    let mut stmt = conn.prepare("SELECT image, tag FROM AgLibraryKeywordImage")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+
+    let mut keywords: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (image, tag) = row?;
+        let name = full_name(tag);
+        if !name.is_empty() {
+            keywords.entry(image).or_default().push(name);
+        }
+    }
+    Ok(keywords)
+}
@@ -0,0 +1,52 @@
+use crate::upload::{UploadItem, is_image_or_video};
+use anyhow::Result;
+use std::path::Path;
+use walkdir::{DirEntry, WalkDir};
+
+/// Directories Synology DSM creates next to user files: per-file thumbnail/index folders,
+/// the shared-folder recycle bin and Btrfs snapshot views.
+const SYSTEM_DIRS: &[&str] = &["@eaDir", "#recycle", "#snapshot", "@tmp"];
+
+/// Prefixes of thumbnails and index files generated by Synology Photos/Moments/File Station.
+const GENERATED_PREFIXES: &[&str] = &["SYNOPHOTO_THUMB", "SYNOFILE_THUMB", "SYNOPHOTO_FILM"];
+
+/// Reads a Synology Photos / Moments shared folder (e.g. `/volume1/photo` or a user's
+/// `Photos` folder), skipping everything DSM generates. With `folder_albums`, each
+/// directory containing media becomes an album named after its path below the root.
+pub fn read_folder(root: &Path, folder_albums: bool) -> Result<Vec<UploadItem>> {
+    if !root.is_dir() {
+        anyhow::bail!("Path {:?} is not a directory", root);
+    }
+
+    let mut items = Vec::new();
+    let walker = WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| !is_synology_generated(e));
+    for entry in walker.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !entry.file_type().is_file() || !is_image_or_video(path) {
+            continue;
+        }
+
+        let album = path
+            .parent()
+            .and_then(|p| p.strip_prefix(root).ok())
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .filter(|a| folder_albums && !a.is_empty());
+        items.push(UploadItem {
+            albums: album.into_iter().collect(),
+            ..UploadItem::new(path.to_path_buf())
+        });
+    }
+    Ok(items)
+}
+
+/// Whether a directory entry was created by DSM rather than by the user.
+fn is_synology_generated(entry: &DirEntry) -> bool {
+    let name = entry.file_name().to_string_lossy();
+    if entry.file_type().is_dir() {
+        SYSTEM_DIRS.contains(&name.as_ref())
+    } else {
+        GENERATED_PREFIXES.iter().any(|p| name.starts_with(p))
+    }
+}
@@ -0,0 +1,145 @@
+use crate::api::ImmichClient;
+use crate::progress;
+use anyhow::{Context, Result, bail};
+use futures::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Server queues started after an upload, with the names they are reported under.
+const QUEUES: [(&str, &str); 3] = [
+    ("metadataExtraction", "metadata"),
+    ("thumbnailGeneration", "thumbnails"),
+    ("smartSearch", "smart search"),
+];
+
+/// Per-asset jobs queued when the queues can't be started.
+const ASSET_JOBS: [&str; 2] = ["refresh-metadata", "regenerate-thumbnail"];
+
+/// Assets per jobs request.
+const BATCH_SIZE: usize = 500;
+
+/// How often the queues are checked while waiting for them to drain.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often assets are checked while waiting for them to be processed.
+const ASSET_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Assets fetched at the same time while checking them.
+const ASSET_CHECKS: usize = 8;
+
+/// Gets the server processing newly uploaded assets right away. With an admin's key, the
+/// metadata, thumbnail and smart search queues are started for whatever they haven't
+/// processed yet, and their progress is reported until they are empty (Ctrl-C stops
+/// waiting). Otherwise metadata extraction and thumbnails are queued for `asset_ids`.
+pub async fn process(client: &ImmichClient, asset_ids: &[String]) -> Result<()> {
+    if asset_ids.is_empty() {
+        return Ok(());
+    }
+    let mut started = true;
+    for (queue, _) in QUEUES {
+        if client.start_queue(queue).await.is_err() {
+            started = false;
+            break;
+        }
+    }
+    if !started {
+        for batch in asset_ids.chunks(BATCH_SIZE) {
+            for job in ASSET_JOBS {
+                client
+                    .run_asset_jobs(batch, job)
+                    .await
+                    .with_context(|| format!("Failed to queue {} jobs", job))?;
+            }
+        }
+        progress::note(format!(
+            "Queued metadata extraction and thumbnails for {} new assets; starting smart \
+             search and following the queues needs an admin's API key.",
+            asset_ids.len()
+        ));
+        return Ok(());
+    }
+
+    progress::note(format!(
+        "Started processing {} new assets; waiting for the server's queues to drain.",
+        asset_ids.len()
+    ));
+    loop {
+        let queues = client
+            .job_queues()
+            .await
+            .context("Failed to read the server's queues")?;
+        let pending: Vec<(&str, u64)> = QUEUES
+            .iter()
+            .filter_map(|(queue, label)| {
+                let counts = &queues.get(*queue)?.job_counts;
+                Some((*label, counts.active + counts.waiting + counts.delayed))
+            })
+            .collect();
+        if pending.iter().all(|(_, count)| *count == 0) {
+            progress::note("Processing complete.");
+            return Ok(());
+        }
+        let status: Vec<String> = pending
+            .iter()
+            .map(|(label, count)| format!("{} {}", label, count))
+            .collect();
+        progress::note(format!("Jobs left: {}", status.join(", ")));
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = tokio::signal::ctrl_c() => {
+                progress::note("Stopped waiting; the server keeps processing in the background.");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Waits until the server generated the thumbnails and extracted the metadata of all
+/// `asset_ids`, so that scripts building on the upload don't see half-processed assets.
+/// Fails when some are still being processed after `timeout`.
+pub async fn wait_for_assets(
+    client: &ImmichClient,
+    asset_ids: &[String],
+    timeout: Duration,
+) -> Result<()> {
+    let start = Instant::now();
+    let mut pending: Vec<&String> = asset_ids.iter().collect();
+    let mut reported = None;
+    while !pending.is_empty() {
+        let done: Vec<bool> = futures::stream::iter(&pending)
+            .map(|id| async move {
+                // An asset that can't be read yet counts as not processed.
+                client
+                    .get_asset(id)
+                    .await
+                    .is_ok_and(|asset| asset.thumbhash.is_some() && asset.exif_info.is_some())
+            })
+            .buffered(ASSET_CHECKS)
+            .collect()
+            .await;
+        let mut done = done.into_iter();
+        pending.retain(|_| !done.next().unwrap_or(false));
+        if pending.is_empty() {
+            break;
+        }
+        if reported != Some(pending.len()) {
+            reported = Some(pending.len());
+            progress::note(format!(
+                "Waiting for the server to process {} of {} new assets...",
+                pending.len(),
+                asset_ids.len()
+            ));
+        }
+        if start.elapsed() >= timeout {
+            bail!(
+                "{} assets were still being processed after {}s",
+                pending.len(),
+                timeout.as_secs()
+            );
+        }
+        tokio::time::sleep(ASSET_POLL_INTERVAL).await;
+    }
+    if !asset_ids.is_empty() {
+        progress::note(format!("All {} new assets are processed.", asset_ids.len()));
+    }
+    Ok(())
+}
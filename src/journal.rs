@@ -0,0 +1,270 @@
+use crate::config::Config;
+use crate::upload::UploadItem;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Record of a single run: the files it planned to upload, followed by one line per
+/// completed file. Kept until the run finishes without failures, so an interrupted run
+/// can be resumed without rescanning.
+pub struct Journal {
+    path: PathBuf,
+    writer: Mutex<File>,
+    /// Album name -> IDs of assets completed before the run was resumed.
+    album_assets: HashMap<String, Vec<String>>,
+}
+
+/// First line of a journal file.
+#[derive(Serialize, Deserialize)]
+struct Plan {
+    planned: Vec<UploadItem>,
+}
+
+/// Following lines of a journal file.
+#[derive(Serialize, Deserialize)]
+struct Done {
+    done: PathBuf,
+    asset_id: Option<String>,
+}
+
+/// Progress of an interrupted run.
+pub struct Pending {
+    /// Files that were not completed.
+    pub items: Vec<UploadItem>,
+    /// Number of files the run planned to upload.
+    pub planned: usize,
+    /// Album name -> IDs of assets that were completed.
+    album_assets: HashMap<String, Vec<String>>,
+}
+
+impl Journal {
+    /// Starts a new journal for `key` (e.g. the upload directory), replacing any previous one.
+    pub fn create(key: &str, items: &[UploadItem]) -> Result<Self> {
+        let path = journal_path(key)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file =
+            File::create(&path).with_context(|| format!("Failed to create journal {:?}", path))?;
+        let plan = Plan {
+            planned: items.to_vec(),
+        };
+        writeln!(file, "{}", serde_json::to_string(&plan)?)?;
+        file.flush()?;
+        Ok(Journal {
+            path,
+            writer: Mutex::new(file),
+            album_assets: HashMap::new(),
+        })
+    }
+
+    /// Reads what is left of an interrupted run for `key`, if there was one.
+    pub fn pending(key: &str) -> Result<Option<Pending>> {
+        let path = journal_path(key)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let reader = BufReader::new(File::open(&path)?);
+        Pending::read(reader).with_context(|| format!("Corrupt journal {:?}", path))
+    }
+
+    /// Reopens the journal for `key` to keep recording an interrupted run.
+    pub fn reopen(key: &str, pending: &mut Pending) -> Result<Self> {
+        let path = journal_path(key)?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open journal {:?}", path))?;
+        Ok(Journal {
+            path,
+            writer: Mutex::new(file),
+            album_assets: std::mem::take(&mut pending.album_assets),
+        })
+    }
+
+    /// Albums of assets completed before the run was resumed, which still need to be
+    /// assigned at the end of the run.
+    pub fn album_assets(&self) -> &HashMap<String, Vec<String>> {
+        &self.album_assets
+    }
+
+    /// Records a file as completed.
+    pub fn record_done(&self, path: &Path, asset_id: Option<&str>) -> Result<()> {
+        let line = serde_json::to_string(&Done {
+            done: path.to_path_buf(),
+            asset_id: asset_id.map(str::to_string),
+        })?;
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{}", line)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Deletes the journal once the run has completed.
+    pub fn finish(self) -> Result<()> {
+        drop(self.writer);
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Pending {
+    /// Replays a journal: the plan on its first line, minus the files recorded as done.
+    fn read(reader: impl BufRead) -> Result<Option<Self>> {
+        let mut lines = reader.lines();
+        let Some(first) = lines.next() else {
+            return Ok(None);
+        };
+        let plan: Plan = serde_json::from_str(&first?)?;
+
+        let mut done = HashMap::new();
+        for line in lines {
+            // A line cut short by the interruption is ignored; that file is simply redone.
+            if let Ok(entry) = serde_json::from_str::<Done>(&line?) {
+                done.insert(entry.done, entry.asset_id);
+            }
+        }
+
+        let planned = plan.planned.len();
+        let mut items = Vec::new();
+        let mut album_assets: HashMap<String, Vec<String>> = HashMap::new();
+        for item in plan.planned {
+            match done.get(&item.path) {
+                None => items.push(item),
+                Some(asset_id) => {
+                    for album in &item.albums {
+                        album_assets
+                            .entry(album.clone())
+                            .or_default()
+                            .extend(asset_id.clone());
+                    }
+                }
+            }
+        }
+        Ok(Some(Pending {
+            items,
+            planned,
+            album_assets,
+        }))
+    }
+}
+
+/// Location of the journal for a key, under ~/.immich/journals.
+fn journal_path(key: &str) -> Result<PathBuf> {
+    let name = sha1_smol::Sha1::from(key).digest().to_string();
+    Ok(Config::state_dir()?
+        .join("journals")
+        .join(format!("{}.jsonl", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(path: &str, albums: &[&str]) -> UploadItem {
+        let mut item = UploadItem::new(PathBuf::from(path));
+        item.albums = albums.iter().map(|a| a.to_string()).collect();
+        item
+    }
+
+    fn plan(items: &[UploadItem]) -> String {
+        serde_json::to_string(&Plan {
+            planned: items.to_vec(),
+        })
+        .unwrap()
+            + "\n"
+    }
+
+    fn done(path: &str, asset_id: Option<&str>) -> String {
+        serde_json::to_string(&Done {
+            done: PathBuf::from(path),
+            asset_id: asset_id.map(str::to_string),
+        })
+        .unwrap()
+            + "\n"
+    }
+
+    fn replay(journal: &str) -> Pending {
+        Pending::read(journal.as_bytes()).unwrap().unwrap()
+    }
+
+    fn paths(pending: &Pending) -> Vec<&str> {
+        pending
+            .items
+            .iter()
+            .map(|item| item.path.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn replays_a_partial_journal() {
+        let items = [
+            item("/a.jpg", &["Trip"]),
+            item("/b.jpg", &["Trip"]),
+            item("/c.jpg", &[]),
+        ];
+        let journal = plan(&items) + &done("/a.jpg", Some("asset-a"));
+        let pending = replay(&journal);
+        assert_eq!(pending.planned, 3);
+        assert_eq!(paths(&pending), ["/b.jpg", "/c.jpg"]);
+        assert_eq!(pending.album_assets["Trip"], ["asset-a"]);
+    }
+
+    #[test]
+    fn redoes_a_file_whose_line_was_cut_short() {
+        let items = [item("/a.jpg", &[]), item("/b.jpg", &[])];
+        let cut = done("/b.jpg", Some("asset-b"));
+        let journal = plan(&items) + &done("/a.jpg", Some("asset-a")) + &cut[..cut.len() / 2];
+        assert_eq!(paths(&replay(&journal)), ["/b.jpg"]);
+    }
+
+    #[test]
+    fn counts_completed_entries_once() {
+        let items = [item("/a.jpg", &["Trip"]), item("/b.jpg", &["Trip"])];
+        // Skipped files are done without an asset; a file may be recorded twice when a
+        // resumed run is interrupted again.
+        let journal = plan(&items)
+            + &done("/a.jpg", Some("asset-a"))
+            + &done("/a.jpg", Some("asset-a"))
+            + &done("/b.jpg", None)
+            + &done("/elsewhere.jpg", Some("asset-x"));
+        let pending = replay(&journal);
+        assert!(pending.items.is_empty());
+        assert_eq!(pending.planned, 2);
+        assert_eq!(pending.album_assets["Trip"], ["asset-a"]);
+    }
+
+    #[test]
+    fn rejects_a_journal_without_a_plan() {
+        assert!(Pending::read("".as_bytes()).unwrap().is_none());
+        let cut = plan(&[item("/a.jpg", &[])]);
+        assert!(Pending::read(&cut.as_bytes()[..cut.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn replays_what_was_recorded() {
+        let path = std::env::temp_dir().join(format!("journal-test-{}.jsonl", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(plan(&[item("/a.jpg", &["Trip"]), item("/b.jpg", &[])]).as_bytes())
+            .unwrap();
+        let journal = Journal {
+            path: path.clone(),
+            writer: Mutex::new(file),
+            album_assets: HashMap::new(),
+        };
+        journal
+            .record_done(Path::new("/a.jpg"), Some("asset-a"))
+            .unwrap();
+        let pending = Pending::read(BufReader::new(File::open(&path).unwrap()))
+            .unwrap()
+            .unwrap();
+        journal.finish().unwrap();
+        assert_eq!(paths(&pending), ["/b.jpg"]);
+        assert_eq!(pending.album_assets["Trip"], ["asset-a"]);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// Name messages are logged under.
+const IDENTIFIER: &str = "rimmich-uploader";
+
+/// Where messages about the run are logged, in addition to the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogTarget {
+    /// Only the terminal.
+    Stderr,
+    /// The local syslog daemon.
+    Syslog,
+    /// The systemd journal, with priorities for `journalctl -p`.
+    Journald,
+    /// A file given with `--log-file`, one timestamped line per message.
+    File,
+    /// The Windows Application event log: run summaries, warnings and errors only.
+    Eventlog,
+}
+
+/// Severity of a logged message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    /// The summary of a run.
+    Notice,
+    Info,
+}
+
+impl Level {
+    /// The syslog severity, also used as the journal priority.
+    fn severity(self) -> u8 {
+        match self {
+            Level::Error => 3,
+            Level::Warning => 4,
+            Level::Notice => 5,
+            Level::Info => 6,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warning => "WARN",
+            Level::Notice => "NOTICE",
+            Level::Info => "INFO",
+        }
+    }
+}
+
+enum Sink {
+    #[cfg(unix)]
+    Syslog(std::os::unix::net::UnixDatagram),
+    #[cfg(unix)]
+    Journald(std::os::unix::net::UnixDatagram),
+    File(Mutex<File>),
+    #[cfg(windows)]
+    Eventlog,
+}
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+/// Sets where messages are logged for this run.
+pub fn init(target: LogTarget, file: Option<&Path>) -> Result<()> {
+    let sink = match target {
+        LogTarget::Stderr => return Ok(()),
+        LogTarget::File => {
+            let path = file.context("--log-target file needs --log-file")?;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file {:?}", path))?;
+            Sink::File(Mutex::new(file))
+        }
+        LogTarget::Syslog | LogTarget::Journald => socket_sink(target)?,
+        LogTarget::Eventlog => eventlog_sink()?,
+    };
+    let _ = SINK.set(sink);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn socket_sink(target: LogTarget) -> Result<Sink> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    if target == LogTarget::Journald {
+        socket
+            .connect("/run/systemd/journal/socket")
+            .context("Failed to connect to the systemd journal")?;
+        return Ok(Sink::Journald(socket));
+    }
+    // Linux and the BSDs use /dev/log; macOS uses /var/run/syslog.
+    ["/dev/log", "/var/run/syslog"]
+        .iter()
+        .find(|path| socket.connect(path).is_ok())
+        .context("Failed to connect to syslog")?;
+    Ok(Sink::Syslog(socket))
+}
+
+#[cfg(not(unix))]
+fn socket_sink(target: LogTarget) -> Result<Sink> {
+    anyhow::bail!("--log-target {:?} is only available on Unix", target)
+}
+
+#[cfg(windows)]
+fn eventlog_sink() -> Result<Sink> {
+    Ok(Sink::Eventlog)
+}
+
+#[cfg(not(windows))]
+fn eventlog_sink() -> Result<Sink> {
+    anyhow::bail!("--log-target eventlog is only available on Windows")
+}
+
+/// Logs `message` to the configured target; the caller still prints it as usual.
+pub fn log(level: Level, message: &str) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let _ = write(sink, level, message);
+}
+
+fn write(sink: &Sink, level: Level, message: &str) -> Result<()> {
+    match sink {
+        #[cfg(unix)]
+        Sink::Syslog(socket) => {
+            // Facility "user"; the daemon adds the timestamp and host.
+            let line = format!(
+                "<{}>{}[{}]: {}",
+                8 + level.severity(),
+                IDENTIFIER,
+                std::process::id(),
+                message
+            );
+            socket.send(line.as_bytes())?;
+        }
+        #[cfg(unix)]
+        Sink::Journald(socket) => {
+            socket.send(&journal_entry(level, message))?;
+        }
+        Sink::File(file) => {
+            let mut file = file.lock().unwrap();
+            for line in message.lines() {
+                writeln!(
+                    file,
+                    "{} {} {}",
+                    chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+                    level.as_str(),
+                    line
+                )?;
+            }
+        }
+        #[cfg(windows)]
+        Sink::Eventlog => write_event(level, message)?,
+    }
+    Ok(())
+}
+
+/// Records an event with `eventcreate`, which registers the source on first use (that
+/// first run needs administrator rights). Routine messages are left out, as every event
+/// starts a process.
+#[cfg(windows)]
+fn write_event(level: Level, message: &str) -> Result<()> {
+    let (kind, id) = match level {
+        Level::Error => ("ERROR", "1"),
+        Level::Warning => ("WARNING", "2"),
+        Level::Notice => ("INFORMATION", "3"),
+        Level::Info => return Ok(()),
+    };
+    let status = std::process::Command::new("eventcreate")
+        .args([
+            "/L",
+            "APPLICATION",
+            "/SO",
+            IDENTIFIER,
+            "/T",
+            kind,
+            "/ID",
+            id,
+            "/D",
+        ])
+        .arg(message)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("eventcreate failed with {}", status);
+    }
+    Ok(())
+}
+
+/// A journal entry in the native protocol: `FIELD=value` lines, with values that span
+/// several lines sent as a little-endian length followed by the raw bytes.
+#[cfg(unix)]
+fn journal_entry(level: Level, message: &str) -> Vec<u8> {
+    let mut entry = Vec::new();
+    for (field, value) in [
+        ("PRIORITY", level.severity().to_string()),
+        ("SYSLOG_IDENTIFIER", IDENTIFIER.to_string()),
+        ("SYSLOG_PID", std::process::id().to_string()),
+        ("MESSAGE", message.to_string()),
+    ] {
+        entry.extend_from_slice(field.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
@@ -1,18 +1,56 @@
+mod admin;
+mod albums;
+mod api;
+mod assets;
+mod backup;
+mod benchmark;
+mod checksums;
 mod config;
+mod controls;
+mod crypto;
+mod dedupe;
+mod doctor;
+mod download;
+mod existing;
+mod exit;
+mod failures;
+mod filename_date;
+mod hooks;
+mod import;
+mod jobs;
+mod journal;
+mod logging;
+mod metadata;
+mod migrate;
+mod mime;
+mod order;
+mod picker;
+mod plan;
+mod progress;
+mod reconcile;
+mod restore;
+mod scan;
+mod screenshot;
+mod self_update;
+mod sidecar;
+mod source;
+mod telemetry;
+mod upload;
 
 use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
-use clap::{Parser, Subcommand};
-use config::{Config, UserConfig};
-use futures::StreamExt;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use reqwest::multipart;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::SystemTime;
-use walkdir::WalkDir;
+use api::ImmichClient;
+use chrono::{DateTime, FixedOffset};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use config::{Config, EXPORT_PASSPHRASE_ENV, HttpConfig, Profile, UserConfig};
+use exit::ExitKind;
+use logging::{Level, LogTarget};
+use order::{UploadOrder, UploadPriority};
+use progress::ProgressMode;
+use screenshot::ScreenshotAction;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use upload::{ShareRole, UploadOptions, Visibility};
 
 /// Command-line arguments for the Immich uploader.
 #[derive(Parser)]
@@ -22,6 +60,50 @@ struct Cli {
     #[command(subcommand)]
     command: Commands,
 
+    /// Server and credentials to use.
+    #[command(flatten)]
+    connection: ConnectArgs,
+
+    /// Number of concurrent uploads to perform [default: 10].
+    #[arg(short, long, env = "IMMICH_CONCURRENT")]
+    concurrent: Option<usize>,
+
+    /// Apply a named upload preset from the config file; flags still take precedence.
+    #[arg(short, long, env = "IMMICH_PROFILE")]
+    profile: Option<String>,
+
+    /// Use this config file instead of ~/.immich/config.toml.
+    #[arg(long, env = "IMMICH_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Keep resume journals and other state here (default: next to the config file).
+    #[arg(long, env = "IMMICH_STATE_DIR")]
+    state_dir: Option<PathBuf>,
+
+    /// How to show progress [default: bar on a terminal, plain otherwise].
+    #[arg(long, env = "IMMICH_PROGRESS")]
+    progress: Option<ProgressMode>,
+
+    /// Also log messages, warnings and errors to syslog, the systemd journal or a file.
+    #[arg(long, env = "IMMICH_LOG_TARGET", default_value = "stderr")]
+    log_target: LogTarget,
+
+    /// File to append log lines to with `--log-target file`.
+    #[arg(long, env = "IMMICH_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Print how long scanning, reading, hashing and sending each file took, on stderr.
+    #[arg(long)]
+    trace: bool,
+
+    /// Send tracing spans to this OTLP/HTTP collector (e.g. http://localhost:4318).
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+}
+
+/// How to reach and authenticate with the Immich server.
+#[derive(Args, Clone, Default)]
+struct ConnectArgs {
     /// Immich server URL (e.g., http://192.168.1.10:2283).
     /// Overrides configuration file settings.
     #[arg(short, long, env = "IMMICH_SERVER_URL")]
@@ -32,14 +114,45 @@ struct Cli {
     #[arg(short, long, env = "IMMICH_API_KEY")]
     key: Option<String>,
 
+    /// Upload through a shared album link instead of an API key.
+    /// Accepts the link's key or the full link URL (which also sets the server).
+    #[arg(long, env = "IMMICH_SHARE_KEY", conflicts_with_all = ["key", "user"])]
+    share_key: Option<String>,
+
     /// Use a specific user from the configuration.
     /// Overrides the default current user.
     #[arg(short, long)]
     user: Option<String>,
 
-    /// Number of concurrent uploads to perform.
-    #[arg(short, long, default_value_t = 10)]
-    concurrent: usize,
+    /// Negotiate HTTP/2 with the server (over plain HTTP, assumes the server speaks it).
+    #[arg(long)]
+    http2: bool,
+
+    /// Seconds an idle connection is kept open for reuse.
+    #[arg(long)]
+    pool_idle_timeout: Option<u64>,
+
+    /// Maximum number of idle connections kept open to the server.
+    #[arg(long)]
+    pool_max_idle: Option<usize>,
+
+    /// Send requests through this proxy: http://, https://, socks5:// or socks5h:// (which
+    /// also resolves the server name through the proxy).
+    #[arg(long, env = "IMMICH_PROXY")]
+    proxy: Option<String>,
+
+    /// Connect to ADDRESS for HOST:PORT instead of looking it up, like curl's --resolve
+    /// (e.g. immich.example.com:443:192.168.1.10). Can be repeated.
+    #[arg(long, value_name = "HOST:PORT:ADDRESS")]
+    resolve: Vec<String>,
+
+    /// Only connect to IPv4 addresses of the server (e.g. when its AAAA record is broken).
+    #[arg(short = '4', long, conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Only connect to IPv6 addresses of the server.
+    #[arg(short = '6', long)]
+    ipv6: bool,
 }
 
 /// Main subcommands for the application.
@@ -48,7 +161,21 @@ enum Commands {
     /// Upload photos and videos from a directory to the Immich server.
     Upload {
         /// Directory to scan for media files.
-        directory: PathBuf,
+        #[arg(env = "IMMICH_UPLOAD_DIR", required_unless_present_any = ["retry_failed", "files_from", "manifest"])]
+        directory: Option<PathBuf>,
+
+        /// Carry out a plan written by the `plan` command instead of scanning.
+        #[arg(long, conflicts_with_all = ["directory", "files_from", "resume", "retry_failed", "album_mirror", "album", "pick_album"])]
+        manifest: Option<PathBuf>,
+
+        /// Upload the files listed in this file (one path per line, `-` for stdin) instead of scanning.
+        #[arg(long, conflicts_with_all = ["directory", "resume", "retry_failed", "album_mirror"])]
+        files_from: Option<PathBuf>,
+
+        /// Write the paths of failed files to failed-<timestamp>.txt in this directory
+        /// (default: current directory), ready for --files-from.
+        #[arg(long, num_args = 0..=1, default_missing_value = ".")]
+        write_failed: Option<PathBuf>,
 
         /// Whether to scan subdirectories recursively.
         #[arg(short, long, default_value_t = true)]
@@ -57,12 +184,631 @@ enum Commands {
         /// Skip files that have already been uploaded (if possible).
         #[arg(short, long, default_value_t = false)]
         skip_existing: bool,
+
+        /// What to do when a file is already on the server with another capture date or
+        /// other metadata: skip it, update the existing asset's date, description, favorite
+        /// flag and tags, or ask. Implies --skip-existing unless skip.
+        #[arg(long, env = "IMMICH_ON_DUPLICATE", value_enum, default_value = "skip")]
+        on_duplicate: existing::DuplicatePolicy,
+
+        /// Mark assets whose XMP/EXIF star rating is at least this value as favorites.
+        #[arg(long, env = "IMMICH_FAVORITE_RATING", value_parser = clap::value_parser!(i32).range(1..=5))]
+        favorite_rating: Option<i32>,
+
+        /// Apply embedded IPTC/XMP keywords (including hierarchical ones) as Immich tags.
+        #[arg(long, env = "IMMICH_IMPORT_TAGS", default_value_t = false)]
+        import_tags: bool,
+
+        /// Use embedded titles (XMP, or set in Windows Explorer's file properties) as
+        /// descriptions.
+        #[arg(long, env = "IMMICH_IMPORT_TITLES", default_value_t = false)]
+        import_titles: bool,
+
+        /// Apply macOS Finder tags as Immich tags; files tagged "Favorite" become
+        /// favorites instead. Only has an effect on macOS.
+        #[arg(long, env = "IMMICH_FINDER_TAGS", default_value_t = false)]
+        finder_tags: bool,
+
+        /// Mirror the folder tree as albums and keep their contents in sync on later runs.
+        #[arg(long, env = "IMMICH_ALBUM_MIRROR", default_value_t = false)]
+        album_mirror: bool,
+
+        /// Separator between folder names in mirrored album names (e.g. "2019/Trip") [default: /].
+        #[arg(long)]
+        album_separator: Option<String>,
+
+        /// Add all uploaded files to this album, creating it if needed. Without a name,
+        /// pick one of your albums (or name a new one) interactively.
+        #[arg(long, env = "IMMICH_ALBUM", num_args = 0..=1, default_missing_value = "")]
+        album: Option<String>,
+
+        /// Pick the album to add files to from your albums interactively (same as --album
+        /// without a name).
+        #[arg(long, default_value_t = false, conflicts_with = "album")]
+        pick_album: bool,
+
+        /// Share albums created by this run with an Immich user (email or name). Repeatable.
+        #[arg(long = "share-with")]
+        share_with: Vec<String>,
+
+        /// Permission given to users albums are shared with [default: viewer].
+        #[arg(long, value_enum)]
+        share_role: Option<ShareRole>,
+
+        /// Upload into the timeline, hidden, or straight into the locked folder.
+        #[arg(long, env = "IMMICH_VISIBILITY", value_enum)]
+        visibility: Option<Visibility>,
+
+        /// After each upload, check the checksum and size stored by the server against the local file.
+        #[arg(long, env = "IMMICH_VERIFY", default_value_t = false)]
+        verify: bool,
+
+        /// Upload the video embedded in Samsung/Google Motion Photos as a separate asset
+        /// linked to the still, so it plays like a Live Photo.
+        #[arg(long, env = "IMMICH_SPLIT_MOTION_PHOTOS", default_value_t = false)]
+        split_motion_photos: bool,
+
+        /// Tag screenshots, upload them into the archive, or skip them. Screenshots are
+        /// recognized by file name, EXIF data and screen-like size and resolution.
+        #[arg(long, env = "IMMICH_SCREENSHOTS", value_enum)]
+        screenshots: Option<ScreenshotAction>,
+
+        /// Put screenshots into this album instead of the run's other albums.
+        #[arg(long, env = "IMMICH_SCREENSHOT_ALBUM")]
+        screenshot_album: Option<String>,
+
+        /// Descend at most this many folder levels below the directory (0: only its own
+        /// files), e.g. 2 for year/month trees with scratch folders further down.
+        #[arg(long, env = "IMMICH_MAX_DEPTH")]
+        max_depth: Option<usize>,
+
+        /// Don't descend into other filesystems mounted below the directory, such as
+        /// network shares or `.zfs/snapshot` trees.
+        #[arg(long, env = "IMMICH_ONE_FILE_SYSTEM", default_value_t = false)]
+        one_file_system: bool,
+
+        /// Leave files modified less than this long ago (e.g. 30s, 5m, 1h) for the next run,
+        /// as they may still be being written.
+        #[arg(long, env = "IMMICH_MIN_AGE", value_parser = parse_duration)]
+        min_age: Option<Duration>,
+
+        /// Upload files in this order instead of the order they are found in.
+        #[arg(long, env = "IMMICH_ORDER", value_enum)]
+        order: Option<UploadOrder>,
+
+        /// Upload these files first: the smallest, the most recently taken, or images
+        /// before videos. Applied on top of --order.
+        #[arg(long, env = "IMMICH_PRIORITY", value_enum)]
+        priority: Option<UploadPriority>,
+
+        /// Leave files another process still has open (e.g. a slow copy) for the next run.
+        /// Linux only.
+        #[arg(long, env = "IMMICH_SKIP_OPEN", default_value_t = false)]
+        skip_open: bool,
+
+        /// Show what would be uploaded (files, size, user, albums) and ask before uploading.
+        #[arg(long, env = "IMMICH_INTERACTIVE", default_value_t = false)]
+        interactive: bool,
+
+        /// When the server becomes unreachable, pause and wait for it to come back instead
+        /// of stopping the run for a later --resume.
+        #[arg(long, env = "IMMICH_WAIT_FOR_SERVER", default_value_t = false)]
+        wait_for_server: bool,
+
+        /// Start even if the files don't fit in the remaining storage quota or the server's
+        /// free disk space.
+        #[arg(long, env = "IMMICH_NO_QUOTA_CHECK", default_value_t = false)]
+        no_quota_check: bool,
+
+        /// After uploading, get the server extracting metadata, generating thumbnails and
+        /// indexing the new assets for smart search right away, and report until it is done.
+        #[arg(long, env = "IMMICH_PROCESS", default_value_t = false)]
+        process: bool,
+
+        /// After uploading, wait until the server generated thumbnails and metadata for
+        /// every new asset, failing after this long (default 10m), so scripts can use them
+        /// right away.
+        #[arg(
+            long,
+            env = "IMMICH_WAIT_FOR_PROCESSING",
+            value_parser = parse_duration,
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "10m",
+            value_name = "TIMEOUT"
+        )]
+        wait_for_processing: Option<Duration>,
+
+        /// Upload without asking, overriding --interactive (e.g. from a profile) in scripts.
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
+
+        /// Continue an interrupted run of this directory where it left off, without rescanning.
+        #[arg(long, default_value_t = false)]
+        resume: bool,
+
+        /// Only retry uploads that failed in earlier runs (those below the directory, if given).
+        #[arg(long, default_value_t = false, conflicts_with = "resume")]
+        retry_failed: bool,
+    },
+    /// Scan a directory and write what an upload would do (files, sizes, checksums, which
+    /// the server already has, target albums) to a manifest for review, without uploading.
+    /// `upload --manifest` carries it out.
+    Plan {
+        /// Directory to scan for media files.
+        directory: PathBuf,
+
+        /// File to write the plan to.
+        #[arg(short, long, default_value = "plan.json")]
+        output: PathBuf,
+
+        /// Whether to scan subdirectories recursively.
+        #[arg(short, long, default_value_t = true)]
+        recursive: bool,
+
+        /// Add all files to this album.
+        #[arg(long, env = "IMMICH_ALBUM")]
+        album: Option<String>,
+
+        /// Add files to an album per folder.
+        #[arg(long, env = "IMMICH_ALBUM_MIRROR", default_value_t = false)]
+        album_mirror: bool,
+
+        /// Separator between folder names in mirrored album names [default: /].
+        #[arg(long)]
+        album_separator: Option<String>,
+
+        /// Descend at most this many folder levels below the directory.
+        #[arg(long, env = "IMMICH_MAX_DEPTH")]
+        max_depth: Option<usize>,
+
+        /// Don't descend into other filesystems mounted below the directory.
+        #[arg(long, env = "IMMICH_ONE_FILE_SYSTEM", default_value_t = false)]
+        one_file_system: bool,
+    },
+    /// Import media from another photo manager, keeping its albums and metadata.
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+    /// Copy all assets, albums and metadata from one configured user/server to another.
+    Migrate {
+        /// Configured user to copy from.
+        #[arg(long)]
+        from: String,
+        /// Configured user to copy to.
+        #[arg(long)]
+        to: String,
+    },
+    /// Measure upload throughput against the server and recommend a concurrency.
+    /// Uploads synthetic images, which are moved to the trash after each round.
+    Benchmark {
+        /// Payload sizes to test, in MiB.
+        #[arg(long, value_delimiter = ',', default_values_t = [0.5, 4.0, 32.0])]
+        sizes: Vec<f64>,
+        /// Concurrency levels to test.
+        #[arg(long, value_delimiter = ',', default_values_t = [1, 2, 4, 8, 16])]
+        levels: Vec<usize>,
+        /// Files uploaded per round (at least the round's concurrency).
+        #[arg(long, default_value_t = 8)]
+        count: usize,
+    },
+    /// List the media files an upload of a directory would pick up, without contacting
+    /// the server, and optionally find duplicates among them.
+    Scan {
+        /// Directory to scan for media files.
+        directory: PathBuf,
+
+        /// Whether to scan subdirectories recursively.
+        #[arg(short, long, default_value_t = true)]
+        recursive: bool,
+
+        /// Descend at most this many folder levels below the directory (0: only its own files).
+        #[arg(long)]
+        max_depth: Option<usize>,
+
+        /// Don't descend into other filesystems mounted below the directory.
+        #[arg(long, default_value_t = false)]
+        one_file_system: bool,
+
+        /// Report byte-identical files.
+        #[arg(long, default_value_t = false)]
+        duplicates: bool,
+
+        /// Also report visually similar JPEGs whose perceptual hashes differ in at most
+        /// this many of 64 bits (implies --duplicates).
+        #[arg(long, num_args = 0..=1, default_missing_value = "6")]
+        similar: Option<u32>,
+    },
+    /// Compare a local directory with the assets uploaded from this device: files only
+    /// found locally, assets only found on the server, and pairs whose contents differ.
+    /// Exits with an error unless every local file is on the server, e.g. before deleting
+    /// local originals.
+    Reconcile {
+        /// Directory to compare.
+        directory: PathBuf,
+
+        /// Whether to include subdirectories.
+        #[arg(short, long, default_value_t = true)]
+        recursive: bool,
+    },
+    /// Change assets already on the server.
+    Assets {
+        #[command(subcommand)]
+        command: AssetsCommands,
+    },
+    /// Download the originals of assets matching the filters, e.g. an album or everything
+    /// showing a person.
+    Download {
+        /// Directory to save the files in.
+        #[arg(short, long)]
+        output: PathBuf,
+        #[command(flatten)]
+        selection: assets::Selection,
+        /// Also write each asset's description, tags, people, favorite flag, rating and
+        /// location from the server to a sidecar next to it (`photo.jpg.xmp` or `.json`).
+        #[arg(long, value_enum)]
+        sidecar: Option<sidecar::SidecarFormat>,
+        /// How to arrange the files: all in one directory, a directory per album, or
+        /// YYYY/MM directories by capture date.
+        #[arg(long, value_enum, default_value = "flat")]
+        layout: download::Layout,
+        /// What to do when another file already has an asset's name: append the start
+        /// of the asset ID, prepend the capture date, or skip the asset.
+        #[arg(long, value_enum, default_value = "suffix")]
+        on_collision: download::Collision,
+    },
+    /// Download every original plus a manifest of albums, favorites, archive state,
+    /// descriptions and people, to keep a complete copy of the library. Re-running it
+    /// into the same directory only downloads new assets.
+    Backup {
+        /// Directory to write the backup to.
+        destination: PathBuf,
+        /// Also write each asset's metadata to a sidecar next to its original, so the
+        /// originals keep their curation without the manifest.
+        #[arg(long, value_enum)]
+        sidecar: Option<sidecar::SidecarFormat>,
+    },
+    /// Upload a backup written by `backup` and rebuild its albums, favorites, archive
+    /// state and descriptions. Assets already on the server are skipped, so it can be
+    /// re-run after an interruption.
+    Restore {
+        /// Directory holding the backup.
+        directory: PathBuf,
+    },
+    /// Polish albums on the server: covers, descriptions and sort order.
+    Album {
+        #[command(subcommand)]
+        command: AlbumCommands,
+    },
+    /// Find duplicate assets on the server.
+    Dedupe {
+        #[command(subcommand)]
+        command: DedupeCommands,
+    },
+    /// Print a shell completion script, including the names of configured users.
+    /// Regenerate it after adding or renaming users.
+    Completions {
+        /// Shell to generate the script for.
+        shell: clap_complete::Shell,
+    },
+    /// Update to the latest release from GitHub, verifying its checksum.
+    SelfUpdate {
+        /// Only report whether an update is available.
+        #[arg(long, default_value_t = false)]
+        check: bool,
+    },
+    /// Check everything between this machine and the server step by step (config, name
+    /// resolution, TCP, TLS, ping, the key, uploading and reverse proxy body size limits)
+    /// and explain how to fix what fails. Probe uploads are moved to the trash.
+    Doctor {
+        /// Size in MiB of the upload that checks the body size limit.
+        #[arg(long, default_value_t = 100)]
+        body_size: u64,
+    },
+    /// Manage the configuration file.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
     },
     /// Manage stored user credentials and server URLs.
     User {
         #[command(subcommand)]
         command: UserCommands,
     },
+    /// Server administration, using an admin's API key.
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommands,
+    },
+}
+
+/// Photo managers that can be imported from.
+#[derive(Subcommand)]
+enum ImportSource {
+    /// Import an Apple Photos library (.photoslibrary) directly, without exporting first.
+    ApplePhotos {
+        /// Path to the .photoslibrary bundle.
+        library: PathBuf,
+        /// Also import photos hidden in Photos.
+        #[arg(long, default_value_t = false)]
+        include_hidden: bool,
+    },
+    /// Import an iCloud Photos export (Apple's "Download originals" archive or an icloudpd tree).
+    Icloud {
+        /// Directory containing the extracted export.
+        directory: PathBuf,
+        /// Also upload edited variants (IMG_E1234) next to the originals.
+        #[arg(long, default_value_t = false)]
+        include_edited: bool,
+        /// Also import photos marked hidden in the export.
+        #[arg(long, default_value_t = false)]
+        include_hidden: bool,
+    },
+    /// Import a Flickr account export (media folders plus JSON metadata).
+    Flickr {
+        /// Directory containing all extracted parts of the export.
+        directory: PathBuf,
+    },
+    /// Import a Lightroom Classic catalog (.lrcat), mapping collections to albums.
+    Lightroom {
+        /// Path to the .lrcat catalog file.
+        catalog: PathBuf,
+        /// Only import photos flagged as picks.
+        #[arg(long, default_value_t = false)]
+        picked_only: bool,
+        /// Mark photos rated at least this many stars as favorites.
+        #[arg(long, value_parser = clap::value_parser!(i32).range(1..=5))]
+        favorite_rating: Option<i32>,
+    },
+    /// Migrate a Synology Photos / Moments folder, skipping @eaDir thumbnails and recycle bins.
+    Synology {
+        /// Shared folder to import (e.g. /volume1/photo).
+        directory: PathBuf,
+        /// Create an album for each folder, named after its path.
+        #[arg(long, default_value_t = false)]
+        folder_albums: bool,
+        /// Apply embedded IPTC/XMP keywords (e.g. written by Synology Photos) as tags.
+        #[arg(long, default_value_t = false)]
+        import_tags: bool,
+    },
+    /// Stream a OneDrive folder (the camera roll by default) to Immich without downloading it first.
+    Onedrive {
+        /// Application (client) ID of an Azure app registration allowing public client flows.
+        #[arg(long, env = "ONEDRIVE_CLIENT_ID")]
+        client_id: String,
+        /// Azure AD tenant: "consumers" for personal accounts, "organizations" or a tenant ID for work accounts.
+        #[arg(long, default_value = "consumers")]
+        tenant: String,
+        /// OneDrive folder to import (e.g. "Pictures/Camera Roll"). Defaults to the camera roll.
+        #[arg(long)]
+        folder: Option<String>,
+    },
+    /// Stream a Dropbox folder (Camera Uploads by default) to Immich, continuing where the last run stopped.
+    Dropbox {
+        /// Dropbox access token (from an app in the Dropbox App Console with files.content.read).
+        #[arg(long, env = "DROPBOX_TOKEN")]
+        token: String,
+        /// Dropbox folder to import.
+        #[arg(long, default_value = "/Camera Uploads")]
+        folder: String,
+        /// Ignore the saved cursor and list the whole folder again.
+        #[arg(long, default_value_t = false)]
+        full: bool,
+    },
+    /// Import a digiKam-managed tree using its database (digikam4.db).
+    Digikam {
+        /// Path to the digikam4.db database file.
+        database: PathBuf,
+        /// Mark photos rated at least this many stars as favorites.
+        #[arg(long, value_parser = clap::value_parser!(i32).range(1..=5))]
+        favorite_rating: Option<i32>,
+    },
+}
+
+/// Upload defaults applied whenever a user is selected.
+#[derive(Args)]
+struct UserDefaultsArgs {
+    /// Album uploads go to when no --album is given ("" to clear).
+    #[arg(long)]
+    album: Option<String>,
+    /// Upload into the archive by default.
+    #[arg(long)]
+    archive: Option<bool>,
+    /// Device ID reported to the server ("" to clear).
+    #[arg(long)]
+    device_id: Option<String>,
+}
+
+impl UserDefaultsArgs {
+    /// Applies the given defaults to a user; empty strings clear a setting.
+    fn apply(self, user: &mut UserConfig) {
+        if let Some(album) = self.album {
+            user.album = Some(album).filter(|a| !a.is_empty());
+        }
+        if let Some(archive) = self.archive {
+            user.archive = archive;
+        }
+        if let Some(device_id) = self.device_id {
+            user.device_id = Some(device_id).filter(|d| !d.is_empty());
+        }
+    }
+}
+
+/// Subcommands for the configuration file.
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Encrypt the config file with a passphrase (read from IMMICH_CONFIG_PASSPHRASE or
+    /// prompted), which is then needed to use it.
+    Encrypt,
+    /// Store the config file in plain text again.
+    Decrypt,
+    /// Check the config file, state directory, servers and keys, and explain any problems.
+    #[command(alias = "validate")]
+    Doctor,
+}
+
+/// Subcommands for assets on the server.
+#[derive(Subcommand)]
+enum AssetsCommands {
+    /// Set the description, date, location, favorite or archived flag of one asset, or
+    /// of every asset matching the filters.
+    #[command(group(
+        clap::ArgGroup::new("changes")
+            .required(true)
+            .multiple(true)
+            .args(["description", "date", "location", "favorite", "archived"])
+    ))]
+    Update {
+        #[command(flatten)]
+        selection: assets::Selection,
+        /// New description ("" to clear).
+        #[arg(long)]
+        description: Option<String>,
+        /// New capture date, as RFC 3339 or local time (e.g. "2019-07-14 18:30").
+        #[arg(long, value_parser = assets::parse_date)]
+        date: Option<DateTime<FixedOffset>>,
+        /// New location as "latitude,longitude" (e.g. 48.8584,2.2945).
+        #[arg(long, value_parser = assets::parse_location, allow_hyphen_values = true)]
+        location: Option<(f64, f64)>,
+        /// Mark as favorite or not.
+        #[arg(long)]
+        favorite: Option<bool>,
+        /// Move to the archive or back to the timeline.
+        #[arg(long)]
+        archived: Option<bool>,
+    },
+    /// Mark every asset matching the filters as favorite.
+    Favorite {
+        #[command(flatten)]
+        selection: assets::Selection,
+        /// Remove the favorite mark instead.
+        #[arg(long, default_value_t = false)]
+        remove: bool,
+    },
+    /// Move every asset matching the filters to the archive.
+    Archive {
+        #[command(flatten)]
+        selection: assets::Selection,
+        /// Move them back to the timeline instead.
+        #[arg(long, default_value_t = false)]
+        remove: bool,
+    },
+    /// Move every asset matching the filters to the trash. Only lists them unless --yes
+    /// is given.
+    Delete {
+        #[command(flatten)]
+        selection: assets::Selection,
+        /// Really delete the listed assets.
+        #[arg(long, default_value_t = false)]
+        yes: bool,
+        /// Delete permanently instead of moving to the trash.
+        #[arg(long, default_value_t = false)]
+        permanent: bool,
+    },
+    /// Download the thumbnail or preview the server generated for one asset, or for every
+    /// asset matching the filters.
+    Thumbnail {
+        #[command(flatten)]
+        selection: assets::Selection,
+        /// Which image to download.
+        #[arg(long, value_enum, default_value = "thumbnail")]
+        size: download::ThumbnailSize,
+        /// Directory to save the images in, or "-" to write a single asset's image to stdout.
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+}
+
+/// Subcommands for albums on the server.
+#[derive(Subcommand)]
+enum AlbumCommands {
+    /// Set the cover of albums to their earliest photo, or to a given asset.
+    SetCover {
+        /// Names of the albums.
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        albums: Vec<String>,
+        /// Change every album.
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// ID of the asset to use instead of the earliest photo.
+        #[arg(long, conflicts_with = "all")]
+        asset: Option<String>,
+    },
+    /// Set the description of an album.
+    Describe {
+        /// Name of the album.
+        album: String,
+        /// New description ("" to clear).
+        description: String,
+    },
+    /// Set the order albums show their assets in.
+    Sort {
+        /// Names of the albums.
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        albums: Vec<String>,
+        /// Change every album.
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Which assets come first.
+        #[arg(long, value_enum)]
+        order: albums::AlbumOrder,
+    },
+}
+
+/// Subcommands for finding duplicates.
+#[derive(Subcommand)]
+enum DedupeCommands {
+    /// List groups of identical assets (same checksum) and of assets the server found to
+    /// be visually similar, with the space that deleting all but one of each would free.
+    Report {
+        /// Only report byte-identical assets.
+        #[arg(long, default_value_t = false)]
+        exact: bool,
+    },
+}
+
+/// Subcommands for server administration.
+#[derive(Subcommand)]
+enum AdminCommands {
+    /// Manage the accounts on the server.
+    User {
+        #[command(subcommand)]
+        command: AdminUserCommands,
+    },
+}
+
+/// Subcommands for accounts on the server.
+#[derive(Subcommand)]
+enum AdminUserCommands {
+    /// Create an account. The password is read from IMMICH_NEW_USER_PASSWORD or prompted,
+    /// and must be changed at first login in the web app.
+    Create {
+        /// Email address to log in with.
+        email: String,
+        /// Display name.
+        #[arg(long)]
+        name: String,
+        /// Storage quota in GiB (unlimited if not given).
+        #[arg(long)]
+        quota_gb: Option<u64>,
+        /// Also create an API key for the account and save it as a configured user of
+        /// this name.
+        #[arg(long, value_name = "NAME")]
+        save: Option<String>,
+    },
+    /// List the accounts on the server with their storage use.
+    List,
+    /// Disable an account. The server keeps it restorable until it purges deleted users.
+    Disable {
+        /// Email address of the account.
+        email: String,
+    },
+    /// Restore an account disabled with `disable`.
+    Enable {
+        /// Email address of the account.
+        email: String,
+    },
 }
 
 /// Subcommands for user management.
@@ -81,6 +827,8 @@ enum UserCommands {
         /// Whether to set this as the default user.
         #[arg(short, long, default_value_t = false)]
         default: bool,
+        #[command(flatten)]
+        defaults: UserDefaultsArgs,
     },
     /// List all configured users.
     List,
@@ -94,286 +842,1033 @@ enum UserCommands {
         /// Name of the user to set as default.
         name: String,
     },
+    /// Change the server URL, API key or upload defaults of a user, keeping it the default
+    /// if it was.
+    #[command(group(
+        clap::ArgGroup::new("changes")
+            .required(true)
+            .multiple(true)
+            .args(["server", "key", "album", "archive", "device_id"])
+    ))]
+    Edit {
+        /// Name of the user to change.
+        name: String,
+        /// New Immich server URL.
+        #[arg(short, long)]
+        server: Option<String>,
+        /// New Immich API key.
+        #[arg(short, long)]
+        key: Option<String>,
+        #[command(flatten)]
+        defaults: UserDefaultsArgs,
+    },
+    /// Rename a user configuration, keeping it the default if it was.
+    Rename {
+        /// Current name of the user.
+        old: String,
+        /// New name for the user.
+        new: String,
+    },
+    /// Export users to a file (or stdout) to move them to another machine.
+    Export {
+        /// Users to export (default: all).
+        names: Vec<String>,
+        /// File to write to instead of stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Encrypt the export with a passphrase (read from IMMICH_EXPORT_PASSPHRASE or prompted).
+        #[arg(short, long, default_value_t = false)]
+        encrypt: bool,
+    },
+    /// Import users from a file written by `user export`.
+    Import {
+        /// File to read (encrypted exports are detected automatically).
+        file: PathBuf,
+        /// Replace existing users with the same name instead of skipping them.
+        #[arg(long, default_value_t = false)]
+        overwrite: bool,
+    },
+    /// Check that stored API keys are still accepted, and show their owner and permissions.
+    Check {
+        /// User to check (default: all configured users).
+        name: Option<String>,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     env_logger::init();
     let cli = Cli::parse();
-    let mut config = Config::load()?;
+    let exporter = telemetry::init(cli.trace, cli.otlp_endpoint.clone());
+    let result = run(cli).await;
+    if let Some(exporter) = exporter
+        && let Err(e) = exporter.export().await
+    {
+        eprintln!("{:#}", e);
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            logging::log(Level::Error, &format!("Error: {:#}", e));
+            eprintln!("Error: {:?}", e);
+            exit::code(&e)
+        }
+    }
+}
+
+async fn run(mut cli: Cli) -> Result<()> {
+    Config::set_paths(cli.config.clone(), cli.state_dir.clone());
+    progress::set_mode(cli.progress);
+    logging::init(cli.log_target, cli.log_file.as_deref()).context(ExitKind::Config)?;
+    if let Commands::SelfUpdate { check } = cli.command {
+        return self_update::self_update(check).await;
+    }
+    // The doctor has to run even when the config file doesn't load.
+    if let Commands::Config {
+        command: ConfigCommands::Doctor,
+    } = cli.command
+    {
+        return doctor::run().await;
+    }
+    if let Commands::Doctor { body_size } = cli.command {
+        let mut report = doctor::Report::default();
+        if let Some(config) = doctor::check_config(&mut report)? {
+            let client = client_for(&cli.connection, &config)?;
+            let http = cli.connection.http_config(&config);
+            doctor::check_connection(&mut report, &client, &http, body_size << 20).await?;
+        }
+        return report.finish();
+    }
+    let mut config = Config::load().context(ExitKind::Config)?;
+    filename_date::set_rules(&config.date_rules)
+        .context("Invalid date rule in config")
+        .context(ExitKind::Config)?;
+
+    let profile = match &cli.profile {
+        Some(name) => config
+            .profiles
+            .get(name)
+            .cloned()
+            .with_context(|| format!("Profile '{}' not found in config", name))
+            .context(ExitKind::Config)?,
+        None => Profile::default(),
+    };
+    if cli.connection.server.is_none() && cli.connection.share_key.is_none() {
+        cli.connection.user = cli.connection.user.or(profile.user.clone());
+    }
+    let concurrent = cli.concurrent.or(profile.concurrent).unwrap_or(10);
+    let user_defaults = cli.connection.selected_user(&config).cloned();
+    let default_visibility = user_defaults
+        .as_ref()
+        .is_some_and(|u| u.archive)
+        .then_some(Visibility::Archive);
+    let device_id = user_defaults.as_ref().and_then(|u| u.device_id.clone());
 
     match cli.command {
-        Commands::User { command } => match command {
-            UserCommands::Add {
-                name,
-                server,
-                key,
-                default,
-            } => {
-                config.users.insert(
-                    name.clone(),
-                    UserConfig {
-                        api_key: key,
-                        server_url: server,
-                    },
-                );
-                if default || config.current_user.is_none() {
-                    config.current_user = Some(name.clone());
+        Commands::Completions { shell } => {
+            let mut names: Vec<&str> = config.users.keys().map(String::as_str).collect();
+            names.sort();
+            let mut command = completion_command(&names);
+            clap_complete::generate(
+                shell,
+                &mut command,
+                env!("CARGO_PKG_NAME"),
+                &mut std::io::stdout(),
+            );
+        }
+        Commands::SelfUpdate { .. } => unreachable!("handled before loading the config"),
+        Commands::Doctor { .. } => unreachable!("handled before loading the config"),
+        Commands::Config { command } => match command {
+            ConfigCommands::Encrypt => {
+                if config.passphrase.is_some() {
+                    println!("The config file is already encrypted.");
+                    return Ok(());
                 }
+                config.passphrase = Some(crypto::new_passphrase(
+                    config::PASSPHRASE_ENV,
+                    "New config passphrase",
+                )?);
                 config.save()?;
-                println!("User '{}' added successfully.", name);
+                println!("Config file encrypted.");
             }
-            UserCommands::List => {
-                if config.users.is_empty() {
-                    println!("No users configured.");
-                } else {
-                    println!("Users:");
-                    for (name, user) in &config.users {
-                        let current = if config.current_user.as_ref() == Some(name) {
-                            "*"
-                        } else {
-                            " "
-                        };
-                        println!(" {} {}: {}", current, name, user.server_url);
-                    }
+            ConfigCommands::Doctor => unreachable!("handled before loading the config"),
+            ConfigCommands::Decrypt => {
+                if config.passphrase.take().is_none() {
+                    println!("The config file is not encrypted.");
+                    return Ok(());
                 }
+                config.save()?;
+                println!("Config file decrypted.");
             }
-            UserCommands::Delete { name } => {
-                if config.users.remove(&name).is_some() {
-                    if config.current_user.as_ref() == Some(&name) {
-                        config.current_user = None;
+        },
+        Commands::User { command } => {
+            match command {
+                UserCommands::Add {
+                    name,
+                    server,
+                    key,
+                    default,
+                    defaults,
+                } => {
+                    let mut user = UserConfig {
+                        api_key: key,
+                        server_url: server,
+                        album: None,
+                        archive: false,
+                        device_id: None,
+                    };
+                    defaults.apply(&mut user);
+                    config.users.insert(name.clone(), user);
+                    if default || config.current_user.is_none() {
+                        config.current_user = Some(name.clone());
                     }
                     config.save()?;
-                    println!("User '{}' deleted.", name);
-                } else {
-                    anyhow::bail!("User '{}' not found.", name);
+                    println!("User '{}' added successfully.", name);
                 }
-            }
-            UserCommands::Default { name } => {
-                if config.users.contains_key(&name) {
-                    config.current_user = Some(name.clone());
+                UserCommands::List => {
+                    if config.users.is_empty() {
+                        println!("No users configured.");
+                    } else {
+                        println!("Users:");
+                        for (name, user) in &config.users {
+                            let current = if config.current_user.as_ref() == Some(name) {
+                                "*"
+                            } else {
+                                " "
+                            };
+                            println!(" {} {}: {}", current, name, user.server_url);
+                        }
+                    }
+                }
+                UserCommands::Delete { name } => {
+                    if config.users.remove(&name).is_some() {
+                        if config.current_user.as_ref() == Some(&name) {
+                            config.current_user = None;
+                        }
+                        config.save()?;
+                        println!("User '{}' deleted.", name);
+                    } else {
+                        return Err(
+                            anyhow::anyhow!("User '{}' not found.", name).context(ExitKind::Config)
+                        );
+                    }
+                }
+                UserCommands::Default { name } => {
+                    if config.users.contains_key(&name) {
+                        config.current_user = Some(name.clone());
+                        config.save()?;
+                        println!("Default user set to '{}'.", name);
+                    } else {
+                        return Err(
+                            anyhow::anyhow!("User '{}' not found.", name).context(ExitKind::Config)
+                        );
+                    }
+                }
+                UserCommands::Edit {
+                    name,
+                    server,
+                    key,
+                    defaults,
+                } => {
+                    let Some(user) = config.users.get_mut(&name) else {
+                        return Err(
+                            anyhow::anyhow!("User '{}' not found.", name).context(ExitKind::Config)
+                        );
+                    };
+                    if let Some(server) = server {
+                        user.server_url = server;
+                    }
+                    if let Some(key) = key {
+                        user.api_key = key;
+                    }
+                    defaults.apply(user);
                     config.save()?;
-                    println!("Default user set to '{}'.", name);
-                } else {
-                    anyhow::bail!("User '{}' not found.", name);
+                    println!("User '{}' updated.", name);
+                }
+                UserCommands::Rename { old, new } => {
+                    if config.users.contains_key(&new) {
+                        return Err(anyhow::anyhow!("User '{}' already exists.", new)
+                            .context(ExitKind::Config));
+                    }
+                    let Some(user) = config.users.remove(&old) else {
+                        return Err(
+                            anyhow::anyhow!("User '{}' not found.", old).context(ExitKind::Config)
+                        );
+                    };
+                    config.users.insert(new.clone(), user);
+                    if config.current_user.as_ref() == Some(&old) {
+                        config.current_user = Some(new.clone());
+                    }
+                    config.save()?;
+                    println!("User '{}' renamed to '{}'.", old, new);
+                }
+                UserCommands::Export {
+                    names,
+                    output,
+                    encrypt,
+                } => {
+                    if let Some(missing) = names.iter().find(|n| !config.users.contains_key(*n)) {
+                        return Err(anyhow::anyhow!("User '{}' not found.", missing)
+                            .context(ExitKind::Config));
+                    }
+                    let users = config
+                        .users
+                        .iter()
+                        .filter(|(name, _)| names.is_empty() || names.contains(name))
+                        .map(|(name, user)| (name.clone(), user.clone()))
+                        .collect();
+                    let export = Config {
+                        current_user: config
+                            .current_user
+                            .clone()
+                            .filter(|name| names.is_empty() || names.contains(name)),
+                        users,
+                        ..Config::default()
+                    };
+                    let mut content = toml::to_string_pretty(&export)?;
+                    if encrypt {
+                        let passphrase =
+                            crypto::new_passphrase(EXPORT_PASSPHRASE_ENV, "Export passphrase")?;
+                        content = crypto::encrypt(&content, &passphrase)? + "\n";
+                    }
+                    match output {
+                        Some(path) => {
+                            std::fs::write(&path, content)
+                                .with_context(|| format!("Failed to write {:?}", path))?;
+                            println!("Exported {} users to {:?}.", export.users.len(), path);
+                        }
+                        None => print!("{}", content),
+                    }
+                }
+                UserCommands::Import { file, overwrite } => {
+                    let mut content = std::fs::read_to_string(&file)
+                        .with_context(|| format!("Failed to read {:?}", file))?;
+                    if crypto::is_encrypted(&content) {
+                        let passphrase =
+                            crypto::passphrase(EXPORT_PASSPHRASE_ENV, "Export passphrase")?;
+                        content = crypto::decrypt(&content, &passphrase)?;
+                    }
+                    let imported: Config = toml::from_str(&content)
+                        .with_context(|| format!("{:?} is not a user export", file))?;
+
+                    let mut added = 0;
+                    for (name, user) in imported.users {
+                        if config.users.contains_key(&name) && !overwrite {
+                            println!(
+                                "Skipping existing user '{}' (use --overwrite to replace).",
+                                name
+                            );
+                            continue;
+                        }
+                        config.users.insert(name, user);
+                        added += 1;
+                    }
+                    if config.current_user.is_none() {
+                        config.current_user = imported
+                            .current_user
+                            .filter(|name| config.users.contains_key(name));
+                    }
+                    config.save()?;
+                    println!("Imported {} users.", added);
+                }
+                UserCommands::Check { name } => {
+                    let mut names: Vec<&String> = match &name {
+                        Some(name) if !config.users.contains_key(name) => {
+                            return Err(anyhow::anyhow!("User '{}' not found.", name)
+                                .context(ExitKind::Config));
+                        }
+                        Some(name) => vec![name],
+                        None => config.users.keys().collect(),
+                    };
+                    if names.is_empty() {
+                        println!("No users configured.");
+                    }
+                    names.sort();
+
+                    let mut invalid = 0;
+                    for name in names {
+                        let user = &config.users[name];
+                        let client = ImmichClient::new(
+                            api::http_client(&config.http, &user.server_url)?,
+                            &user.server_url,
+                            &user.api_key,
+                        );
+                        match doctor::check_user(&client).await {
+                            Ok(report) => println!("  {}: {}", name, report),
+                            Err(e) => {
+                                invalid += 1;
+                                println!("  {}: {:#}", name, e);
+                            }
+                        }
+                    }
+                    if invalid > 0 {
+                        return Err(anyhow::anyhow!("{} of the checked keys failed", invalid)
+                            .context(ExitKind::Auth));
+                    }
                 }
             }
-        },
+        }
         Commands::Upload {
             directory,
             recursive,
-            skip_existing: _,
+            skip_existing,
+            on_duplicate,
+            favorite_rating,
+            import_tags,
+            import_titles,
+            finder_tags,
+            album_mirror,
+            album_separator,
+            album,
+            pick_album,
+            share_with,
+            share_role,
+            visibility,
+            verify,
+            split_motion_photos,
+            screenshots,
+            screenshot_album,
+            max_depth,
+            one_file_system,
+            min_age,
+            skip_open,
+            order,
+            priority,
+            interactive,
+            wait_for_server,
+            no_quota_check,
+            process,
+            wait_for_processing,
+            yes,
+            resume,
+            retry_failed,
+            files_from,
+            manifest,
+            write_failed,
         } => {
-            let (server_url, api_key) = if let (Some(s), Some(k)) = (cli.server, cli.key) {
-                (s, k)
-            } else if let Some(user_name) = cli.user {
-                let user = config
-                    .users
-                    .get(&user_name)
-                    .with_context(|| format!("User '{}' not found in config", user_name))?;
-                (user.server_url.clone(), user.api_key.clone())
+            let client = connect(&cli.connection, &config).await?;
+            let album = if pick_album || album.as_deref() == Some("") {
+                Some(picker::pick_album(&client).await?)
             } else {
-                let (_, user) = config.get_current_user().context(
-                    "No current user set and no server/key or --user provided. Use 'rimmich-uploader user add' to configure one.",
-                )?;
-                (user.server_url.clone(), user.api_key.clone())
+                album
+            };
+            let options = UploadOptions {
+                favorite_rating: favorite_rating.or(profile.favorite_rating),
+                import_tags: import_tags || profile.import_tags,
+                finder_tags,
+                import_titles,
+                album_mirror: (album_mirror || profile.album_mirror).then(|| {
+                    album_separator
+                        .or(profile.album_separator)
+                        .unwrap_or_else(|| "/".to_string())
+                }),
+                album: album
+                    .or(profile.album)
+                    .or(user_defaults.and_then(|u| u.album)),
+                share_with: if share_with.is_empty() {
+                    profile.share_with
+                } else {
+                    share_with
+                },
+                share_role: match share_role {
+                    Some(role) => role,
+                    None => parse_profile_value(profile.share_role.as_deref())?.unwrap_or_default(),
+                },
+                visibility: match visibility {
+                    Some(visibility) => Some(visibility),
+                    None => {
+                        parse_profile_value(profile.visibility.as_deref())?.or(default_visibility)
+                    }
+                },
+                verify: verify || profile.verify,
+                skip_existing: skip_existing || on_duplicate != existing::DuplicatePolicy::Skip,
+                on_duplicate,
+                split_motion_photos: split_motion_photos || profile.split_motion_photos,
+                screenshots: match screenshots {
+                    Some(action) => Some(action),
+                    None => parse_profile_value(profile.screenshots.as_deref())?,
+                },
+                screenshot_album: screenshot_album.or(profile.screenshot_album),
+                max_depth: max_depth.or(profile.max_depth),
+                one_file_system: one_file_system || profile.one_file_system,
+                min_age: match min_age {
+                    Some(age) => Some(age),
+                    None => profile
+                        .min_age
+                        .as_deref()
+                        .map(parse_duration)
+                        .transpose()
+                        .map_err(|e| anyhow::anyhow!("Invalid profile value: {}", e))
+                        .context(ExitKind::Config)?,
+                },
+                order: match order {
+                    Some(order) => Some(order),
+                    None => parse_profile_value(profile.order.as_deref())?,
+                },
+                priority: match priority {
+                    Some(priority) => Some(priority),
+                    None => parse_profile_value(profile.priority.as_deref())?,
+                },
+                partial_files: config.partial_files.clone(),
+                skip_open: skip_open || profile.skip_open,
+                incomplete: false,
+                interactive: (interactive || profile.interactive) && !yes,
+                wait_for_server: wait_for_server || profile.wait_for_server,
+                skip_quota_check: no_quota_check,
+                process,
+                wait_for_processing,
+                hooks: config.hooks.clone(),
+                resume,
+                failed_list: write_failed,
+                device_id,
             };
 
-            let server_url = server_url.trim_end_matches('/').to_string();
-            let client = reqwest::Client::new();
+            if let Some(manifest) = manifest {
+                plan::upload_plan(client, &manifest, concurrent, options).await?;
+            } else if let Some(list) = files_from {
+                upload::upload_file_list(client, &list, concurrent, options).await?;
+            } else if retry_failed {
+                upload::retry_failed(client, directory.as_deref(), concurrent, options).await?;
+            } else if let Some(directory) = directory {
+                upload::upload_directory(client, &directory, recursive, concurrent, options)
+                    .await?;
+            }
+        }
+        Commands::Plan {
+            directory,
+            output,
+            recursive,
+            album,
+            album_mirror,
+            album_separator,
+            max_depth,
+            one_file_system,
+        } => {
+            let client = connect(&cli.connection, &config).await?;
+            let options = UploadOptions {
+                album_mirror: (album_mirror || profile.album_mirror).then(|| {
+                    album_separator
+                        .or(profile.album_separator)
+                        .unwrap_or_else(|| "/".to_string())
+                }),
+                album: album
+                    .or(profile.album)
+                    .or(user_defaults.and_then(|u| u.album)),
+                max_depth: max_depth.or(profile.max_depth),
+                one_file_system: one_file_system || profile.one_file_system,
+                partial_files: config.partial_files.clone(),
+                ..UploadOptions::default()
+            };
+            plan::write_plan(&client, &directory, recursive, &output, options).await?;
+        }
+        Commands::Import { source } => {
+            let mut options = UploadOptions {
+                visibility: default_visibility,
+                device_id,
+                hooks: config.hooks.clone(),
+                ..UploadOptions::default()
+            };
+            let items = match source {
+                ImportSource::ApplePhotos {
+                    library,
+                    include_hidden,
+                } => import::apple_photos::read_library(&library, include_hidden)?,
+                ImportSource::Icloud {
+                    directory,
+                    include_edited,
+                    include_hidden,
+                } => import::icloud::read_export(&directory, include_edited, include_hidden)?,
+                ImportSource::Flickr { directory } => import::flickr::read_export(&directory)?,
+                ImportSource::Lightroom {
+                    catalog,
+                    picked_only,
+                    favorite_rating,
+                } => {
+                    options.favorite_rating = favorite_rating;
+                    import::lightroom::read_catalog(&catalog, picked_only)?
+                }
+                ImportSource::Synology {
+                    directory,
+                    folder_albums,
+                    import_tags,
+                } => {
+                    options.import_tags = import_tags;
+                    import::synology::read_folder(&directory, folder_albums)?
+                }
+                ImportSource::Onedrive {
+                    client_id,
+                    tenant,
+                    folder,
+                } => {
+                    let client = connect(&cli.connection, &config).await?;
+                    let onedrive = source::onedrive::OneDrive::sign_in(
+                        reqwest::Client::new(),
+                        &client_id,
+                        &tenant,
+                    )
+                    .await?;
+                    let files = onedrive.list(folder.as_deref()).await?;
+                    if files.is_empty() {
+                        progress::note("Nothing to import.");
+                        return Ok(());
+                    }
+                    let total = files.len();
+                    let failed =
+                        source::upload_remote_files(&client, &onedrive, files, concurrent).await?;
+                    return exit::check_failures(failed, total);
+                }
+                ImportSource::Dropbox {
+                    token,
+                    folder,
+                    full,
+                } => {
+                    let client = connect(&cli.connection, &config).await?;
+                    let dropbox = source::dropbox::Dropbox::new(reqwest::Client::new(), &token);
+                    let mut cursors = source::dropbox::CursorStore::load()?;
+                    let cursor = if full { None } else { cursors.get(&folder) };
+                    let listing = dropbox.list(&folder, cursor).await?;
 
-            // Verify connectivity
-            check_connection(&client, &server_url)
-                .await
-                .context("Failed to connect to Immich server")?;
+                    let total = listing.files.len();
+                    let failed = if listing.files.is_empty() {
+                        progress::note(format!("No new files in '{}'.", folder));
+                        0
+                    } else {
+                        source::upload_remote_files(&client, &dropbox, listing.files, concurrent)
+                            .await?
+                    };
+                    // Only move the cursor forward when everything made it, so failures are retried.
+                    if failed == 0 {
+                        cursors.save(&folder, listing.cursor)?;
+                    } else {
+                        progress::note(format!(
+                            "{} files failed; the next run will list them again.",
+                            failed
+                        ));
+                    }
+                    return exit::check_failures(failed, total);
+                }
+                ImportSource::Digikam {
+                    database,
+                    favorite_rating,
+                } => {
+                    options.favorite_rating = favorite_rating;
+                    import::digikam::read_database(&database)?
+                }
+            };
+            if items.is_empty() {
+                progress::note("Nothing to import.");
+                return Ok(());
+            }
 
-            upload_directory(
-                client,
-                &server_url,
-                &api_key,
+            let client = connect(&cli.connection, &config).await?;
+            upload::upload_items(client, items, concurrent, options, None).await?;
+        }
+        Commands::Migrate { from, to } => {
+            let source = connect(&cli.connection.for_user(&from), &config)
+                .await
+                .with_context(|| format!("Source user '{}'", from))?;
+            let target = connect(&cli.connection.for_user(&to), &config)
+                .await
+                .with_context(|| format!("Target user '{}'", to))?;
+            migrate::migrate(&source, &target, concurrent).await?;
+        }
+        Commands::Scan {
+            directory,
+            recursive,
+            duplicates,
+            similar,
+            max_depth,
+            one_file_system,
+        } => scan::scan(
+            &directory,
+            recursive,
+            duplicates,
+            similar,
+            &config.partial_files,
+            max_depth,
+            one_file_system,
+        )?,
+        Commands::Reconcile {
+            directory,
+            recursive,
+        } => {
+            let client = connect(&cli.connection, &config).await?;
+            reconcile::reconcile(
+                &client,
                 &directory,
                 recursive,
-                cli.concurrent,
+                &config.partial_files,
+                device_id.as_deref().unwrap_or(upload::DEVICE_ID),
             )
             .await?;
         }
+        Commands::Assets { command } => match command {
+            AssetsCommands::Update {
+                selection,
+                description,
+                date,
+                location,
+                favorite,
+                archived,
+            } => {
+                let client = connect(&cli.connection, &config).await?;
+                let changes = assets::AssetChanges {
+                    description,
+                    date,
+                    location,
+                    favorite,
+                    archived,
+                };
+                assets::update(&client, &selection, &changes).await?;
+            }
+            AssetsCommands::Favorite { selection, remove } => {
+                let client = connect(&cli.connection, &config).await?;
+                let changes = assets::AssetChanges {
+                    favorite: Some(!remove),
+                    ..assets::AssetChanges::default()
+                };
+                assets::update(&client, &selection, &changes).await?;
+            }
+            AssetsCommands::Archive { selection, remove } => {
+                let client = connect(&cli.connection, &config).await?;
+                let changes = assets::AssetChanges {
+                    archived: Some(!remove),
+                    ..assets::AssetChanges::default()
+                };
+                assets::update(&client, &selection, &changes).await?;
+            }
+            AssetsCommands::Delete {
+                selection,
+                yes,
+                permanent,
+            } => {
+                let client = connect(&cli.connection, &config).await?;
+                assets::delete(&client, &selection, yes, permanent).await?;
+            }
+            AssetsCommands::Thumbnail {
+                selection,
+                size,
+                output,
+            } => {
+                let client = connect(&cli.connection, &config).await?;
+                download::thumbnails(&client, &selection, &output, size).await?;
+            }
+        },
+        Commands::Download {
+            output,
+            selection,
+            sidecar,
+            layout,
+            on_collision,
+        } => {
+            let client = connect(&cli.connection, &config).await?;
+            download::download(
+                &client,
+                &selection,
+                &output,
+                concurrent,
+                sidecar,
+                layout,
+                on_collision,
+            )
+            .await?;
+        }
+        Commands::Backup {
+            destination,
+            sidecar,
+        } => {
+            let client = connect(&cli.connection, &config).await?;
+            backup::backup(&client, &destination, concurrent, sidecar).await?;
+        }
+        Commands::Restore { directory } => {
+            let client = connect(&cli.connection, &config).await?;
+            restore::restore(&client, &directory, concurrent).await?;
+        }
+        Commands::Admin {
+            command: AdminCommands::User { command },
+        } => {
+            let client = connect(&cli.connection, &config).await?;
+            match command {
+                AdminUserCommands::Create {
+                    email,
+                    name,
+                    quota_gb,
+                    save,
+                } => {
+                    let password = crypto::new_passphrase(
+                        "IMMICH_NEW_USER_PASSWORD",
+                        "Password for the new user",
+                    )?;
+                    admin::create_user(
+                        &client,
+                        &mut config,
+                        &email,
+                        &name,
+                        &password,
+                        quota_gb,
+                        save.as_deref(),
+                    )
+                    .await?;
+                }
+                AdminUserCommands::List => admin::list_users(&client).await?,
+                AdminUserCommands::Disable { email } => {
+                    admin::set_disabled(&client, &email, true).await?
+                }
+                AdminUserCommands::Enable { email } => {
+                    admin::set_disabled(&client, &email, false).await?
+                }
+            }
+        }
+        Commands::Album { command } => {
+            let client = connect(&cli.connection, &config).await?;
+            match command {
+                AlbumCommands::SetCover { albums, all, asset } => {
+                    for name in albums::names(&client, albums, all).await? {
+                        albums::set_cover(&client, &name, asset.as_deref()).await?;
+                    }
+                }
+                AlbumCommands::Describe { album, description } => {
+                    albums::describe(&client, &album, &description).await?
+                }
+                AlbumCommands::Sort { albums, all, order } => {
+                    for name in albums::names(&client, albums, all).await? {
+                        albums::sort(&client, &name, order).await?;
+                    }
+                }
+            }
+        }
+        Commands::Dedupe { command } => match command {
+            DedupeCommands::Report { exact } => {
+                let client = connect(&cli.connection, &config).await?;
+                dedupe::report(&client, exact).await?;
+            }
+        },
+        Commands::Benchmark {
+            sizes,
+            levels,
+            count,
+        } => {
+            let client = connect(&cli.connection, &config).await?;
+            benchmark::benchmark(&client, &sizes, &levels, count).await?;
+        }
     }
 
     Ok(())
 }
 
-/// Pings the Immich server to verify connectivity.
-async fn check_connection(client: &reqwest::Client, server_url: &str) -> Result<()> {
-    let url = format!("{}/api/server/ping", server_url);
-    let resp = client.get(&url).send().await?;
-    if !resp.status().is_success() {
-        anyhow::bail!("Server ping failed: {}", resp.status());
+impl ConnectArgs {
+    /// The same connection settings for a configured user, ignoring server/key flags.
+    fn for_user(&self, user: &str) -> Self {
+        ConnectArgs {
+            user: Some(user.to_string()),
+            server: None,
+            key: None,
+            share_key: None,
+            ..self.clone()
+        }
     }
-    let body = resp.text().await?;
-    // Immich ping returns "pong" on success.
-    if !body.contains("pong") {
-        anyhow::bail!("Unexpected response from ping: {}", body);
+
+    /// The configured user that will be connected as, if credentials don't come from flags.
+    fn selected_user<'a>(&self, config: &'a Config) -> Option<&'a UserConfig> {
+        if self.share_key.is_some() || (self.server.is_some() && self.key.is_some()) {
+            return None;
+        }
+        match &self.user {
+            Some(name) => config.users.get(name),
+            None => config.get_current_user().map(|(_, user)| user),
+        }
+    }
+
+    /// HTTP settings from the configuration file, overridden by flags.
+    fn http_config(&self, config: &Config) -> HttpConfig {
+        HttpConfig {
+            http2: self.http2 || config.http.http2,
+            pool_idle_timeout: self.pool_idle_timeout.or(config.http.pool_idle_timeout),
+            pool_max_idle: self.pool_max_idle.or(config.http.pool_max_idle),
+            proxy: self.proxy.clone().or_else(|| config.http.proxy.clone()),
+            resolve: [&self.resolve[..], &config.http.resolve[..]].concat(),
+            // A flag replaces the config file's choice rather than adding to it.
+            ipv4: self.ipv4 || (config.http.ipv4 && !self.ipv6),
+            ipv6: self.ipv6 || (config.http.ipv6 && !self.ipv4),
+        }
     }
-    Ok(())
 }
 
-/// Scans a directory for media files and uploads them concurrently.
-async fn upload_directory(
-    client: reqwest::Client,
-    server_url: &str,
-    api_key: &str,
-    directory: &Path,
-    recursive: bool,
-    concurrent: usize,
-) -> Result<()> {
-    if !directory.is_dir() {
-        anyhow::bail!("Path {:?} is not a directory", directory);
+/// Resolves the server URL and API key from flags, `--user` or the current user,
+/// and verifies the server is reachable.
+async fn connect(args: &ConnectArgs, config: &Config) -> Result<ImmichClient> {
+    let client = client_for(args, config)?;
+    // Verify connectivity, then that the key is accepted.
+    client
+        .ping()
+        .await
+        .context("Failed to connect to Immich server")
+        .context(ExitKind::Connection)?;
+    if !client.is_shared_link() {
+        client
+            .validate_key()
+            .await
+            .context("The server rejected the API key")
+            .context(ExitKind::Auth)?;
+    }
+    Ok(client)
+}
+
+/// The client for the server URL and key from flags, `--user` or the current user,
+/// without contacting the server.
+fn client_for(args: &ConnectArgs, config: &Config) -> Result<ImmichClient> {
+    let http = args.http_config(config);
+    if let Some(share_key) = &args.share_key {
+        let (link_server, share_key) = parse_share_link(share_key);
+        let server_url = args
+            .server
+            .clone()
+            .or(link_server)
+            .context("--server is required when --share-key is not a full link URL")
+            .context(ExitKind::Config)?;
+        return Ok(ImmichClient::with_share_key(
+            api::http_client(&http, &server_url)?,
+            &server_url,
+            &share_key,
+        ));
     }
 
-    println!("Scanning directory: {:?}", directory);
-    let mut files = Vec::new();
-    let walker = if recursive {
-        WalkDir::new(directory)
+    let (server_url, api_key) = if let (Some(s), Some(k)) = (&args.server, &args.key) {
+        (s.clone(), k.clone())
+    } else if let Some(user_name) = &args.user {
+        let user = config
+            .users
+            .get(user_name)
+            .with_context(|| format!("User '{}' not found in config", user_name))
+            .context(ExitKind::Config)?;
+        (user.server_url.clone(), user.api_key.clone())
     } else {
-        WalkDir::new(directory).max_depth(1)
+        let (_, user) = config
+            .get_current_user()
+            .context(
+                "No current user set and no server/key or --user provided. Use 'rimmich-uploader user add' to configure one.",
+            )
+            .context(ExitKind::Config)?;
+        (user.server_url.clone(), user.api_key.clone())
     };
 
-    // Filter files by mime type (images and videos).
-    for entry in walker.into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let path = entry.path();
-            if is_image_or_video(path) {
-                files.push(path.to_path_buf());
-            }
-        }
-    }
-
-    if files.is_empty() {
-        println!("No supported files found in {:?}", directory);
-        return Ok(());
-    }
+    Ok(ImmichClient::new(
+        api::http_client(&http, &server_url)?,
+        &server_url,
+        &api_key,
+    ))
+}
 
-    println!(
-        "Found {} files to upload. Starting upload with concurrency {}...",
-        files.len(),
-        concurrent
-    );
-
-    let m = MultiProgress::new();
-    let pb = m.add(ProgressBar::new(files.len() as u64));
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")?
-            .progress_chars("#>-"),
-    );
-
-    let client = Arc::new(client);
-    let server_url = Arc::new(server_url.to_string());
-    let api_key = Arc::new(api_key.to_string());
-    let device_id = "rimmich-uploader";
-
-    // Use a stream to process uploads concurrently with a limit.
-    let mut requests = futures::stream::iter(files)
-        .map(|path| {
-            let client = Arc::clone(&client);
-            let server_url = Arc::clone(&server_url);
-            let api_key = Arc::clone(&api_key);
-            let pb = pb.clone();
-            async move {
-                let result = upload_file(&client, &server_url, &api_key, &path, device_id).await;
-                match result {
-                    Ok(_) => {
-                        pb.inc(1);
-                    }
-                    Err(e) => {
-                        pb.println(format!("Failed to upload {:?}: {}", path, e));
-                        pb.inc(1); // Still increment but mark failure in output
-                    }
-                }
-            }
+/// The command-line definition with configured user names offered wherever a user is named.
+fn completion_command(names: &[&str]) -> clap::Command {
+    let users = clap::builder::PossibleValuesParser::new(names.iter().map(|n| n.to_string()));
+    let with_users = |arg: clap::Arg| arg.value_parser(users.clone());
+    Cli::command()
+        .mut_arg("user", with_users)
+        .mut_subcommand("user", |user| {
+            ["delete", "default", "edit", "check", "export"]
+                .into_iter()
+                .fold(user, |user, sub| {
+                    user.mut_subcommand(sub, |c| {
+                        let arg = if sub == "export" { "names" } else { "name" };
+                        c.mut_arg(arg, with_users)
+                    })
+                })
+                .mut_subcommand("rename", |c| c.mut_arg("old", with_users))
         })
-        .buffer_unordered(concurrent);
-
-    // Consume the stream.
-    while requests.next().await.is_some() {}
+        .mut_subcommand("migrate", |c| {
+            c.mut_arg("from", with_users).mut_arg("to", with_users)
+        })
+}
 
-    pb.finish_with_message("Upload complete");
+/// Parses a profile setting with the same names its command-line flag accepts.
+pub fn parse_profile_value<T: clap::ValueEnum>(value: Option<&str>) -> Result<Option<T>> {
+    value
+        .map(|v| {
+            T::from_str(v, true)
+                .map_err(|e| anyhow::anyhow!("Invalid profile value: {}", e))
+                .context(ExitKind::Config)
+        })
+        .transpose()
+}
 
-    Ok(())
+/// Parses a duration given in seconds, optionally with an `s`, `m`, `h` or `d` suffix.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", value))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => {
+            return Err(format!(
+                "invalid duration unit in '{}' (use s, m, h or d)",
+                value
+            ));
+        }
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{}' is too long", value))
 }
 
-/// Checks if a file path corresponds to a supported image or video mime type.
-fn is_image_or_video(path: &Path) -> bool {
-    let mime = mime_guess::from_path(path).first_or_octet_stream();
-    let mime_str = mime.to_string();
-    mime_str.starts_with("image/") || mime_str.starts_with("video/")
+/// Splits a shared link such as `https://photos.example.com/share/<key>` into the server
+/// URL and key. Anything that is not such a link is taken to be the bare key.
+fn parse_share_link(value: &str) -> (Option<String>, String) {
+    match value.split_once("/share/") {
+        Some((server, key)) if server.contains("://") => (
+            Some(server.to_string()),
+            key.trim_end_matches('/').to_string(),
+        ),
+        _ => (None, value.to_string()),
+    }
 }
 
-/// Uploads a single file to the Immich server with appropriate metadata.
-async fn upload_file(
-    client: &reqwest::Client,
-    server_url: &str,
-    api_key: &str,
-    path: &Path,
-    device_id: &str,
-) -> Result<()> {
-    let metadata = std::fs::metadata(path)?;
-    // Use file creation time if available, otherwise fallback to modification time or current time.
-    let created_at: DateTime<Utc> = metadata
-        .created()
-        .or_else(|_| metadata.modified())
-        .unwrap_or_else(|_| SystemTime::now())
-        .into();
-    let modified_at: DateTime<Utc> = metadata
-        .modified()
-        .unwrap_or_else(|_| SystemTime::now())
-        .into();
-
-    let filename = path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .context("Invalid filename")?;
-
-    // Create a stable deviceAssetId from path hash to avoid duplicate uploads in some contexts.
-    let mut hasher = DefaultHasher::new();
-    path.hash(&mut hasher);
-    let device_asset_id = format!("{}-{}", device_id, hasher.finish());
-
-    let file_bytes = tokio::fs::read(path).await?;
-    let part = multipart::Part::bytes(file_bytes)
-        .file_name(filename.to_string())
-        .mime_str(
-            &mime_guess::from_path(path)
-                .first_or_octet_stream()
-                .to_string(),
-        )?;
-
-    let form = multipart::Form::new()
-        .part("assetData", part)
-        .text("deviceAssetId", device_asset_id)
-        .text("deviceId", device_id.to_string())
-        .text("fileCreatedAt", created_at.to_rfc3339())
-        .text("fileModifiedAt", modified_at.to_rfc3339())
-        .text("isFavorite", "false");
-
-    let url = format!("{}/api/assets", server_url);
-
-    let response = client
-        .post(&url)
-        .header("x-api-key", api_key)
-        .multipart(form)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        // If it's 409 Conflict, it means it's already there (behavior depends on Immich API version).
-        if status == reqwest::StatusCode::CONFLICT || body.contains("already exists") {
-            return Ok(());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        for (value, seconds) in [
+            ("0", 0),
+            ("90", 90),
+            ("90s", 90),
+            ("15m", 900),
+            ("2h", 7200),
+            ("7d", 604800),
+        ] {
+            assert_eq!(
+                parse_duration(value),
+                Ok(Duration::from_secs(seconds)),
+                "{}",
+                value
+            );
         }
-        anyhow::bail!("Server returned error {}: {}", status, body);
     }
 
-    Ok(())
+    #[test]
+    fn rejects_invalid_durations() {
+        for value in ["", "m", "-5m", "1.5h", "5 m", "5w", "5mm", "ten"] {
+            assert!(parse_duration(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert!(parse_duration("99999999999999999d").is_err());
+        assert!(parse_duration("99999999999999999999").is_err());
+        assert_eq!(
+            parse_duration(&format!("{}s", u64::MAX)),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+        assert!(parse_duration(&format!("{}m", u64::MAX / 60 + 1)).is_err());
+    }
 }
@@ -0,0 +1,772 @@
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Bytes read from each end of a file to find its metadata: EXIF, IPTC and XMP sit near
+/// the start of images, and often near the end of videos.
+const METADATA_WINDOW: u64 = 1 << 20;
+
+/// EXIF tag number of the Windows/Adobe star rating (0x4746) in IFD0.
+const EXIF_RATING_TAG: u16 = 0x4746;
+
+/// EXIF tag number of Windows' rating in percent (0x4749) in IFD0.
+const EXIF_RATING_PERCENT_TAG: u16 = 0x4749;
+
+/// EXIF tag numbers of the title and the ";"-separated keywords Windows Explorer writes,
+/// as UTF-16 text.
+const EXIF_XP_TITLE_TAG: u16 = 0x9C9B;
+const EXIF_XP_KEYWORDS_TAG: u16 = 0x9C9E;
+
+/// Curation metadata found in a file's embedded EXIF/XMP or in its XMP sidecar.
+#[derive(Default, Debug)]
+pub struct EmbeddedMetadata {
+    /// Star rating (0-5, or -1 for rejected).
+    pub rating: Option<i32>,
+    /// Keywords, with hierarchical ones joined by "/" (e.g. "Places/France/Paris").
+    pub keywords: Vec<String>,
+    /// Title, as set in Lightroom or in the file's properties in Windows Explorer.
+    pub title: Option<String>,
+}
+
+impl EmbeddedMetadata {
+    /// Extracts metadata for a file from its XMP sidecar and the start and end of its
+    /// contents, without reading all of a large video.
+    pub fn from_file(path: &Path) -> Self {
+        let bytes = read_ends(path).unwrap_or_default();
+        Self::read(path, &bytes)
+    }
+
+    /// Extracts metadata for a file, preferring values from an XMP sidecar,
+    /// then the embedded XMP packet, then IPTC and EXIF (including the properties
+    /// Windows Explorer writes there).
+    pub fn read(path: &Path, bytes: &[u8]) -> Self {
+        let sidecar = read_sidecar(path);
+        let embedded = find_xmp_packet(bytes);
+        let packets: Vec<&str> = sidecar.as_deref().into_iter().chain(embedded).collect();
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(bytes))
+            .ok();
+
+        let rating = packets
+            .iter()
+            .find_map(|p| xmp_property(p, "xmp:Rating"))
+            .and_then(|v| parse_rating(&v))
+            .or_else(|| {
+                packets
+                    .iter()
+                    .find_map(|p| xmp_property(p, "MicrosoftPhoto:Rating"))
+                    .and_then(|v| percent_rating(v.trim().parse().ok()?))
+            })
+            .or_else(|| exif_rating(exif.as_ref()?));
+
+        let keywords = packets
+            .iter()
+            .map(|p| xmp_keywords(p))
+            .find(|k| !k.is_empty())
+            .unwrap_or_else(|| {
+                let keywords = iptc_keywords(bytes);
+                if !keywords.is_empty() {
+                    return keywords;
+                }
+                exif.as_ref()
+                    .and_then(|exif| exif_xp_text(exif, EXIF_XP_KEYWORDS_TAG))
+                    .map(|text| {
+                        text.split(';')
+                            .map(str::trim)
+                            .filter(|k| !k.is_empty())
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            });
+
+        let title = packets
+            .iter()
+            .find_map(|p| xmp_list(p, "dc:title").into_iter().next())
+            .or_else(|| exif_xp_text(exif.as_ref()?, EXIF_XP_TITLE_TAG))
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty());
+
+        EmbeddedMetadata {
+            rating,
+            keywords,
+            title,
+        }
+    }
+}
+
+/// The camera and capture date recorded in an image's EXIF block.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ExifSummary {
+    /// DateTimeOriginal, else DateTime, in the camera's local time.
+    pub taken_at: Option<chrono::NaiveDateTime>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+}
+
+/// Reads the EXIF summary of an image. Only the EXIF block is read, not the whole file.
+pub fn exif_summary(path: &Path) -> Option<ExifSummary> {
+    let file = std::fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .ok()?;
+    let text = |tag| match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => Some(
+            String::from_utf8_lossy(values.first()?)
+                .trim_end_matches('\0')
+                .trim()
+                .to_string(),
+        ),
+        _ => None,
+    };
+    let taken_at = [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| {
+            chrono::NaiveDateTime::parse_from_str(&text(tag)?, "%Y:%m:%d %H:%M:%S").ok()
+        });
+    Some(ExifSummary {
+        taken_at,
+        make: text(exif::Tag::Make),
+        model: text(exif::Tag::Model),
+    })
+}
+
+/// The EXIF capture date of an image (DateTimeOriginal, else DateTime), in the camera's
+/// local time.
+pub fn exif_capture_date(path: &Path) -> Option<chrono::NaiveDateTime> {
+    exif_summary(path)?.taken_at
+}
+
+/// The first and last `METADATA_WINDOW` bytes of a file, or all of a smaller one.
+fn read_ends(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut bytes = Vec::new();
+    if size <= 2 * METADATA_WINDOW {
+        file.read_to_end(&mut bytes)?;
+        return Ok(bytes);
+    }
+    (&mut file).take(METADATA_WINDOW).read_to_end(&mut bytes)?;
+    file.seek(SeekFrom::End(-(METADATA_WINDOW as i64)))?;
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Where the MP4 embedded in a Google or Samsung Motion Photo starts, if the JPEG has one.
+/// Google's XMP gives the video's length from the end of the file; Samsung appends the
+/// video after a `MotionPhoto_Data` marker, which is searched for without holding the
+/// whole file in memory.
+pub fn motion_photo_video_offset(path: &Path) -> Option<u64> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let mut head = Vec::new();
+    (&mut file)
+        .take(METADATA_WINDOW)
+        .read_to_end(&mut head)
+        .ok()?;
+    let offset = find_xmp_packet(&head)
+        .and_then(|packet| xmp_video_offset(packet, size))
+        .or_else(|| {
+            file.rewind().ok()?;
+            find_in_reader(&mut file, SAMSUNG_MOTION_MARKER)
+                .map(|position| position + SAMSUNG_MOTION_MARKER.len() as u64)
+        })?;
+    // Every MP4 starts with an `ftyp` box.
+    let mut box_header = [0u8; 8];
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(&mut box_header).ok()?;
+    (offset > 0 && &box_header[4..] == b"ftyp").then_some(offset)
+}
+
+const SAMSUNG_MOTION_MARKER: &[u8] = b"MotionPhoto_Data";
+
+/// Where the video of a Google Motion Photo of `size` bytes starts, by its XMP.
+fn xmp_video_offset(packet: &str, size: u64) -> Option<u64> {
+    let length = xmp_property(packet, "GCamera:MicroVideoOffset")
+        .or_else(|| motion_photo_item_length(packet))?;
+    size.checked_sub(length.parse().ok()?)
+}
+
+/// Position of the first `needle` in what `reader` yields, read a piece at a time.
+fn find_in_reader(reader: &mut impl Read, needle: &[u8]) -> Option<u64> {
+    let mut buffer = vec![0u8; 1 << 20];
+    // Bytes kept from the previous piece, in case the needle spans two of them.
+    let mut kept = 0;
+    let mut consumed = 0u64;
+    loop {
+        let read = reader.read(&mut buffer[kept..]).ok()?;
+        if read == 0 {
+            return None;
+        }
+        let filled = kept + read;
+        if let Some(position) = find_bytes(&buffer[..filled], needle) {
+            return Some(consumed + position as u64);
+        }
+        let keep = (needle.len() - 1).min(filled);
+        buffer.copy_within(filled - keep..filled, 0);
+        consumed += (filled - keep) as u64;
+        kept = keep;
+    }
+}
+
+/// Length of the video item in the container directory of a Motion Photo's XMP
+/// (`<Container:Item Item:Semantic="MotionPhoto" Item:Length="..."/>`).
+fn motion_photo_item_length(packet: &str) -> Option<String> {
+    let semantic = packet.find("Item:Semantic=\"MotionPhoto\"")?;
+    let start = packet[..semantic].rfind('<')?;
+    let end = semantic + packet[semantic..].find('>')?;
+    xmp_property(&packet[start..end], "Item:Length")
+}
+
+/// Reads the XMP sidecar next to a file, checking both `photo.jpg.xmp` and `photo.xmp`.
+fn read_sidecar(path: &Path) -> Option<String> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    let mut appended = path.as_os_str().to_owned();
+    appended.push(".xmp");
+    candidates.push(appended.into());
+    candidates.push(path.with_extension("xmp"));
+    candidates
+        .iter()
+        .find(|p| p.is_file())
+        .and_then(|p| std::fs::read_to_string(p).ok())
+}
+
+/// Locates the XMP packet embedded in the file contents, if any.
+fn find_xmp_packet(bytes: &[u8]) -> Option<&str> {
+    const START: &[u8] = b"<x:xmpmeta";
+    const END: &[u8] = b"</x:xmpmeta>";
+    let start = find_bytes(bytes, START)?;
+    let end = start + find_bytes(&bytes[start..], END)? + END.len();
+    std::str::from_utf8(&bytes[start..end]).ok()
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Reads a simple XMP property written either as an attribute (`name="value"`)
+/// or as an element (`<name>value</name>`).
+fn xmp_property(packet: &str, name: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        let pattern = format!("{}={}", name, quote);
+        if let Some(pos) = packet.find(&pattern) {
+            let rest = &packet[pos + pattern.len()..];
+            let end = rest.find(quote)?;
+            return Some(rest[..end].trim().to_string());
+        }
+    }
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = packet.find(&open)? + open.len();
+    let end = start + packet[start..].find(&close)?;
+    Some(packet[start..end].trim().to_string())
+}
+
+/// Reads the items of an XMP array property (`<name><rdf:Bag><rdf:li>...</rdf:li></rdf:Bag></name>`).
+fn xmp_list(packet: &str, name: &str) -> Vec<String> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let Some(start) = packet.find(&open).map(|p| p + open.len()) else {
+        return Vec::new();
+    };
+    let Some(end) = packet[start..].find(&close).map(|p| p + start) else {
+        return Vec::new();
+    };
+
+    let mut items = Vec::new();
+    let mut rest = &packet[start..end];
+    while let Some(pos) = rest.find("<rdf:li") {
+        rest = &rest[pos..];
+        let (Some(open_end), Some(close)) = (rest.find('>'), rest.find("</rdf:li>")) else {
+            break;
+        };
+        if open_end < close {
+            let value = unescape_xml(rest[open_end + 1..close].trim());
+            if !value.is_empty() {
+                items.push(value);
+            }
+        }
+        rest = &rest[close + "</rdf:li>".len()..];
+    }
+    items
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Collects keywords from an XMP packet.
+/// Hierarchical subjects (Lightroom `lr:hierarchicalSubject`, digiKam `digiKam:TagsList`)
+/// take precedence; flat `dc:subject` keywords are kept only when they are not already
+/// the leaf of a hierarchical keyword.
+fn xmp_keywords(packet: &str) -> Vec<String> {
+    let mut keywords: Vec<String> = xmp_list(packet, "lr:hierarchicalSubject")
+        .into_iter()
+        .map(|k| k.split('|').map(str::trim).collect::<Vec<_>>().join("/"))
+        .chain(xmp_list(packet, "digiKam:TagsList"))
+        .collect();
+
+    for subject in xmp_list(packet, "dc:subject") {
+        let is_leaf = keywords
+            .iter()
+            .any(|k| k.rsplit('/').next() == Some(subject.as_str()));
+        if !is_leaf {
+            keywords.push(subject);
+        }
+    }
+
+    keywords.sort();
+    keywords.dedup();
+    keywords
+}
+
+/// Reads IPTC-IIM keywords (dataset 2:25) from a Photoshop APP13 segment.
+fn iptc_keywords(bytes: &[u8]) -> Vec<String> {
+    const MARKER: &[u8] = b"Photoshop 3.0\0";
+    let Some(start) = find_bytes(bytes, MARKER) else {
+        return Vec::new();
+    };
+    // The two bytes preceding the marker hold the segment length (including themselves).
+    let length = if start >= 2 {
+        u16::from_be_bytes([bytes[start - 2], bytes[start - 1]]) as usize
+    } else {
+        0
+    };
+    let segment = &bytes[start..(start + length.saturating_sub(2)).min(bytes.len())];
+
+    let mut keywords = Vec::new();
+    let mut i = 0;
+    while i + 5 <= segment.len() {
+        if segment[i] == 0x1C && segment[i + 1] == 2 && segment[i + 2] == 25 {
+            let size = u16::from_be_bytes([segment[i + 3], segment[i + 4]]) as usize;
+            let end = i + 5 + size;
+            if end > segment.len() {
+                break;
+            }
+            let keyword = String::from_utf8_lossy(&segment[i + 5..end])
+                .trim()
+                .to_string();
+            if !keyword.is_empty() && !keywords.contains(&keyword) {
+                keywords.push(keyword);
+            }
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    keywords
+}
+
+/// Parses an XMP rating, which may be written as a decimal (e.g. "4.0").
+fn parse_rating(value: &str) -> Option<i32> {
+    value.parse::<f64>().ok().map(|r| r.round() as i32)
+}
+
+/// Reads the rating from the EXIF IFD0 Rating tag, or else from Windows' RatingPercent.
+fn exif_rating(exif: &exif::Exif) -> Option<i32> {
+    let field = |tag| exif.get_field(exif::Tag(exif::Context::Tiff, tag), exif::In::PRIMARY);
+    field(EXIF_RATING_TAG)
+        .and_then(|f| f.value.get_uint(0))
+        .map(|r| r as i32)
+        .or_else(|| percent_rating(field(EXIF_RATING_PERCENT_TAG)?.value.get_uint(0)?))
+}
+
+/// Converts Windows' rating in percent (1, 25, 50, 75 and 99 for one to five stars)
+/// to stars; 0 means unrated.
+fn percent_rating(percent: u32) -> Option<i32> {
+    (percent > 0).then(|| ((f64::from(percent) / 25.0).round() as i32 + 1).min(5))
+}
+
+/// Reads one of the UTF-16 text tags Windows Explorer writes into IFD0.
+fn exif_xp_text(exif: &exif::Exif, tag: u16) -> Option<String> {
+    let field = exif.get_field(exif::Tag(exif::Context::Tiff, tag), exif::In::PRIMARY)?;
+    let exif::Value::Byte(bytes) = &field.value else {
+        return None;
+    };
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|unit| *unit != 0)
+        .collect();
+    Some(String::from_utf16_lossy(&units))
+}
+
+/// Extended attribute holding a file's Finder tags, as a binary property list of
+/// "name\ncolor" strings.
+#[cfg(target_os = "macos")]
+const FINDER_TAGS_XATTR: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// The Finder tags of a file (e.g. "Red", "Family"), without their colors. Always empty
+/// outside of macOS.
+#[cfg(target_os = "macos")]
+pub fn finder_tags(path: &Path) -> Vec<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let (Ok(path), Ok(name)) = (
+        std::ffi::CString::new(path.as_os_str().as_bytes()),
+        std::ffi::CString::new(FINDER_TAGS_XATTR),
+    ) else {
+        return Vec::new();
+    };
+    // SAFETY: both strings are NUL-terminated; a null buffer only asks for the size.
+    let size =
+        unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0, 0, 0) };
+    if size <= 0 {
+        return Vec::new();
+    }
+    let mut value = vec![0u8; size as usize];
+    // SAFETY: `value` has room for the `size` bytes asked for.
+    let read = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len(),
+            0,
+            0,
+        )
+    };
+    if read <= 0 {
+        return Vec::new();
+    }
+    value.truncate(read as usize);
+    parse_finder_tags(&value)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn finder_tags(_path: &Path) -> Vec<String> {
+    Vec::new()
+}
+
+/// Tag names in the value of the Finder tags attribute.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_finder_tags(value: &[u8]) -> Vec<String> {
+    bplist_strings(value)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|tag| {
+            let name = tag.split('\n').next()?.trim();
+            (!name.is_empty()).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// The strings in a binary property list ("bplist00") whose top object is an array.
+/// Other kinds of objects in the array are left out.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn bplist_strings(data: &[u8]) -> Option<Vec<String>> {
+    if !data.starts_with(b"bplist00") || data.len() < 40 {
+        return None;
+    }
+    let trailer = &data[data.len() - 32..];
+    let offset_size = usize::from(trailer[6]);
+    let ref_size = usize::from(trailer[7]);
+    let count = usize::try_from(be_uint(&trailer[8..16])?).ok()?;
+    let top = usize::try_from(be_uint(&trailer[16..24])?).ok()?;
+    let table = usize::try_from(be_uint(&trailer[24..32])?).ok()?;
+    let offset = |index: usize| -> Option<usize> {
+        let start = table.checked_add(index.checked_mul(offset_size)?)?;
+        usize::try_from(be_uint(data.get(start..start.checked_add(offset_size)?)?)?).ok()
+    };
+    let (kind, length, body) = bplist_object(data, offset(top)?)?;
+    if kind != 0xA {
+        return None;
+    }
+    let mut strings = Vec::new();
+    for index in 0..length {
+        let start = index.checked_mul(ref_size)?;
+        let reference = usize::try_from(be_uint(body.get(start..start + ref_size)?)?).ok()?;
+        if reference >= count {
+            return None;
+        }
+        strings.extend(bplist_string(data, offset(reference)?));
+    }
+    Some(strings)
+}
+
+/// The type, length and contents of the property list object at `start`.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn bplist_object(data: &[u8], start: usize) -> Option<(u8, usize, &[u8])> {
+    let marker = *data.get(start)?;
+    let mut length = usize::from(marker & 0xF);
+    let mut body = start + 1;
+    // Longer lengths follow as an integer object.
+    if length == 0xF {
+        let int = *data.get(body)?;
+        if int >> 4 != 1 {
+            return None;
+        }
+        let size = 1usize << (int & 0xF);
+        length = usize::try_from(be_uint(data.get(body + 1..body + 1 + size)?)?).ok()?;
+        body += 1 + size;
+    }
+    Some((marker >> 4, length, data.get(body..)?))
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn bplist_string(data: &[u8], start: usize) -> Option<String> {
+    let (kind, length, body) = bplist_object(data, start)?;
+    match kind {
+        0x5 => Some(String::from_utf8_lossy(body.get(..length)?).into_owned()),
+        0x6 => {
+            let units: Vec<u16> = body
+                .get(..length.checked_mul(2)?)?
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            Some(String::from_utf16_lossy(&units))
+        }
+        _ => None,
+    }
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn be_uint(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |n, b| (n << 8) | u64::from(*b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A JPEG made of the given APPn segments and nothing else.
+    fn jpeg(segments: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8];
+        for (marker, payload) in segments {
+            bytes.extend_from_slice(&[0xFF, *marker]);
+            bytes.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+            bytes.extend_from_slice(payload);
+        }
+        bytes.extend_from_slice(&[0xFF, 0xD9]);
+        bytes
+    }
+
+    fn xmp_segment(packet: &str) -> (u8, Vec<u8>) {
+        let mut payload = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+        payload.extend_from_slice(packet.as_bytes());
+        (0xE1, payload)
+    }
+
+    /// An APP13 segment holding IPTC keywords (dataset 2:25).
+    fn iptc_segment(keywords: &[&str]) -> (u8, Vec<u8>) {
+        let mut iptc = Vec::new();
+        for keyword in keywords {
+            iptc.extend_from_slice(&[0x1C, 2, 25]);
+            iptc.extend_from_slice(&(keyword.len() as u16).to_be_bytes());
+            iptc.extend_from_slice(keyword.as_bytes());
+        }
+        let mut payload = b"Photoshop 3.0\08BIM\x04\x04\x00\x00".to_vec();
+        payload.extend_from_slice(&(iptc.len() as u32).to_be_bytes());
+        payload.extend_from_slice(&iptc);
+        (0xED, payload)
+    }
+
+    /// A little-endian TIFF with IFD0 holding `(tag, type, count, value)` entries; values
+    /// over four bytes are stored after the directory.
+    fn tiff(entries: &[(u16, u16, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = b"II*\x00\x08\x00\x00\x00".to_vec();
+        let mut data_offset = 8 + 2 + entries.len() * 12 + 4;
+        let mut data = Vec::new();
+        bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (tag, kind, value) in entries {
+            let count = if *kind == 3 {
+                value.len() / 2
+            } else {
+                value.len()
+            };
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&kind.to_le_bytes());
+            bytes.extend_from_slice(&(count as u32).to_le_bytes());
+            if value.len() <= 4 {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                bytes.extend_from_slice(&inline);
+            } else {
+                bytes.extend_from_slice(&(data_offset as u32).to_le_bytes());
+                data.extend_from_slice(value);
+                data_offset += value.len();
+            }
+        }
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    fn read(bytes: &[u8]) -> EmbeddedMetadata {
+        EmbeddedMetadata::read(Path::new("/nonexistent/photo.jpg"), bytes)
+    }
+
+    const LIGHTROOM_XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmp:Rating="4.0">
+   <dc:title><rdf:Alt><rdf:li xml:lang="x-default"> Tom &amp; Jerry </rdf:li></rdf:Alt></dc:title>
+   <dc:subject><rdf:Bag><rdf:li>Paris</rdf:li><rdf:li>Summer</rdf:li></rdf:Bag></dc:subject>
+   <lr:hierarchicalSubject><rdf:Bag>
+    <rdf:li>Places|France|Paris</rdf:li>
+   </rdf:Bag></lr:hierarchicalSubject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+    #[test]
+    fn reads_embedded_xmp() {
+        let metadata = read(&jpeg(&[xmp_segment(LIGHTROOM_XMP)]));
+        assert_eq!(metadata.rating, Some(4));
+        assert_eq!(metadata.keywords, ["Places/France/Paris", "Summer"]);
+        assert_eq!(metadata.title.as_deref(), Some("Tom & Jerry"));
+    }
+
+    #[test]
+    fn reads_windows_xmp_rating_in_percent() {
+        let packet = r#"<x:xmpmeta><rdf:Description MicrosoftPhoto:Rating="75"/></x:xmpmeta>"#;
+        assert_eq!(read(&jpeg(&[xmp_segment(packet)])).rating, Some(4));
+        // Adobe's star rating wins over Windows' percentage.
+        let packet = r#"<x:xmpmeta><rdf:Description xmp:Rating="-1" MicrosoftPhoto:Rating="99"/></x:xmpmeta>"#;
+        assert_eq!(read(&jpeg(&[xmp_segment(packet)])).rating, Some(-1));
+    }
+
+    #[test]
+    fn maps_windows_percent_ratings_to_stars() {
+        for (percent, stars) in [
+            (0, None),
+            (1, Some(1)),
+            (25, Some(2)),
+            (50, Some(3)),
+            (75, Some(4)),
+            (99, Some(5)),
+            (100, Some(5)),
+            (u32::MAX, Some(5)),
+        ] {
+            assert_eq!(percent_rating(percent), stars, "{}%", percent);
+        }
+    }
+
+    #[test]
+    fn tolerates_malformed_xmp() {
+        for packet in [
+            "<x:xmpmeta><dc:subject><rdf:Bag><rdf:li>Paris</x:xmpmeta>",
+            "<x:xmpmeta><dc:title><rdf:li</dc:title></x:xmpmeta>",
+            r#"<x:xmpmeta xmp:Rating="four"></x:xmpmeta>"#,
+            r#"<x:xmpmeta xmp:Rating="5"#,
+        ] {
+            let metadata = read(&jpeg(&[xmp_segment(packet)]));
+            assert_eq!(metadata.rating, None, "{}", packet);
+            assert!(metadata.keywords.is_empty(), "{}", packet);
+            assert_eq!(metadata.title, None, "{}", packet);
+        }
+    }
+
+    #[test]
+    fn reads_iptc_keywords() {
+        let bytes = jpeg(&[iptc_segment(&["Holiday", " Lake ", "Holiday", ""])]);
+        assert_eq!(iptc_keywords(&bytes), ["Holiday", "Lake"]);
+        assert_eq!(read(&bytes).keywords, ["Holiday", "Lake"]);
+    }
+
+    #[test]
+    fn prefers_xmp_keywords_over_iptc() {
+        let bytes = jpeg(&[iptc_segment(&["Old"]), xmp_segment(LIGHTROOM_XMP)]);
+        assert_eq!(read(&bytes).keywords, ["Places/France/Paris", "Summer"]);
+    }
+
+    #[test]
+    fn stops_at_truncated_iptc() {
+        let (marker, mut payload) = iptc_segment(&["Holiday", "Lake"]);
+        // The second keyword claims more bytes than the segment has.
+        let last = payload.len() - "Lake".len() - 2;
+        payload[last..last + 2].copy_from_slice(&200u16.to_be_bytes());
+        assert_eq!(iptc_keywords(&jpeg(&[(marker, payload)])), ["Holiday"]);
+
+        let bytes = jpeg(&[iptc_segment(&["Holiday"])]);
+        for end in 0..bytes.len() {
+            iptc_keywords(&bytes[..end]);
+        }
+        assert!(iptc_keywords(b"Photoshop 3.0\0\x1C\x02\x19\x00\x02ab").is_empty());
+    }
+
+    #[test]
+    fn reads_windows_exif_properties() {
+        let exif = tiff(&[
+            (EXIF_RATING_PERCENT_TAG, 3, 75u16.to_le_bytes().to_vec()),
+            (EXIF_XP_TITLE_TAG, 1, utf16("Sunset")),
+            (EXIF_XP_KEYWORDS_TAG, 1, utf16("Holiday; Lake District;")),
+        ]);
+        let metadata = read(&exif);
+        assert_eq!(metadata.rating, Some(4));
+        assert_eq!(metadata.keywords, ["Holiday", "Lake District"]);
+        assert_eq!(metadata.title.as_deref(), Some("Sunset"));
+    }
+
+    #[test]
+    fn prefers_exif_stars_over_percent() {
+        let exif = tiff(&[
+            (EXIF_RATING_TAG, 3, 2u16.to_le_bytes().to_vec()),
+            (EXIF_RATING_PERCENT_TAG, 3, 99u16.to_le_bytes().to_vec()),
+        ]);
+        assert_eq!(read(&exif).rating, Some(2));
+    }
+
+    #[test]
+    fn tolerates_truncated_exif() {
+        let exif = tiff(&[
+            (EXIF_RATING_PERCENT_TAG, 3, 75u16.to_le_bytes().to_vec()),
+            (EXIF_XP_TITLE_TAG, 1, utf16("Sunset over the lake")),
+        ]);
+        for end in 0..exif.len() {
+            read(&exif[..end]);
+        }
+        // A title cut off in the middle of a character is not read at all.
+        let metadata = read(&exif[..exif.len() - 1]);
+        assert_eq!(metadata.title, None);
+    }
+
+    /// `["Red\n6", "Family", "Favorite\n0"]`, as written by Finder.
+    const FINDER_TAGS: &[u8] = b"bplist00\xA3\x01\x02\x03\x55Red\n6\x56Family\x5AFavorite\n0\x08\x0C\x12\x19\x00\x00\x00\x00\x00\x00\x01\x01\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x24";
+
+    #[test]
+    fn reads_finder_tags() {
+        assert_eq!(
+            parse_finder_tags(FINDER_TAGS),
+            ["Red", "Family", "Favorite"]
+        );
+        // Non-ASCII names are stored as UTF-16.
+        let utf16 = b"bplist00\xA1\x01\x66\x00C\x00a\x00f\x00\xE9\x00\x20\x26\x15\x08\x0A\x00\x00\x00\x00\x00\x00\x01\x01\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x17";
+        assert_eq!(parse_finder_tags(utf16), ["Café ☕"]);
+    }
+
+    #[test]
+    fn rejects_malformed_property_lists() {
+        // A dictionary instead of an array.
+        let dictionary = b"bplist00\xD1\x01\x02\x51a\x51b\x08\x0B\x0D\x00\x00\x00\x00\x00\x00\x01\x01\x00\x00\x00\x00\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x0F";
+        assert_eq!(bplist_strings(dictionary), None);
+        assert_eq!(bplist_strings(b"bplist01"), None);
+        assert_eq!(bplist_strings(&FINDER_TAGS[1..]), None);
+        // An array entry referring past the object table.
+        let mut dangling = FINDER_TAGS.to_vec();
+        dangling[11] = 9;
+        assert_eq!(bplist_strings(&dangling), None);
+        // An offset table pointing outside the data.
+        let mut outside = FINDER_TAGS.to_vec();
+        let table = outside.len() - 1;
+        outside[table] = 0xFF;
+        assert_eq!(bplist_strings(&outside), None);
+        for end in 0..FINDER_TAGS.len() {
+            assert!(parse_finder_tags(&FINDER_TAGS[..end]).is_empty());
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::api::{Asset, ImmichClient};
+use crate::assets;
+use crate::exit;
+use crate::progress::Progress;
+use crate::source::stream_part;
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Copies every asset from one Immich account to another, streaming originals without a
+/// local copy and keeping favorites, archive state, descriptions, Live Photo links and albums.
+pub async fn migrate(from: &ImmichClient, to: &ImmichClient, concurrent: usize) -> Result<()> {
+    println!("Listing assets on the source server...");
+    let assets = from
+        .all_assets()
+        .await
+        .context("Failed to list source assets")?;
+    if assets.is_empty() {
+        println!("The source account has no assets.");
+        return Ok(());
+    }
+    println!(
+        "Migrating {} assets with concurrency {}...",
+        assets.len(),
+        concurrent
+    );
+
+    let total = assets.len();
+    let pb = Progress::new(total)?;
+
+    // Source asset ID -> target asset ID, used to rebuild albums.
+    let id_map: Mutex<HashMap<String, String>> = Mutex::default();
+    let mut requests = futures::stream::iter(assets)
+        .map(|asset| {
+            let pb = pb.clone();
+            let id_map = &id_map;
+            async move {
+                match migrate_asset(from, to, &asset).await {
+                    Ok(target_id) => {
+                        id_map.lock().unwrap().insert(asset.id, target_id);
+                    }
+                    Err(e) => {
+                        pb.warn(format!(
+                            "Failed to migrate {}: {:#}",
+                            asset.original_file_name, e
+                        ));
+                    }
+                }
+                pb.inc();
+            }
+        })
+        .buffer_unordered(concurrent);
+
+    // Consume the stream.
+    while requests.next().await.is_some() {}
+    drop(requests);
+    pb.finish("Assets migrated");
+
+    let id_map = id_map.into_inner().unwrap();
+    migrate_albums(from, to, &id_map).await?;
+    exit::check_failures(total - id_map.len(), total)
+}
+
+/// Copies one asset (and the motion part of a Live Photo) and returns its ID on the target.
+async fn migrate_asset(from: &ImmichClient, to: &ImmichClient, asset: &Asset) -> Result<String> {
+    // The motion part must exist before the still can be linked to it.
+    let live_photo_video_id = match &asset.live_photo_video_id {
+        Some(video_id) => {
+            let video = from.get_asset(video_id).await?;
+            Some(
+                transfer(from, to, &video, None)
+                    .await
+                    .context("Failed to migrate Live Photo video")?,
+            )
+        }
+        None => None,
+    };
+    transfer(from, to, asset, live_photo_video_id).await
+}
+
+async fn transfer(
+    from: &ImmichClient,
+    to: &ImmichClient,
+    asset: &Asset,
+    live_photo_video_id: Option<String>,
+) -> Result<String> {
+    let download = from.download_original(&asset.id).await?;
+    let part = stream_part(download, &asset.original_file_name, None)?;
+
+    let form = assets::copy_form(
+        part,
+        format!("immich-{}", asset.id),
+        (asset.file_created_at, asset.file_modified_at),
+        asset.is_favorite,
+        asset.archived(),
+        live_photo_video_id,
+    );
+
+    let uploaded = to
+        .upload_asset(form)
+        .await?
+        .context("Target server rejected the asset without reporting its ID")?;
+    if let Some(description) = asset.description() {
+        to.update_asset_description(&uploaded.id, description)
+            .await
+            .context("Failed to set description")?;
+    }
+    Ok(uploaded.id)
+}
+
+/// Recreates the source albums on the target (reusing albums with the same name).
+async fn migrate_albums(
+    from: &ImmichClient,
+    to: &ImmichClient,
+    id_map: &HashMap<String, String>,
+) -> Result<()> {
+    let albums = from
+        .list_albums()
+        .await
+        .context("Failed to list source albums")?;
+    let mut existing: HashMap<String, String> = to
+        .list_albums()
+        .await
+        .context("Failed to list target albums")?
+        .into_iter()
+        .map(|a| (a.album_name, a.id))
+        .collect();
+
+    for album in albums {
+        let details = from.get_album(&album.id).await?;
+        let asset_ids: Vec<String> = details
+            .assets
+            .iter()
+            .filter_map(|a| id_map.get(&a.id).cloned())
+            .collect();
+        if asset_ids.is_empty() {
+            continue;
+        }
+
+        let album_id = match existing.get(&album.album_name) {
+            Some(id) => id.clone(),
+            None => {
+                let description = Some(album.description.as_str()).filter(|d| !d.is_empty());
+                let created = to
+                    .create_album(&album.album_name, description)
+                    .await
+                    .with_context(|| format!("Failed to create album '{}'", album.album_name))?;
+                existing.insert(album.album_name.clone(), created.id.clone());
+                created.id
+            }
+        };
+        to.add_assets_to_album(&album_id, &asset_ids)
+            .await
+            .with_context(|| format!("Failed to fill album '{}'", album.album_name))?;
+        println!("Album '{}': {} assets.", album.album_name, asset_ids.len());
+    }
+    Ok(())
+}
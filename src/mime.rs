@@ -0,0 +1,227 @@
+use mime_guess::Mime;
+use std::io::Read;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Bytes read from the start of a file to recognize its format.
+const HEADER_SIZE: u64 = 8192;
+
+/// Types only recognized as a container, whose extension tells the exact format: camera
+/// raw files are TIFF inside, HEIF holds HEIC and AVIF, and MP4 and Matroska hold many
+/// kinds of video.
+const CONTAINERS: [&str; 4] = ["image/tiff", "image/heif", "video/mp4", "video/x-matroska"];
+
+/// `infer`'s matchers, with the camera and phone formats it doesn't know about.
+static MATCHERS: LazyLock<infer::Infer> = LazyLock::new(|| {
+    let mut matchers = infer::Infer::new();
+    matchers.add("image/heic", "heic", is_heic);
+    matchers.add("image/x-canon-cr3", "cr3", |h| has_brand(h, &[b"crx "]));
+    matchers.add("video/3gpp", "3gp", |h| {
+        has_brand(h, &[b"3gp4", b"3gp5", b"3gp6", b"3ge6", b"3gs7"])
+    });
+    matchers.add("video/3gpp2", "3g2", |h| {
+        has_brand(h, &[b"3g2a", b"3g2b", b"3g2c"])
+    });
+    matchers.add("image/x-fuji-raf", "raf", |h| {
+        h.starts_with(b"FUJIFILMCCD-RAW")
+    });
+    matchers.add("image/x-olympus-orf", "orf", |h| h.starts_with(b"IIRO"));
+    matchers.add("image/x-panasonic-rw2", "rw2", |h| {
+        h.starts_with(b"IIU\x00")
+    });
+    matchers
+});
+
+/// What a file is, found once and kept with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileType {
+    pub mime: Mime,
+    /// The usual extension of the format recognized in the contents, to upload files under
+    /// a name the server accepts.
+    pub extension: Option<&'static str>,
+}
+
+/// The type of the file at `path` by its contents, or by the extension of `name` when the
+/// contents are not recognized or only name a container. `name` is usually the file's own
+/// name, but may differ when a file is uploaded under another one.
+pub fn detect(path: &Path, name: &str) -> FileType {
+    let by_extension = mime_guess::from_path(name).first_or_octet_stream();
+    match read_header(path).and_then(|header| sniff(&header)) {
+        Some((sniffed, extension)) => FileType {
+            mime: resolve(sniffed, by_extension),
+            extension: Some(extension),
+        },
+        None => FileType {
+            mime: by_extension,
+            extension: None,
+        },
+    }
+}
+
+/// Whether a type is one Immich stores: an image or a video.
+pub fn is_media(mime: &Mime) -> bool {
+    mime.type_() == mime_guess::mime::IMAGE || mime.type_() == mime_guess::mime::VIDEO
+}
+
+/// Whether a file's name says what it is, so a file without an image or video extension
+/// can be passed over without looking inside. Names with no extension, or one that only
+/// means "binary data" (`.bin`, `.dat`), say nothing.
+pub fn has_known_extension(path: &Path) -> bool {
+    mime_guess::from_path(path)
+        .first()
+        .is_some_and(|mime| mime != mime_guess::mime::APPLICATION_OCTET_STREAM)
+}
+
+fn read_header(path: &Path) -> Option<Vec<u8>> {
+    let mut header = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(HEADER_SIZE).read_to_end(&mut header))
+        .ok()?;
+    Some(header)
+}
+
+/// Recognizes a format and its usual extension by the signature at the start of a file.
+/// Documents and archives are recognized too, so files only named like media can be told
+/// apart.
+fn sniff(header: &[u8]) -> Option<(Mime, &'static str)> {
+    let matched = MATCHERS.get(header)?;
+    Some((matched.mime_type().parse().ok()?, matched.extension()))
+}
+
+/// Picks between what the contents and the extension say a file is: the extension when
+/// the contents only tell the kind of container it uses.
+fn resolve(sniffed: Mime, by_extension: Mime) -> Mime {
+    if CONTAINERS.contains(&sniffed.essence_str()) && sniffed.type_() == by_extension.type_() {
+        by_extension
+    } else {
+        sniffed
+    }
+}
+
+/// The major brand of an ISO base media file (MP4, MOV, HEIF, CR3), named after `ftyp`.
+fn has_brand(header: &[u8], brands: &[&[u8; 4]]) -> bool {
+    header.get(4..8) == Some(b"ftyp")
+        && header
+            .get(8..12)
+            .is_some_and(|brand| brands.iter().any(|b| b.as_slice() == brand))
+}
+
+fn is_heic(header: &[u8]) -> bool {
+    has_brand(
+        header,
+        &[
+            b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"hevm", b"hevs",
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+        let mut header = vec![0, 0, 0, 0x18];
+        header.extend_from_slice(b"ftyp");
+        header.extend_from_slice(brand);
+        header.extend_from_slice(&[0; 12]);
+        header
+    }
+
+    fn sniffed(header: &[u8]) -> Option<String> {
+        sniff(header).map(|(mime, _)| mime.essence_str().to_string())
+    }
+
+    #[test]
+    fn recognizes_images() {
+        let cases: [(&[u8], &str); 8] = [
+            (b"\xFF\xD8\xFF\xE1\x00\x10Exif\x00\x00", "image/jpeg"),
+            (b"\x89PNG\r\n\x1A\n\x00\x00\x00\x0DIHDR", "image/png"),
+            (b"GIF89a\x01\x00\x01\x00\x00\x00", "image/gif"),
+            (b"RIFF\x00\x00\x00\x00WEBPVP8 ", "image/webp"),
+            (b"FUJIFILMCCD-RAW 0201FF383501", "image/x-fuji-raf"),
+            (
+                b"IIRO\x08\x00\x00\x00\x00\x00\x00\x00",
+                "image/x-olympus-orf",
+            ),
+            (
+                b"IIU\x00\x08\x00\x00\x00\x00\x00\x00\x00",
+                "image/x-panasonic-rw2",
+            ),
+            (b"II*\x00\x10\x00\x00\x00CR\x02\x00", "image/x-canon-cr2"),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(sniffed(header).as_deref(), Some(expected), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn recognizes_iso_media_brands() {
+        let cases: [(&[u8; 4], &str); 7] = [
+            (b"heic", "image/heic"),
+            (b"hevc", "image/heic"),
+            (b"avif", "image/avif"),
+            (b"crx ", "image/x-canon-cr3"),
+            (b"qt  ", "video/quicktime"),
+            (b"isom", "video/mp4"),
+            (b"3gp5", "video/3gpp"),
+        ];
+        for (brand, expected) in cases {
+            assert_eq!(
+                sniffed(&ftyp(brand)).as_deref(),
+                Some(expected),
+                "{:?}",
+                brand
+            );
+        }
+    }
+
+    #[test]
+    fn recognizes_non_media() {
+        assert_eq!(
+            sniffed(b"%PDF-1.7\n%\xE2\xE3\xCF\xD3").as_deref(),
+            Some("application/pdf")
+        );
+        assert_eq!(
+            sniffed(b"PK\x03\x04\x14\x00\x00\x00\x08\x00").as_deref(),
+            Some("application/zip")
+        );
+        let sniffed = sniffed(b"<!DOCTYPE html><html><body>Not found</body></html>").unwrap();
+        assert!(!is_media(&sniffed.parse().unwrap()));
+    }
+
+    #[test]
+    fn leaves_unknown_and_short_input_alone() {
+        assert_eq!(sniffed(b""), None);
+        assert_eq!(sniffed(b"\xFF"), None);
+        assert_eq!(sniffed(b"just some text"), None);
+        assert_eq!(sniffed(&ftyp(b"heic")[..10]), None);
+    }
+
+    #[test]
+    fn prefers_the_extension_within_a_container() {
+        let mime = |s: &str| s.parse::<Mime>().unwrap();
+        // A Nikon raw file is a TIFF inside.
+        assert_eq!(
+            resolve(mime("image/tiff"), mime("image/x-nikon-nef")),
+            mime("image/x-nikon-nef")
+        );
+        // An MP4 named .jpg is a video all the same.
+        assert_eq!(
+            resolve(mime("video/mp4"), mime("image/jpeg")),
+            mime("video/mp4")
+        );
+        // A JPEG named .png is a JPEG.
+        assert_eq!(
+            resolve(mime("image/jpeg"), mime("image/png")),
+            mime("image/jpeg")
+        );
+    }
+
+    #[test]
+    fn tells_known_extensions() {
+        assert!(has_known_extension(Path::new("IMG_0001.HEIC")));
+        assert!(has_known_extension(Path::new("notes.txt")));
+        assert!(!has_known_extension(Path::new("IMG_0001")));
+        assert!(!has_known_extension(Path::new("recovered.bin")));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::metadata;
+use crate::upload::UploadItem;
+use chrono::{DateTime, Local, NaiveDateTime};
+use std::cmp::Ordering;
+use std::path::Path;
+
+/// Order files are uploaded in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum UploadOrder {
+    /// By path, folder by folder.
+    Path,
+    /// By modification time, oldest first.
+    Mtime,
+    /// By EXIF capture date, oldest first; files without one by modification time.
+    ExifDate,
+    /// By size, smallest first.
+    Size,
+}
+
+/// What to upload first, on top of the order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum UploadPriority {
+    /// Smallest files first, so most files are on the server early.
+    #[value(name = "smallest-first")]
+    Smallest,
+    /// Most recently taken files first.
+    #[value(name = "newest-first")]
+    Newest,
+    /// Images before videos.
+    #[value(name = "images-first")]
+    Images,
+}
+
+/// Sorts `items` into `order`, breaking ties by path so runs are repeatable.
+pub fn sort(items: &mut [UploadItem], order: UploadOrder) {
+    items.sort_by(|a, b| a.path.cmp(&b.path));
+    match order {
+        UploadOrder::Path => {}
+        UploadOrder::Mtime => sort_by_key(items, |item| modified(&item.path)),
+        UploadOrder::ExifDate => sort_by_key(items, capture_date),
+        UploadOrder::Size => sort_by_key(items, |item| size(&item.path)),
+    }
+}
+
+/// Moves the files `priority` favors to the front, keeping the order among equals.
+pub fn prioritize(items: &mut [UploadItem], priority: UploadPriority) {
+    match priority {
+        UploadPriority::Smallest => sort_by_key(items, |item| size(&item.path)),
+        UploadPriority::Newest => {
+            sort_by_key(items, |item| capture_date(item).map(std::cmp::Reverse))
+        }
+        UploadPriority::Images => sort_by_key(items, |item| {
+            Some(item.file_type().mime.type_() != mime_guess::mime::IMAGE)
+        }),
+    }
+}
+
+/// When a file was taken: the date from its source, its EXIF date, or its modification
+/// time.
+fn capture_date(item: &UploadItem) -> Option<NaiveDateTime> {
+    item.taken_at
+        .map(|t| t.with_timezone(&Local).naive_local())
+        .or_else(|| metadata::exif_capture_date(&item.path))
+        .or_else(|| modified(&item.path))
+}
+
+/// Stably sorts by a key read from the file once per item; files whose key can't be read
+/// go last.
+fn sort_by_key<K: Ord>(items: &mut [UploadItem], key: impl Fn(&UploadItem) -> Option<K>) {
+    let mut keyed: Vec<(Option<K>, UploadItem)> = items
+        .iter_mut()
+        .map(|item| (key(item), std::mem::take(item)))
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    for (slot, (_, item)) in items.iter_mut().zip(keyed) {
+        *slot = item;
+    }
+}
+
+fn modified(path: &Path) -> Option<NaiveDateTime> {
+    let modified: DateTime<Local> = std::fs::metadata(path).ok()?.modified().ok()?.into();
+    Some(modified.naive_local())
+}
+
+fn size(path: &Path) -> Option<u64> {
+    Some(std::fs::metadata(path).ok()?.len())
+}
@@ -0,0 +1,105 @@
+use crate::api::ImmichClient;
+use anyhow::{Context, Result, bail};
+use std::io::{IsTerminal, Write};
+
+/// Albums shown at once while picking.
+const SHOWN: usize = 15;
+
+/// Lets the user pick one of their albums, narrowing the list by typing part of its name
+/// (letters in order, not necessarily adjacent), or name a new album to create.
+pub async fn pick_album(client: &ImmichClient) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
+        bail!("Picking an album needs a terminal; pass the album name to --album instead");
+    }
+    let mut names: Vec<String> = client
+        .list_albums()
+        .await
+        .context("Failed to list albums")?
+        .into_iter()
+        .map(|a| a.album_name)
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names.dedup();
+
+    let mut filter = String::new();
+    loop {
+        let matches = ranked(&names, &filter);
+        if matches.is_empty() {
+            eprintln!("No album matches '{}'.", filter);
+        }
+        for (i, name) in matches.iter().take(SHOWN).enumerate() {
+            eprintln!("  {:>2}) {}", i + 1, name);
+        }
+        if matches.len() > SHOWN {
+            eprintln!(
+                "  ... and {} more; type to narrow them down",
+                matches.len() - SHOWN
+            );
+        }
+        if filter.is_empty() {
+            eprint!("Album (number, text to filter, or a new name): ");
+        } else {
+            eprint!(
+                "Album (number, text to filter, or + to create '{}'): ",
+                filter
+            );
+        }
+        std::io::stderr().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            bail!("No album picked");
+        }
+        let input = line.trim();
+        if input == "+" && !filter.is_empty() {
+            return Ok(filter);
+        }
+        if let Ok(number) = input.parse::<usize>()
+            && let Some(name) = matches
+                .get(number.wrapping_sub(1))
+                .filter(|_| number <= SHOWN)
+        {
+            return Ok(name.to_string());
+        }
+        if let Some(name) = names.iter().find(|n| n.eq_ignore_ascii_case(input)) {
+            return Ok(name.clone());
+        }
+        filter = input.to_string();
+        if !filter.is_empty() && ranked(&names, &filter).is_empty() {
+            eprint!("Create new album '{}'? [Y/n] ", filter);
+            std::io::stderr().flush()?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            if !matches!(answer.trim().to_lowercase().as_str(), "n" | "no") {
+                return Ok(filter);
+            }
+            filter.clear();
+        }
+    }
+}
+
+/// The names matching `filter`, best matches first.
+fn ranked<'a>(names: &'a [String], filter: &str) -> Vec<&'a str> {
+    let mut scored: Vec<(usize, &str)> = names
+        .iter()
+        .filter_map(|name| Some((score(filter, name)?, name.as_str())))
+        .collect();
+    scored.sort_by_key(|(score, _)| *score);
+    scored.into_iter().map(|(_, name)| name).collect()
+}
+
+/// How well `name` matches `filter` (lower is better): the letters of the filter have to
+/// appear in order, and each letter skipped on the way costs a point.
+fn score(filter: &str, name: &str) -> Option<usize> {
+    let mut name_chars = name.chars().flat_map(char::to_lowercase);
+    let mut skipped = 0;
+    for wanted in filter.chars().flat_map(char::to_lowercase) {
+        loop {
+            let c = name_chars.next()?;
+            if c == wanted {
+                break;
+            }
+            skipped += 1;
+        }
+    }
+    Some(skipped)
+}
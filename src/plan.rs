@@ -0,0 +1,237 @@
+use crate::api::ImmichClient;
+use crate::checksums::ChecksumCache;
+use crate::existing::{Difference, DuplicatePolicy};
+use crate::progress::{self, Progress};
+use crate::upload::{self, UploadItem, UploadOptions};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// Albums listed by name in the plan before the rest are summed up.
+const LISTED_ALBUMS: usize = 10;
+
+/// An upload prepared ahead of time by `plan` and carried out by `upload --manifest`, so
+/// it can be reviewed (and edited) first.
+#[derive(Serialize, Deserialize)]
+pub struct Plan {
+    pub created_at: DateTime<Utc>,
+    /// Server the files were checked against.
+    pub server: String,
+    pub directory: PathBuf,
+    pub files: Vec<PlannedFile>,
+}
+
+/// A file in a plan, with the albums it goes to.
+#[derive(Serialize, Deserialize)]
+pub struct PlannedFile {
+    #[serde(flatten)]
+    pub item: UploadItem,
+    pub size: u64,
+    /// Base64-encoded SHA-1; files changed after planning are not uploaded.
+    pub checksum: String,
+    /// Whether the server already had the file when the plan was made.
+    pub on_server: bool,
+}
+
+/// Scans `directory` like an upload would, hashes the files, checks which the server
+/// already has and writes the result to `output` without uploading anything.
+pub async fn write_plan(
+    client: &ImmichClient,
+    directory: &Path,
+    recursive: bool,
+    output: &Path,
+    mut options: UploadOptions,
+) -> Result<()> {
+    if !directory.is_dir() {
+        bail!("Path {:?} is not a directory", directory);
+    }
+    // Absolute paths keep the plan usable from another working directory.
+    let directory = &directory.canonicalize()?;
+    let items = upload::scan_directory(directory, recursive, &mut options);
+    progress::note(format!("Hashing {} files...", items.len()));
+    let mut checksums = ChecksumCache::load();
+    let pb = Progress::new(items.len())?;
+    let mut files = Vec::new();
+    for item in items {
+        let hashed = std::fs::metadata(&item.path)
+            .and_then(|m| Ok((m.len(), checksums.checksum(&item.path)?)));
+        match hashed {
+            Ok((size, checksum)) => files.push(PlannedFile {
+                item,
+                size,
+                checksum,
+                on_server: false,
+            }),
+            Err(e) => pb.warn(format!("Failed to read {:?}: {}", item.path, e)),
+        }
+        pb.inc();
+    }
+    pb.finish("Hashing complete");
+    if let Err(e) = checksums.save() {
+        progress::note(format!("Failed to save checksum cache: {:#}", e));
+    }
+
+    let indexed: Vec<(String, String)> = files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (i.to_string(), f.checksum.clone()))
+        .collect();
+    for batch in indexed.chunks(1000) {
+        for result in client
+            .bulk_upload_check(batch)
+            .await
+            .context("Failed to check which files the server has")?
+        {
+            if result.reason.as_deref() == Some("duplicate")
+                && let Some(file) = result.id.parse().ok().and_then(|i: usize| files.get_mut(i))
+            {
+                file.on_server = true;
+            }
+        }
+    }
+
+    let plan = Plan {
+        created_at: Utc::now(),
+        server: client.server_url().to_string(),
+        directory: directory.to_path_buf(),
+        files,
+    };
+    std::fs::write(output, serde_json::to_string_pretty(&plan)?)
+        .with_context(|| format!("Failed to write {:?}", output))?;
+    let new: Vec<&PlannedFile> = plan.files.iter().filter(|f| !f.on_server).collect();
+    progress::note(format!(
+        "Planned {} files: {} new ({}), {} already on the server.",
+        plan.files.len(),
+        new.len(),
+        HumanBytes(new.iter().map(|f| f.size).sum()),
+        plan.files.len() - new.len()
+    ));
+    progress::note(format!(
+        "Review {:?}, then run `upload --manifest {}` to carry it out.",
+        output,
+        output.display()
+    ));
+    Ok(())
+}
+
+/// Uploads the files of a plan into the albums it names. Files changed since planning are
+/// left out, and files the server has by then are only added to their albums.
+pub async fn upload_plan(
+    client: ImmichClient,
+    manifest: &Path,
+    concurrent: usize,
+    mut options: UploadOptions,
+) -> Result<()> {
+    let plan: Plan = serde_json::from_str(
+        &std::fs::read_to_string(manifest)
+            .with_context(|| format!("Failed to read {:?}", manifest))?,
+    )
+    .with_context(|| format!("Failed to parse {:?}", manifest))?;
+    if plan.server != client.server_url() {
+        progress::note(format!(
+            "The plan was made against {}, uploading to {}.",
+            plan.server,
+            client.server_url()
+        ));
+    }
+
+    let mut checksums = ChecksumCache::load();
+    let mut changed = 0;
+    let mut items = Vec::new();
+    for file in plan.files {
+        match checksums.checksum(&file.item.path) {
+            Ok(checksum) if checksum == file.checksum => items.push(file.item),
+            _ => {
+                progress::note(format!(
+                    "{:?} changed or disappeared since the plan was made; skipping it.",
+                    file.item.path
+                ));
+                changed += 1;
+            }
+        }
+    }
+    if let Err(e) = checksums.save() {
+        progress::note(format!("Failed to save checksum cache: {:#}", e));
+    }
+    if changed > 0 {
+        options.incomplete = true;
+    }
+    if items.is_empty() {
+        progress::note("Nothing left to upload from the plan.");
+        return Ok(());
+    }
+
+    // The plan already says which albums each file goes to.
+    options.album = None;
+    options.album_mirror = None;
+    options.skip_existing = true;
+    upload::upload_items(client, items, concurrent, options, None).await
+}
+
+/// Shows what a run is about to upload and where, and asks whether to go ahead.
+pub async fn confirm(
+    client: &ImmichClient,
+    items: &[UploadItem],
+    differences: &[Difference<'_>],
+    options: &UploadOptions,
+) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        bail!("--interactive needs a terminal to ask on; pass --yes to upload without asking");
+    }
+    let size: u64 = items
+        .iter()
+        .filter_map(|item| std::fs::metadata(&item.path).ok())
+        .map(|m| m.len())
+        .sum();
+    let target = if client.is_shared_link() {
+        "a shared link".to_string()
+    } else {
+        match client.current_user().await {
+            Ok(user) => format!("{} <{}>", user.name, user.email),
+            Err(_) => "the configured user".to_string(),
+        }
+    };
+    let mut albums: BTreeMap<&str, usize> = BTreeMap::new();
+    for item in items {
+        for album in &item.albums {
+            *albums.entry(album).or_default() += 1;
+        }
+    }
+
+    eprintln!("Upload plan:");
+    eprintln!("  Files:  {} ({})", items.len(), HumanBytes(size));
+    eprintln!("  Server: {}", client.server_url());
+    eprintln!("  User:   {}", target);
+    if let Some(visibility) = options.visibility {
+        eprintln!("  Visibility: {:?}", visibility);
+    }
+    if !albums.is_empty() {
+        eprintln!("  Albums: {}", albums.len());
+        for (album, count) in albums.iter().take(LISTED_ALBUMS) {
+            eprintln!("    {} ({} files)", album, count);
+        }
+        if albums.len() > LISTED_ALBUMS {
+            eprintln!("    ... and {} more", albums.len() - LISTED_ALBUMS);
+        }
+    }
+    if !differences.is_empty() {
+        let verb = match options.on_duplicate {
+            DuplicatePolicy::Ask => "Ask about updating",
+            _ => "Update",
+        };
+        eprintln!(
+            "  Existing: {} {} assets with a different date or metadata",
+            verb,
+            differences.len()
+        );
+    }
+    eprint!("Upload {} files? [y/N] ", items.len());
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,348 @@
+use crate::logging::{self, Level};
+use anyhow::Result;
+use indicatif::{HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How often plain progress lines are printed.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How transfer progress is shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressMode {
+    /// An interactive progress bar.
+    Bar,
+    /// A summary line every few seconds, for logs and CI.
+    Plain,
+    /// No progress output; errors and summaries are still printed.
+    None,
+    /// One JSON event per line on stdout; all other messages go to stderr.
+    Json,
+}
+
+/// A machine-readable progress event, printed as one JSON line in json mode.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A directory is being scanned for files.
+    ScanStarted { path: &'a Path },
+    /// A file will be uploaded in this run.
+    FileQueued { path: &'a Path },
+    /// Another file finished, successfully or not. Byte counts are included when the run
+    /// tracks them.
+    UploadProgress {
+        done: u64,
+        total: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes_done: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes_total: Option<u64>,
+    },
+    /// A file finished; `asset_id` is missing for duplicates the server didn't identify.
+    FileDone {
+        path: &'a Path,
+        ok: bool,
+        asset_id: Option<&'a str>,
+        error: Option<String>,
+    },
+    /// The run ended.
+    RunSummary {
+        total: usize,
+        uploaded: usize,
+        /// Files among `uploaded` the server already had.
+        duplicates: usize,
+        failed: usize,
+        not_started: usize,
+        elapsed_secs: u64,
+    },
+}
+
+static MODE: OnceLock<ProgressMode> = OnceLock::new();
+
+/// Sets the progress mode for this run. Without one, a bar is drawn when stdout is a
+/// terminal and plain lines are printed otherwise.
+pub fn set_mode(mode: Option<ProgressMode>) {
+    let mode = mode.unwrap_or(if std::io::stdout().is_terminal() {
+        ProgressMode::Bar
+    } else {
+        ProgressMode::Plain
+    });
+    let _ = MODE.set(mode);
+}
+
+/// The progress mode of this run.
+pub fn mode() -> ProgressMode {
+    *MODE.get().unwrap_or(&ProgressMode::Bar)
+}
+
+/// Prints an event line in json mode; does nothing otherwise.
+pub fn emit(event: Event) {
+    if mode() != ProgressMode::Json {
+        return;
+    }
+    if let Ok(line) = serde_json::to_string(&event) {
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+}
+
+/// Prints an informational message: on stdout, or on stderr in json mode so that stdout
+/// only carries events.
+pub fn note(message: impl AsRef<str>) {
+    logging::log(Level::Info, message.as_ref());
+    match mode() {
+        ProgressMode::Json => eprintln!("{}", message.as_ref()),
+        _ => println!("{}", message.as_ref()),
+    }
+}
+
+/// Progress of a batch of transfers, shown according to the selected mode.
+#[derive(Clone)]
+pub struct Progress {
+    mode: ProgressMode,
+    /// Counts in every mode; only drawn in bar mode.
+    bar: ProgressBar,
+    /// When the last plain line was printed.
+    last_report: Arc<Mutex<Instant>>,
+    /// Finished and total files when the bar counts bytes instead.
+    files: Option<Arc<FileCount>>,
+    /// Holds the bar and one line per running transfer, with the style of those lines; in
+    /// bar mode when counting bytes.
+    multi: Option<(MultiProgress, ProgressStyle)>,
+}
+
+struct FileCount {
+    done: AtomicU64,
+    total: u64,
+}
+
+impl Progress {
+    /// Starts tracking `total` transfers.
+    pub fn new(total: usize) -> Result<Self> {
+        let mode = mode();
+        let bar = match mode {
+            ProgressMode::Bar => ProgressBar::new(total as u64).with_style(bar_style()?),
+            _ => ProgressBar::hidden(),
+        };
+        bar.set_length(total as u64);
+        Ok(Progress {
+            mode,
+            bar,
+            last_report: Arc::new(Mutex::new(Instant::now())),
+            files: None,
+            multi: None,
+        })
+    }
+
+    /// Starts tracking `files` transfers of `bytes` in total. The bar and its ETA follow
+    /// the bytes sent through [`Progress::transfer`], so a few large videos don't throw
+    /// them off; the file count is shown next to it.
+    pub fn bytes(files: usize, bytes: u64) -> Result<Self> {
+        let mode = mode();
+        let (bar, multi) = match mode {
+            ProgressMode::Bar => {
+                let multi = MultiProgress::new();
+                let bar = multi.add(ProgressBar::new(bytes).with_style(bytes_style()?));
+                (bar, Some((multi, transfer_style()?)))
+            }
+            _ => (ProgressBar::hidden(), None),
+        };
+        bar.set_length(bytes);
+        bar.set_message(format!("0/{} files", files));
+        Ok(Progress {
+            mode,
+            bar,
+            last_report: Arc::new(Mutex::new(Instant::now())),
+            files: Some(Arc::new(FileCount {
+                done: AtomicU64::new(0),
+                total: files as u64,
+            })),
+            multi,
+        })
+    }
+
+    /// Tracks the bytes of one transfer of `size` bytes, shown on its own line below the
+    /// bar as `name` while it runs. Whatever wasn't reported by the time the last handle
+    /// is dropped counts as done, so failed and skipped transfers don't hold the bar back.
+    pub fn transfer(&self, size: u64, name: &str) -> Transfer {
+        let line = self.multi.as_ref().map(|(multi, style)| {
+            let line = multi.add(ProgressBar::new(size).with_style(style.clone()));
+            line.set_message(name.to_string());
+            line
+        });
+        Transfer(Arc::new(TransferState {
+            bar: self.bar.clone(),
+            line,
+            size,
+            sent: AtomicU64::new(0),
+        }))
+    }
+
+    /// Counts one finished transfer.
+    pub fn inc(&self) {
+        let (done, total) = match &self.files {
+            Some(files) => {
+                let done = files.done.fetch_add(1, Ordering::SeqCst) + 1;
+                self.bar
+                    .set_message(format!("{}/{} files", done, files.total));
+                (done, files.total)
+            }
+            None => {
+                self.bar.inc(1);
+                (self.bar.position(), self.bar.length().unwrap_or(0))
+            }
+        };
+        if self.mode == ProgressMode::Json {
+            let bytes = self.files.is_some();
+            emit(Event::UploadProgress {
+                done,
+                total,
+                bytes_done: bytes.then(|| self.bar.position()),
+                bytes_total: bytes.then(|| self.bar.length().unwrap_or(0)),
+            });
+        }
+        if self.mode != ProgressMode::Plain {
+            return;
+        }
+        let mut last_report = self.last_report.lock().unwrap();
+        if last_report.elapsed() >= PLAIN_INTERVAL {
+            *last_report = Instant::now();
+            println!("Progress: {}", self.summary());
+        }
+    }
+
+    /// Prints a message without disturbing the bar.
+    pub fn println(&self, message: impl AsRef<str>) {
+        match self.mode {
+            ProgressMode::Bar => {
+                logging::log(Level::Info, message.as_ref());
+                self.bar.println(message)
+            }
+            _ => note(message),
+        }
+    }
+
+    /// Prints a problem without disturbing the bar, logging it as a warning.
+    pub fn warn(&self, message: impl AsRef<str>) {
+        logging::log(Level::Warning, message.as_ref());
+        match self.mode {
+            ProgressMode::Bar => self.bar.println(message),
+            ProgressMode::Json => eprintln!("{}", message.as_ref()),
+            _ => println!("{}", message.as_ref()),
+        }
+    }
+
+    /// Time since tracking started.
+    pub fn elapsed(&self) -> Duration {
+        self.bar.elapsed()
+    }
+
+    /// Ends the bar, or prints a final plain line, with `message`.
+    pub fn finish(&self, message: &'static str) {
+        logging::log(Level::Notice, &format!("{}: {}", message, self.summary()));
+        match self.mode {
+            ProgressMode::Bar => self.bar.finish_with_message(message),
+            ProgressMode::Plain => println!("{}: {}", message, self.summary()),
+            ProgressMode::None | ProgressMode::Json => {}
+        }
+    }
+
+    /// Leaves the bar where it stopped, or prints a final plain line, with `message`.
+    pub fn abandon(&self, message: &'static str) {
+        logging::log(Level::Notice, &format!("{}: {}", message, self.summary()));
+        match self.mode {
+            ProgressMode::Bar => self.bar.abandon_with_message(message),
+            ProgressMode::Plain => println!("{}: {}", message, self.summary()),
+            ProgressMode::None | ProgressMode::Json => {}
+        }
+    }
+
+    /// "done/total (percent), elapsed" for plain lines, with sizes and the time left when
+    /// counting bytes.
+    fn summary(&self) -> String {
+        let position = self.bar.position();
+        let length = self.bar.length().unwrap_or(0);
+        if let Some(files) = &self.files {
+            return format!(
+                "{}/{} files, {}/{} ({}%), {}s elapsed, {} left",
+                files.done.load(Ordering::SeqCst),
+                files.total,
+                HumanBytes(position),
+                HumanBytes(length),
+                position * 100 / length.max(1),
+                self.bar.elapsed().as_secs(),
+                HumanDuration(self.bar.eta())
+            );
+        }
+        format!(
+            "{}/{} ({}%), {}s elapsed",
+            position,
+            length,
+            position * 100 / length.max(1),
+            self.bar.elapsed().as_secs()
+        )
+    }
+}
+
+/// The bytes of one transfer, reported as they are sent.
+#[derive(Clone)]
+pub struct Transfer(Arc<TransferState>);
+
+struct TransferState {
+    bar: ProgressBar,
+    /// The transfer's own line, in bar mode.
+    line: Option<ProgressBar>,
+    size: u64,
+    sent: AtomicU64,
+}
+
+impl Transfer {
+    /// Counts `bytes` more as sent, never more than the size given up front.
+    pub fn add(&self, bytes: u64) {
+        let before = self.0.sent.fetch_add(bytes, Ordering::SeqCst);
+        let counted = bytes.min(self.0.size.saturating_sub(before));
+        self.0.bar.inc(counted);
+        if let Some(line) = &self.0.line {
+            line.inc(counted);
+        }
+    }
+}
+
+impl Drop for TransferState {
+    fn drop(&mut self) {
+        self.bar.inc(self.size.saturating_sub(*self.sent.get_mut()));
+        if let Some(line) = &self.line {
+            line.finish_and_clear();
+        }
+    }
+}
+
+/// Style of the transfer progress bar.
+fn bar_style() -> Result<ProgressStyle> {
+    Ok(ProgressStyle::default_bar()
+        .template(
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}",
+        )?
+        .progress_chars("#>-"))
+}
+
+/// Style of the progress bar when it counts bytes.
+fn bytes_style() -> Result<ProgressStyle> {
+    Ok(ProgressStyle::default_bar()
+        .template(
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {binary_bytes_per_sec} ({eta}) {msg}",
+        )?
+        .progress_chars("#>-"))
+}
+
+/// Style of the line of a running transfer.
+fn transfer_style() -> Result<ProgressStyle> {
+    Ok(ProgressStyle::with_template(
+        "  {wide_msg} {percent:>3}% {binary_bytes_per_sec:>12}",
+    )?)
+}
@@ -0,0 +1,209 @@
+use crate::api::{Asset, ImmichClient};
+use crate::checksums::ChecksumCache;
+use crate::progress::{self, Progress};
+use crate::upload::{self, is_image_or_video, is_partial_file};
+use anyhow::{Context, Result, bail};
+use indicatif::HumanBytes;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// How a local file compares to the asset it was paired with.
+enum PairStatus {
+    /// Same checksum.
+    Identical,
+    /// Same name, but different contents.
+    Differs {
+        local_size: u64,
+        server_size: Option<u64>,
+    },
+}
+
+/// Compares the media files below `directory` with the assets `device_id` uploaded, and
+/// reports files only found locally, assets only found on the server, and pairs whose
+/// contents differ. Files are paired by checksum, or by name when no asset has theirs.
+/// Files that can't be read are reported too. Fails when any local file is not known to
+/// be safely on the server.
+pub async fn reconcile(
+    client: &ImmichClient,
+    directory: &Path,
+    recursive: bool,
+    partial_files: &[String],
+    device_id: &str,
+) -> Result<()> {
+    if !directory.is_dir() {
+        bail!("Path {:?} is not a directory", directory);
+    }
+    let files: Vec<(PathBuf, u64)> = upload::walk(directory, recursive, None, false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_type().is_file()
+                && !is_partial_file(e.path(), partial_files)
+                && is_image_or_video(e.path())
+        })
+        .filter_map(|e| Some((e.path().to_path_buf(), e.metadata().ok()?.len())))
+        .collect();
+    progress::note(format!(
+        "Found {} media files ({}) in {:?}.",
+        files.len(),
+        HumanBytes(files.iter().map(|(_, size)| size).sum()),
+        directory
+    ));
+
+    let assets = device_assets(client, device_id).await?;
+    progress::note(format!(
+        "Found {} assets uploaded from device '{}'.",
+        assets.len(),
+        device_id
+    ));
+    let mut by_checksum: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, asset) in assets.iter().enumerate() {
+        if let Some(checksum) = &asset.checksum {
+            by_checksum.entry(checksum).or_default().push(index);
+        }
+        by_name
+            .entry(&asset.original_file_name)
+            .or_default()
+            .push(index);
+    }
+
+    let mut checksums = ChecksumCache::load();
+    let pb = Progress::new(files.len())?;
+    let mut paired: HashSet<usize> = HashSet::new();
+    let mut unpaired_files = Vec::new();
+    let mut local_only = Vec::new();
+    let mut unreadable = Vec::new();
+    let mut pairs = Vec::new();
+    for (path, size) in &files {
+        let checksum = match checksums.checksum(path) {
+            Ok(checksum) => checksum,
+            Err(e) => {
+                pb.warn(format!("Failed to read {:?}: {}", path, e));
+                unreadable.push(path);
+                pb.inc();
+                continue;
+            }
+        };
+        let found = by_checksum
+            .get(checksum.as_str())
+            .and_then(|indexes| indexes.iter().find(|i| !paired.contains(i)));
+        match found {
+            Some(&index) => {
+                paired.insert(index);
+                pairs.push((path, index, PairStatus::Identical));
+            }
+            None => unpaired_files.push((path, *size)),
+        }
+        pb.inc();
+    }
+    pb.finish("Checksums complete");
+    let _ = checksums.save();
+
+    // Only files whose contents no asset has are paired by name.
+    for (path, size) in unpaired_files {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let found = by_name
+            .get(name.as_ref())
+            .and_then(|indexes| indexes.iter().find(|i| !paired.contains(i)));
+        match found {
+            Some(&index) => {
+                paired.insert(index);
+                let status = PairStatus::Differs {
+                    local_size: size,
+                    server_size: assets[index].file_size(),
+                };
+                pairs.push((path, index, status));
+            }
+            None => local_only.push((path, size)),
+        }
+    }
+    local_only.sort();
+    let server_only: Vec<&Asset> = (0..assets.len())
+        .filter(|i| !paired.contains(i))
+        .map(|i| &assets[i])
+        .collect();
+
+    let differing: Vec<_> = pairs
+        .iter()
+        .filter(|(_, _, status)| matches!(status, PairStatus::Differs { .. }))
+        .collect();
+    if !local_only.is_empty() {
+        println!("Only local ({}):", local_only.len());
+        for (path, size) in &local_only {
+            println!("  {} ({})", path.display(), HumanBytes(*size));
+        }
+    }
+    if !server_only.is_empty() {
+        println!("Only on the server ({}):", server_only.len());
+        for asset in &server_only {
+            println!("  {} ({})", asset.original_file_name, asset.id);
+        }
+    }
+    if !unreadable.is_empty() {
+        println!("Unreadable ({}):", unreadable.len());
+        for path in &unreadable {
+            println!("  {}", path.display());
+        }
+    }
+    if !differing.is_empty() {
+        println!("Different contents ({}):", differing.len());
+        for (path, index, status) in &differing {
+            if let PairStatus::Differs {
+                local_size,
+                server_size,
+            } = status
+            {
+                let server_size = server_size
+                    .map(|s| HumanBytes(s).to_string())
+                    .unwrap_or_else(|| "unknown size".to_string());
+                println!(
+                    "  {} ({}) vs {} ({})",
+                    path.display(),
+                    HumanBytes(*local_size),
+                    assets[*index].id,
+                    server_size
+                );
+            }
+        }
+    }
+    println!(
+        "{} identical, {} different, {} only local, {} only on the server, {} unreadable.",
+        pairs.len() - differing.len(),
+        differing.len(),
+        local_only.len(),
+        server_only.len(),
+        unreadable.len()
+    );
+
+    if !local_only.is_empty() || !differing.is_empty() {
+        bail!(
+            "{} local files are not on the server as they are",
+            local_only.len() + differing.len()
+        );
+    }
+    if !unreadable.is_empty() {
+        bail!(
+            "{} local files could not be read to compare them",
+            unreadable.len()
+        );
+    }
+    Ok(())
+}
+
+/// Every asset uploaded from `device_id`, including the motion parts of Live Photos,
+/// which search leaves out.
+async fn device_assets(client: &ImmichClient, device_id: &str) -> Result<Vec<Asset>> {
+    let mut assets = client
+        .search_assets(json!({ "deviceId": device_id }))
+        .await
+        .context("Failed to list assets")?;
+    let videos = client.live_photo_videos(&assets).await?;
+    assets.extend(
+        videos
+            .into_iter()
+            .filter(|video| video.device_id.as_deref() == Some(device_id)),
+    );
+    Ok(assets)
+}
@@ -0,0 +1,229 @@
+use crate::api::ImmichClient;
+use crate::assets;
+use crate::backup::{BackupAsset, MANIFEST, Manifest};
+use crate::exit;
+use crate::progress::Progress;
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use reqwest::multipart;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Uploads the assets of a backup written by `backup` and rebuilds its albums, favorites,
+/// archive state and descriptions. Assets the server already has (by checksum) are not
+/// sent again, so an interrupted restore can simply be run again.
+pub async fn restore(client: &ImmichClient, directory: &Path, concurrent: usize) -> Result<()> {
+    let path = directory.join(MANIFEST);
+    let manifest: Manifest = serde_json::from_str(
+        &std::fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?,
+    )
+    .with_context(|| format!("Failed to parse {:?}", path))?;
+
+    // Backup asset ID -> asset ID on this server.
+    let mut id_map = already_restored(client, &manifest.assets).await?;
+    println!(
+        "{} of {} assets are already on the server.",
+        id_map.len(),
+        manifest.assets.len()
+    );
+
+    // Motion parts go first, so the stills can be linked to them.
+    let videos: HashSet<&str> = manifest
+        .assets
+        .iter()
+        .filter_map(|a| a.live_photo_video_id.as_deref())
+        .collect();
+    let (motion, stills): (Vec<&BackupAsset>, Vec<&BackupAsset>) = manifest
+        .assets
+        .iter()
+        .filter(|a| !id_map.contains_key(&a.id))
+        .partition(|a| videos.contains(a.id.as_str()));
+    let total = motion.len() + stills.len();
+    if total > 0 {
+        println!("Uploading {} assets...", total);
+        let pb = Progress::new(total)?;
+        for assets in [motion, stills] {
+            let restored = Mutex::new(HashMap::new());
+            let id_map_ref = &id_map;
+            let mut uploads = futures::stream::iter(assets)
+                .map(|asset| {
+                    let pb = pb.clone();
+                    let restored = &restored;
+                    async move {
+                        let video_id = asset
+                            .live_photo_video_id
+                            .as_ref()
+                            .and_then(|id| id_map_ref.get(id));
+                        match upload(client, directory, asset, video_id).await {
+                            Ok(id) => {
+                                restored.lock().unwrap().insert(asset.id.clone(), id);
+                            }
+                            Err(e) => pb.warn(format!(
+                                "Failed to restore {}: {:#}",
+                                asset.original_file_name, e
+                            )),
+                        }
+                        pb.inc();
+                    }
+                })
+                .buffer_unordered(concurrent);
+            while uploads.next().await.is_some() {}
+            drop(uploads);
+            id_map.extend(restored.into_inner().unwrap());
+        }
+        pb.finish("Assets restored");
+    }
+
+    restore_metadata(client, &manifest, &id_map).await?;
+    let total = manifest.assets.len();
+    exit::check_failures(total - id_map.len(), total)
+}
+
+/// Asks the server which backed-up assets it already has, by checksum.
+async fn already_restored(
+    client: &ImmichClient,
+    assets: &[BackupAsset],
+) -> Result<HashMap<String, String>> {
+    let files: Vec<(String, String)> = assets
+        .iter()
+        .filter_map(|a| Some((a.id.clone(), a.checksum.clone()?)))
+        .collect();
+    let mut existing = HashMap::new();
+    for batch in files.chunks(1000) {
+        for result in client
+            .bulk_upload_check(batch)
+            .await
+            .context("Failed to check which assets the server has")?
+        {
+            if result.reason.as_deref() == Some("duplicate")
+                && let Some(asset_id) = result.asset_id
+            {
+                existing.insert(result.id, asset_id);
+            }
+        }
+    }
+    Ok(existing)
+}
+
+/// Uploads one backed-up asset and returns its ID on the server.
+async fn upload(
+    client: &ImmichClient,
+    directory: &Path,
+    asset: &BackupAsset,
+    live_photo_video_id: Option<&String>,
+) -> Result<String> {
+    let path = directory.join(&asset.file);
+    let file = tokio::fs::File::open(&path)
+        .await
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let size = file.metadata().await?.len();
+    let part = multipart::Part::stream_with_length(file, size)
+        .file_name(asset.original_file_name.clone())
+        .mime_str(
+            mime_guess::from_path(&asset.original_file_name)
+                .first_or_octet_stream()
+                .as_ref(),
+        )?;
+    let form = assets::copy_form(
+        part,
+        format!("backup-{}", asset.id),
+        (asset.file_created_at, asset.file_modified_at),
+        asset.favorite,
+        asset.archived,
+        live_photo_video_id.cloned(),
+    );
+    let uploaded = client
+        .upload_asset(form)
+        .await?
+        .context("The server rejected the asset without reporting its ID")?;
+    Ok(uploaded.id)
+}
+
+/// Sets favorites, archive state and descriptions on all restored assets (including those
+/// restored by an earlier run), then rebuilds the albums, reusing albums of the same name.
+async fn restore_metadata(
+    client: &ImmichClient,
+    manifest: &Manifest,
+    id_map: &HashMap<String, String>,
+) -> Result<()> {
+    println!("Restoring favorites, archive state and descriptions...");
+    let restored: Vec<(&BackupAsset, &String)> = manifest
+        .assets
+        .iter()
+        .filter_map(|a| Some((a, id_map.get(&a.id)?)))
+        .collect();
+    let favorites: Vec<String> = restored
+        .iter()
+        .filter(|(a, _)| a.favorite)
+        .map(|(_, id)| id.to_string())
+        .collect();
+    let archived: Vec<String> = restored
+        .iter()
+        .filter(|(a, _)| a.archived)
+        .map(|(_, id)| id.to_string())
+        .collect();
+    for batch in favorites.chunks(500) {
+        client
+            .update_assets(batch, &json!({ "isFavorite": true }))
+            .await
+            .context("Failed to restore favorites")?;
+    }
+    for batch in archived.chunks(500) {
+        client
+            .update_assets(
+                batch,
+                &json!({ "isArchived": true, "visibility": "archive" }),
+            )
+            .await
+            .context("Failed to restore the archive")?;
+    }
+    for (asset, id) in &restored {
+        if let Some(description) = &asset.description {
+            client
+                .update_asset_description(id, description)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to restore description of {}",
+                        asset.original_file_name
+                    )
+                })?;
+        }
+    }
+
+    println!("Restoring {} albums...", manifest.albums.len());
+    let mut existing: HashMap<String, String> = client
+        .list_albums()
+        .await
+        .context("Failed to list albums")?
+        .into_iter()
+        .map(|a| (a.album_name, a.id))
+        .collect();
+    for album in &manifest.albums {
+        let album_id = match existing.get(&album.name) {
+            Some(id) => id.clone(),
+            None => {
+                let created = client
+                    .create_album(&album.name, Some(&album.description))
+                    .await
+                    .with_context(|| format!("Failed to create album '{}'", album.name))?;
+                existing.insert(album.name.clone(), created.id.clone());
+                created.id
+            }
+        };
+        let asset_ids: Vec<String> = album
+            .asset_ids
+            .iter()
+            .filter_map(|id| id_map.get(id).cloned())
+            .collect();
+        for batch in asset_ids.chunks(500) {
+            client
+                .add_assets_to_album(&album_id, batch)
+                .await
+                .with_context(|| format!("Failed to fill album '{}'", album.name))?;
+        }
+    }
+    Ok(())
+}
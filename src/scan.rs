@@ -0,0 +1,222 @@
+use crate::checksums::ChecksumCache;
+use crate::upload::{self, is_image_or_video, is_partial_file};
+use anyhow::Result;
+use indicatif::HumanBytes;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// A media file found by the scan.
+struct LocalFile {
+    path: PathBuf,
+    size: u64,
+}
+
+/// Lists the media files below `directory` that an upload would pick up, and with
+/// `duplicates` reports byte-identical files. `similar` additionally reports JPEGs whose
+/// perceptual hashes differ in at most that many bits. Temporary and partial files are
+/// left out like uploads do, including those matching `partial_files`.
+pub fn scan(
+    directory: &Path,
+    recursive: bool,
+    duplicates: bool,
+    similar: Option<u32>,
+    partial_files: &[String],
+    max_depth: Option<usize>,
+    one_file_system: bool,
+) -> Result<()> {
+    if !directory.is_dir() {
+        anyhow::bail!("Path {:?} is not a directory", directory);
+    }
+    let files: Vec<LocalFile> = upload::walk(directory, recursive, max_depth, one_file_system)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_type().is_file()
+                && !is_partial_file(e.path(), partial_files)
+                && is_image_or_video(e.path())
+        })
+        .filter_map(|e| {
+            Some(LocalFile {
+                size: e.metadata().ok()?.len(),
+                path: e.into_path(),
+            })
+        })
+        .collect();
+    println!(
+        "Found {} media files ({}) in {:?}.",
+        files.len(),
+        HumanBytes(files.iter().map(|f| f.size).sum()),
+        directory
+    );
+    if !duplicates && similar.is_none() {
+        return Ok(());
+    }
+
+    let identical = identical_groups(&files);
+    let mut reclaimable = 0;
+    for group in &identical {
+        let size = group[0].size;
+        reclaimable += size * (group.len() as u64 - 1);
+        println!(
+            "{} identical files ({} each):",
+            group.len(),
+            HumanBytes(size)
+        );
+        for file in group {
+            println!("  {}", file.path.display());
+        }
+    }
+
+    let mut similar_count = 0;
+    if let Some(max_distance) = similar {
+        // Only one copy of identical files takes part, so they aren't reported twice.
+        let copies: HashSet<&Path> = identical
+            .iter()
+            .flat_map(|group| group[1..].iter().map(|f| f.path.as_path()))
+            .collect();
+        let candidates: Vec<&LocalFile> = files
+            .iter()
+            .filter(|f| !copies.contains(f.path.as_path()))
+            .collect();
+        for group in similar_groups(&candidates, max_distance) {
+            similar_count += 1;
+            println!("{} similar images:", group.len());
+            for file in group {
+                println!("  {}  ({})", file.path.display(), HumanBytes(file.size));
+            }
+        }
+    }
+
+    println!(
+        "{} groups of identical files ({} reclaimable){}.",
+        identical.len(),
+        HumanBytes(reclaimable),
+        similar
+            .map(|_| format!(", {} groups of similar images", similar_count))
+            .unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Groups files with identical contents, largest savings first. Only files sharing a
+/// size are hashed.
+fn identical_groups(files: &[LocalFile]) -> Vec<Vec<&LocalFile>> {
+    let mut checksums = ChecksumCache::load();
+    let mut by_size: HashMap<u64, Vec<&LocalFile>> = HashMap::new();
+    for file in files.iter().filter(|f| f.size > 0) {
+        by_size.entry(file.size).or_default().push(file);
+    }
+
+    let mut groups = Vec::new();
+    for same_size in by_size.into_values().filter(|files| files.len() > 1) {
+        let mut by_hash: HashMap<String, Vec<&LocalFile>> = HashMap::new();
+        for file in same_size {
+            match checksums.checksum(&file.path) {
+                Ok(hash) => by_hash.entry(hash).or_default().push(file),
+                Err(e) => eprintln!("Failed to read {:?}: {}", file.path, e),
+            }
+        }
+        groups.extend(by_hash.into_values().filter(|files| files.len() > 1));
+    }
+    if let Err(e) = checksums.save() {
+        eprintln!("Failed to save checksum cache: {:#}", e);
+    }
+    for group in &mut groups {
+        group.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    groups.sort_by_key(|group| std::cmp::Reverse(group[0].size * (group.len() as u64 - 1)));
+    groups
+}
+
+/// Groups JPEGs whose perceptual hashes are at most `max_distance` bits apart
+/// (transitively, so a chain of small edits ends up in one group).
+fn similar_groups<'a>(files: &[&'a LocalFile], max_distance: u32) -> Vec<Vec<&'a LocalFile>> {
+    let hashed: Vec<(&LocalFile, u64)> = files
+        .iter()
+        .filter(|f| {
+            let mime = mime_guess::from_path(&f.path).first_or_octet_stream();
+            mime.essence_str() == "image/jpeg"
+        })
+        .filter_map(|f| Some((*f, dhash(&f.path)?)))
+        .collect();
+
+    // Union-find over every close pair; quadratic, but a comparison is a single XOR.
+    let mut parent: Vec<usize> = (0..hashed.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..hashed.len() {
+        for j in i + 1..hashed.len() {
+            if (hashed[i].1 ^ hashed[j].1).count_ones() <= max_distance {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<&LocalFile>> = HashMap::new();
+    for (i, (file, _)) in hashed.iter().enumerate() {
+        groups.entry(root(&mut parent, i)).or_default().push(*file);
+    }
+    let mut groups: Vec<_> = groups.into_values().filter(|g| g.len() > 1).collect();
+    for group in &mut groups {
+        group.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    groups.sort_by_key(|group| std::cmp::Reverse(group.len()));
+    groups
+}
+
+/// Difference hash of a JPEG: the image shrunk to 9x8 grey cells, with one bit per pair
+/// of horizontal neighbours telling whether brightness drops. Decoded at reduced scale,
+/// so even large photos are cheap to hash.
+fn dhash(path: &Path) -> Option<u64> {
+    let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(File::open(path).ok()?));
+    decoder.scale(64, 64).ok()?;
+    let pixels = decoder.decode().ok()?;
+    let info = decoder.info()?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let channels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => 1,
+        jpeg_decoder::PixelFormat::L16 => 2,
+        jpeg_decoder::PixelFormat::RGB24 => 3,
+        jpeg_decoder::PixelFormat::CMYK32 => return None,
+    };
+    if width < 9 || height < 8 {
+        return None;
+    }
+    let brightness = |x: usize, y: usize| -> u64 {
+        let pixel = &pixels[(y * width + x) * channels..][..channels];
+        match channels {
+            3 => (pixel[0] as u64 + pixel[1] as u64 + pixel[2] as u64) / 3,
+            // The high byte of 16-bit grey is enough here.
+            _ => pixel[0] as u64,
+        }
+    };
+
+    let mut cells = [[0u64; 9]; 8];
+    for (cy, row) in cells.iter_mut().enumerate() {
+        for (cx, cell) in row.iter_mut().enumerate() {
+            let (x0, x1) = (cx * width / 9, (cx + 1) * width / 9);
+            let (y0, y1) = (cy * height / 8, (cy + 1) * height / 8);
+            let sum: u64 = (y0..y1)
+                .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                .map(|(x, y)| brightness(x, y))
+                .sum();
+            *cell = sum / ((x1 - x0) * (y1 - y0)) as u64;
+        }
+    }
+
+    let mut hash = 0u64;
+    for row in &cells {
+        for pair in row.windows(2) {
+            hash = hash << 1 | (pair[0] > pair[1]) as u64;
+        }
+    }
+    Some(hash)
+}
@@ -0,0 +1,283 @@
+use crate::upload::{UploadItem, Visibility};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Tag applied to screenshots with `--screenshots tag`.
+const TAG: &str = "Screenshots";
+
+/// Words screenshot tools put in file names, in the languages of common desktop and phone
+/// systems (lowercase).
+const NAME_PATTERNS: &[&str] = &[
+    "screenshot",
+    "screen shot",
+    "screen_shot",
+    "bildschirmfoto",
+    "capture d’écran",
+    "capture d'écran",
+    "captura de pantalla",
+    "schermafbeelding",
+    "istantanea schermo",
+    "スクリーンショット",
+    "屏幕截图",
+];
+
+/// Sizes of common monitors and laptop screens, landscape, including the doubled sizes of
+/// high-density ones.
+const SCREEN_SIZES: &[(u32, u32)] = &[
+    (1280, 720),
+    (1280, 800),
+    (1280, 1024),
+    (1366, 768),
+    (1440, 900),
+    (1536, 864),
+    (1600, 900),
+    (1680, 1050),
+    (1920, 1080),
+    (1920, 1200),
+    (2048, 1536),
+    (2224, 1668),
+    (2388, 1668),
+    (2560, 1080),
+    (2560, 1440),
+    (2560, 1600),
+    (2732, 2048),
+    (2880, 1800),
+    (3024, 1964),
+    (3440, 1440),
+    (3456, 2234),
+    (3840, 2160),
+    (5120, 2880),
+];
+
+/// Longest text chunk read from a PNG to look for a screenshot tool's marker.
+const MAX_TEXT_CHUNK: u32 = 64 * 1024;
+
+/// What to do with screenshots found among the files to upload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ScreenshotAction {
+    /// Upload them with the "Screenshots" tag.
+    Tag,
+    /// Upload them into the archive.
+    Archive,
+    /// Don't upload them.
+    Skip,
+}
+
+/// Applies `action` to the screenshots among `items` and moves them into `album` instead
+/// of the albums they would have joined. Returns how many screenshots were found.
+pub fn route(
+    items: &mut Vec<UploadItem>,
+    action: Option<ScreenshotAction>,
+    album: Option<&str>,
+) -> usize {
+    let mut found = 0;
+    items.retain_mut(|item| {
+        if !is_screenshot(&item.path) {
+            return true;
+        }
+        found += 1;
+        match action {
+            Some(ScreenshotAction::Skip) => return false,
+            Some(ScreenshotAction::Tag) => item.tags.push(TAG.to_string()),
+            Some(ScreenshotAction::Archive) => item.visibility = Some(Visibility::Archive),
+            None => {}
+        }
+        if let Some(album) = album {
+            item.albums = vec![album.to_string()];
+        }
+        true
+    });
+    found
+}
+
+/// Whether a file looks like a screenshot: by its name, by what the EXIF data says about
+/// its origin (iOS marks screenshots in the user comment), or, for PNGs without camera
+/// data, by a screenshot tool's text chunk, a phone screen's aspect ratio, or a screen
+/// resolution of 72/144 dpi together with the size of a common screen.
+pub fn is_screenshot(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if NAME_PATTERNS.iter().any(|p| name.contains(p)) {
+        return true;
+    }
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "heic") {
+        return false;
+    }
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(&file))
+        .ok();
+    if let Some(exif) = &exif {
+        let mentions_screenshot = |tag| {
+            exif.get_field(tag, exif::In::PRIMARY).is_some_and(|f| {
+                let text = match &f.value {
+                    exif::Value::Undefined(bytes, _) => String::from_utf8_lossy(bytes).into(),
+                    value => value.display_as(tag).to_string(),
+                };
+                text.to_lowercase().contains("screenshot")
+            })
+        };
+        if mentions_screenshot(exif::Tag::UserComment) || mentions_screenshot(exif::Tag::Software) {
+            return true;
+        }
+        if exif
+            .get_field(exif::Tag::Model, exif::In::PRIMARY)
+            .is_some()
+        {
+            return false;
+        }
+    }
+    extension == "png" && png_looks_like_screen(&file).unwrap_or(false)
+}
+
+/// Whether a PNG has the marker, proportions or resolution and size of a screen capture.
+fn png_looks_like_screen(mut file: &File) -> std::io::Result<bool> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const INCH: f64 = 0.0254;
+
+    file.seek(SeekFrom::Start(0))?;
+    let mut signature = [0u8; 8];
+    file.read_exact(&mut signature)?;
+    if signature != SIGNATURE {
+        return Ok(false);
+    }
+    let (mut width, mut height, mut screen_dpi) = (0u32, 0u32, false);
+    loop {
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        match &header[4..] {
+            b"IHDR" => {
+                let mut data = [0u8; 8];
+                file.read_exact(&mut data)?;
+                width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+                file.seek(SeekFrom::Current(length as i64 - 8 + 4))?;
+            }
+            b"pHYs" => {
+                let mut data = [0u8; 9];
+                file.read_exact(&mut data)?;
+                let per_meter = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                // Unit 1 is pixels per meter; 0 only gives the aspect ratio.
+                let dpi = (per_meter as f64 * INCH).round();
+                screen_dpi = data[8] == 1 && (dpi == 72.0 || dpi == 144.0);
+                file.seek(SeekFrom::Current(length as i64 - 9 + 4))?;
+            }
+            // macOS and screenshot tools on Linux leave a note in the XMP or as Software.
+            b"tEXt" | b"iTXt" if length <= MAX_TEXT_CHUNK => {
+                let mut text = vec![0u8; length as usize];
+                file.read_exact(&mut text)?;
+                if String::from_utf8_lossy(&text)
+                    .to_lowercase()
+                    .contains("screenshot")
+                {
+                    return Ok(true);
+                }
+                file.seek(SeekFrom::Current(4))?;
+            }
+            // Metadata chunks come before the image data.
+            b"IDAT" | b"IEND" => break,
+            _ => {
+                file.seek(SeekFrom::Current(length as i64 + 4))?;
+            }
+        }
+    }
+    let (long, short) = (width.max(height) as f64, width.min(height) as f64);
+    // Phones since ~2017 have screens of 18:9 to 21.5:9.
+    if short > 0.0 && (2.0..=2.4).contains(&(long / short)) {
+        return Ok(true);
+    }
+    // Image editors export at 72 dpi by default too, so the resolution only counts for
+    // images the size of a screen.
+    let size = (width.max(height), width.min(height));
+    Ok(screen_dpi && SCREEN_SIZES.contains(&size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        // The CRC isn't checked.
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn png(width: u32, height: u32, dpi: Option<u32>, text: Option<&[u8]>) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        png.extend(chunk(b"IHDR", &header));
+        if let Some(dpi) = dpi {
+            let per_meter = (dpi as f64 / 0.0254).round() as u32;
+            let mut physical = per_meter.to_be_bytes().to_vec();
+            physical.extend_from_slice(&per_meter.to_be_bytes());
+            physical.push(1);
+            png.extend(chunk(b"pHYs", &physical));
+        }
+        if let Some(text) = text {
+            png.extend(chunk(b"tEXt", text));
+        }
+        png.extend(chunk(b"IDAT", &[0; 16]));
+        png.extend(chunk(b"IEND", &[]));
+        png
+    }
+
+    fn looks_like_screen(name: &str, png: &[u8]) -> bool {
+        let path = std::env::temp_dir().join(format!(
+            "screenshot-test-{}-{}.png",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, png).unwrap();
+        let result = png_looks_like_screen(&File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn needs_a_screen_size_besides_the_resolution() {
+        assert!(!looks_like_screen("export", &png(800, 600, Some(72), None)));
+        assert!(!looks_like_screen(
+            "photo",
+            &png(4032, 3024, Some(72), None)
+        ));
+        assert!(looks_like_screen(
+            "monitor",
+            &png(1920, 1080, Some(72), None)
+        ));
+        assert!(looks_like_screen(
+            "retina",
+            &png(1800, 2880, Some(144), None)
+        ));
+        assert!(!looks_like_screen("no-dpi", &png(1920, 1080, None, None)));
+    }
+
+    #[test]
+    fn recognizes_phone_screens_and_tool_markers() {
+        assert!(looks_like_screen("phone", &png(1170, 2532, None, None)));
+        let software = b"Software\0gnome-screenshot";
+        assert!(looks_like_screen(
+            "tool",
+            &png(800, 600, None, Some(software))
+        ));
+        let editor = b"Software\0GIMP 2.10";
+        assert!(!looks_like_screen(
+            "editor",
+            &png(800, 600, Some(72), Some(editor))
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use ring::digest::{SHA256, digest};
+use serde::Deserialize;
+use std::path::Path;
+
+const LATEST_RELEASE: &str =
+    "https://api.github.com/repos/xufanglin/rimmich-uploader/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Replaces the running binary with the latest GitHub release when it is newer,
+/// after checking the download against the release's SHA256SUMS.
+pub async fn self_update(check_only: bool) -> Result<()> {
+    let http = reqwest::Client::builder()
+        .user_agent(concat!("rimmich-uploader/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let release: Release = http
+        .get(LATEST_RELEASE)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context("Failed to look up the latest release")?
+        .json()
+        .await?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = release.tag_name.trim_start_matches('v');
+    if parse_version(latest) <= parse_version(current) {
+        println!("Already up to date (v{}).", current);
+        return Ok(());
+    }
+    println!("Update available: v{} -> v{}", current, latest);
+    if check_only {
+        return Ok(());
+    }
+
+    let name = asset_name()?;
+    let find = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|a| a.name == name)
+            .with_context(|| format!("Release {} has no {}", release.tag_name, name))
+    };
+    let binary = find(&name)?;
+    let sums = find("SHA256SUMS")?;
+
+    let sums = http
+        .get(&sums.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let expected = sums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(hash, _)| hash.to_lowercase())
+        .with_context(|| format!("SHA256SUMS has no entry for {}", name))?;
+
+    println!("Downloading {}...", name);
+    let bytes = http
+        .get(&binary.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let actual: String = digest(&SHA256, &bytes)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if actual != expected {
+        anyhow::bail!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            name,
+            expected,
+            actual
+        );
+    }
+
+    let exe = std::env::current_exe()?;
+    replace_binary(&exe, &bytes).with_context(|| format!("Failed to replace {:?}", exe))?;
+    println!("Updated to v{}.", latest);
+    Ok(())
+}
+
+/// Name of the uncompressed release binary for this platform.
+fn asset_name() -> Result<String> {
+    let os = match std::env::consts::OS {
+        "linux" => "linux",
+        "macos" => "macos",
+        "windows" => "windows",
+        other => anyhow::bail!("No release binaries are published for {}", other),
+    };
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "x86_64",
+        "aarch64" => "arm64",
+        other => anyhow::bail!("No release binaries are published for {}", other),
+    };
+    Ok(format!(
+        "rimmich-uploader-{}-{}{}",
+        os,
+        arch,
+        std::env::consts::EXE_SUFFIX
+    ))
+}
+
+/// Parses "1.2.3" into comparable parts; missing or non-numeric parts count as 0.
+fn parse_version(version: &str) -> (u64, u64, u64) {
+    let mut parts = version
+        .split(['.', '-', '+'])
+        .map(|p| p.parse().unwrap_or(0));
+    (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
+}
+
+/// Writes the new binary next to the running one and swaps it in. The running binary
+/// can be renamed but not overwritten on Windows, so it is moved aside first there.
+fn replace_binary(exe: &Path, bytes: &[u8]) -> Result<()> {
+    let staged = exe.with_extension("new");
+    std::fs::write(&staged, bytes)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(windows)]
+    {
+        let old = exe.with_extension("old");
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(exe, &old)?;
+    }
+    std::fs::rename(&staged, exe)?;
+    Ok(())
+}
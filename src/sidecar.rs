@@ -0,0 +1,194 @@
+use crate::api::{Asset, ImmichClient};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Format of the metadata files written next to downloaded originals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SidecarFormat {
+    /// `photo.jpg.xmp`, read by Lightroom, darktable, digiKam and by `upload` itself.
+    Xmp,
+    /// `photo.jpg.json`, for scripts.
+    Json,
+}
+
+/// The curation a sidecar keeps, as written to JSON sidecars.
+#[derive(Serialize)]
+struct SidecarMetadata<'a> {
+    id: &'a str,
+    original_file_name: &'a str,
+    taken_at: String,
+    description: Option<&'a str>,
+    favorite: bool,
+    archived: bool,
+    rating: Option<i32>,
+    /// Full tag paths, e.g. "Places/Paris".
+    tags: Vec<&'a str>,
+    people: Vec<&'a str>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+impl<'a> SidecarMetadata<'a> {
+    fn new(asset: &'a Asset) -> Self {
+        let exif = asset.exif_info.as_ref();
+        SidecarMetadata {
+            id: &asset.id,
+            original_file_name: &asset.original_file_name,
+            taken_at: asset.file_created_at.to_rfc3339(),
+            description: asset.description(),
+            favorite: asset.is_favorite,
+            archived: asset.archived(),
+            rating: exif.and_then(|e| e.rating).filter(|r| *r != 0),
+            tags: asset
+                .tags
+                .iter()
+                .map(|t| {
+                    if t.value.is_empty() {
+                        &t.name
+                    } else {
+                        &t.value
+                    }
+                })
+                .map(String::as_str)
+                .collect(),
+            people: asset
+                .people
+                .iter()
+                .filter(|p| !p.name.is_empty())
+                .map(|p| p.name.as_str())
+                .collect(),
+            latitude: exif.and_then(|e| e.latitude),
+            longitude: exif.and_then(|e| e.longitude),
+        }
+    }
+}
+
+/// Writes the server-side metadata of an asset (description, tags, people, favorite flag,
+/// rating and location) next to its original at `original`, replacing an older sidecar.
+pub async fn write(
+    client: &ImmichClient,
+    asset_id: &str,
+    original: &Path,
+    format: SidecarFormat,
+) -> Result<()> {
+    // Search results leave out tags and people, so the full asset is fetched.
+    let asset = client
+        .get_asset(asset_id)
+        .await
+        .with_context(|| format!("Failed to fetch the metadata of {}", asset_id))?;
+    let metadata = SidecarMetadata::new(&asset);
+    let contents = match format {
+        SidecarFormat::Xmp => xmp(&metadata),
+        SidecarFormat::Json => serde_json::to_string_pretty(&metadata)?,
+    };
+    let path = path(original, format);
+    tokio::fs::write(&path, contents)
+        .await
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// The sidecar of `original`: its full name with `.xmp` or `.json` appended.
+fn path(original: &Path, format: SidecarFormat) -> PathBuf {
+    let mut path = original.as_os_str().to_owned();
+    path.push(match format {
+        SidecarFormat::Xmp => ".xmp",
+        SidecarFormat::Json => ".json",
+    });
+    PathBuf::from(path)
+}
+
+/// Renders an XMP packet. Favorites without a rating get 5 stars, as XMP has no favorite
+/// flag; `upload --favorite-rating 5` turns them back into favorites.
+fn xmp(metadata: &SidecarMetadata) -> String {
+    let mut xmp = String::from(concat!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+        " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+        "  <rdf:Description rdf:about=\"\"\n",
+        "    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n",
+        "    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n",
+        "    xmlns:exif=\"http://ns.adobe.com/exif/1.0/\"\n",
+        "    xmlns:photoshop=\"http://ns.adobe.com/photoshop/1.0/\"\n",
+        "    xmlns:lr=\"http://ns.adobe.com/lightroom/1.0/\"\n",
+        "    xmlns:Iptc4xmpExt=\"http://iptc.org/std/Iptc4xmpExt/2008-02-29/\"",
+    ));
+    let rating = metadata.rating.or(metadata.favorite.then_some(5));
+    if let Some(rating) = rating {
+        let _ = write!(xmp, "\n    xmp:Rating=\"{}\"", rating);
+    }
+    let _ = write!(
+        xmp,
+        "\n    photoshop:DateCreated=\"{}\"",
+        escape(&metadata.taken_at)
+    );
+    if let (Some(latitude), Some(longitude)) = (metadata.latitude, metadata.longitude) {
+        let _ = write!(
+            xmp,
+            "\n    exif:GPSLatitude=\"{}\"\n    exif:GPSLongitude=\"{}\"",
+            coordinate(latitude, 'N', 'S'),
+            coordinate(longitude, 'E', 'W')
+        );
+    }
+    xmp.push_str(">\n");
+    if let Some(description) = metadata.description {
+        let _ = write!(
+            xmp,
+            "   <dc:description>\n    <rdf:Alt>\n     <rdf:li xml:lang=\"x-default\">{}</rdf:li>\n    </rdf:Alt>\n   </dc:description>\n",
+            escape(description)
+        );
+    }
+    if !metadata.tags.is_empty() {
+        // Flat keywords for every tool, plus the hierarchy for those that know it.
+        let leaves = metadata
+            .tags
+            .iter()
+            .map(|t| t.rsplit('/').next().unwrap_or(t));
+        bag(&mut xmp, "dc:subject", leaves);
+        let paths: Vec<String> = metadata.tags.iter().map(|t| t.replace('/', "|")).collect();
+        bag(
+            &mut xmp,
+            "lr:hierarchicalSubject",
+            paths.iter().map(String::as_str),
+        );
+    }
+    if !metadata.people.is_empty() {
+        bag(
+            &mut xmp,
+            "Iptc4xmpExt:PersonInImage",
+            metadata.people.iter().copied(),
+        );
+    }
+    xmp.push_str("  </rdf:Description>\n </rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>\n");
+    xmp
+}
+
+fn bag<'a>(xmp: &mut String, name: &str, items: impl Iterator<Item = &'a str>) {
+    let _ = writeln!(xmp, "   <{}>\n    <rdf:Bag>", name);
+    for item in items {
+        let _ = writeln!(xmp, "     <rdf:li>{}</rdf:li>", escape(item));
+    }
+    let _ = writeln!(xmp, "    </rdf:Bag>\n   </{}>", name);
+}
+
+/// A coordinate in XMP's "degrees,minutes" form, e.g. "48,51.5040N".
+fn coordinate(value: f64, positive: char, negative: char) -> String {
+    let direction = if value < 0.0 { negative } else { positive };
+    let value = value.abs();
+    let degrees = value.trunc();
+    format!(
+        "{},{:.4}{}",
+        degrees as u32,
+        (value - degrees) * 60.0,
+        direction
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
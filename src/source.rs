@@ -0,0 +1,122 @@
+use crate::api::ImmichClient;
+use crate::progress::{self, Progress};
+use crate::upload::DEVICE_ID;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use reqwest::multipart;
+
+/// Dropbox through its HTTP API.
+pub mod dropbox;
+/// Microsoft OneDrive through the Graph API.
+pub mod onedrive;
+
+/// A file held by a remote service, streamed straight into the upload without a local copy.
+#[derive(Debug)]
+pub struct RemoteFile {
+    /// Service-specific ID, used to derive a stable deviceAssetId.
+    pub id: String,
+    /// Filename including extension.
+    pub name: String,
+    /// Size in bytes, needed to stream the multipart body.
+    pub size: u64,
+    /// Capture (or creation) date.
+    pub created_at: DateTime<Utc>,
+    /// Last modification date.
+    pub modified_at: DateTime<Utc>,
+}
+
+/// A service files can be streamed from.
+pub trait RemoteSource {
+    /// Short name of the service, used as prefix for deviceAssetIds.
+    fn name(&self) -> &str;
+
+    /// Starts downloading a file's contents.
+    fn open(&self, file: &RemoteFile) -> impl Future<Output = Result<reqwest::Response>>;
+}
+
+/// Streams remote files to the Immich server concurrently.
+/// Returns the number of files that failed to upload.
+pub async fn upload_remote_files<S: RemoteSource>(
+    client: &ImmichClient,
+    source: &S,
+    files: Vec<RemoteFile>,
+    concurrent: usize,
+) -> Result<usize> {
+    progress::note(format!(
+        "Found {} files to upload. Starting upload with concurrency {}...",
+        files.len(),
+        concurrent
+    ));
+
+    let pb = Progress::new(files.len())?;
+
+    let mut requests = futures::stream::iter(files)
+        .map(|file| {
+            let pb = pb.clone();
+            async move {
+                let result = upload_remote_file(client, source, &file).await;
+                if let Err(e) = &result {
+                    pb.warn(format!("Failed to upload {}: {}", file.name, e));
+                }
+                pb.inc();
+                result.is_ok()
+            }
+        })
+        .buffer_unordered(concurrent);
+
+    // Consume the stream.
+    let mut failed = 0;
+    while let Some(ok) = requests.next().await {
+        if !ok {
+            failed += 1;
+        }
+    }
+
+    pb.finish("Upload complete");
+    Ok(failed)
+}
+
+async fn upload_remote_file<S: RemoteSource>(
+    client: &ImmichClient,
+    source: &S,
+    file: &RemoteFile,
+) -> Result<()> {
+    let download = source
+        .open(file)
+        .await
+        .context("Failed to start download")?;
+    if !download.status().is_success() {
+        anyhow::bail!("Download failed: {}", download.status());
+    }
+
+    let part = stream_part(download, &file.name, Some(file.size))?;
+    let form = multipart::Form::new()
+        .part("assetData", part)
+        .text("deviceAssetId", format!("{}-{}", source.name(), file.id))
+        .text("deviceId", DEVICE_ID)
+        .text("fileCreatedAt", file.created_at.to_rfc3339())
+        .text("fileModifiedAt", file.modified_at.to_rfc3339())
+        .text("isFavorite", "false");
+
+    client.upload_asset(form).await?;
+    Ok(())
+}
+
+/// Wraps a download response as a multipart file part, so the bytes flow straight from
+/// the source into the upload. Without a known size the part is sent chunked.
+pub fn stream_part(
+    download: reqwest::Response,
+    name: &str,
+    size: Option<u64>,
+) -> Result<multipart::Part> {
+    let size = size.or(download.content_length());
+    let body = reqwest::Body::wrap_stream(download.bytes_stream());
+    let part = match size {
+        Some(size) => multipart::Part::stream_with_length(body, size),
+        None => multipart::Part::stream(body),
+    };
+    Ok(part
+        .file_name(name.to_string())
+        .mime_str(mime_guess::from_path(name).first_or_octet_stream().as_ref())?)
+}
@@ -0,0 +1,174 @@
+use super::{RemoteFile, RemoteSource};
+use crate::config::Config;
+use crate::upload::is_image_or_video;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const API: &str = "https://api.dropboxapi.com/2";
+const CONTENT: &str = "https://content.dropboxapi.com/2";
+
+/// A Dropbox account accessed with an access token.
+pub struct Dropbox {
+    http: reqwest::Client,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct ListFolderResult {
+    entries: Vec<Entry>,
+    cursor: String,
+    has_more: bool,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    #[serde(rename = ".tag")]
+    tag: String,
+    #[serde(default)]
+    id: String,
+    name: String,
+    #[serde(default)]
+    size: u64,
+    client_modified: Option<DateTime<Utc>>,
+    server_modified: Option<DateTime<Utc>>,
+}
+
+/// Files listed from a folder together with the cursor to continue from next time.
+pub struct Listing {
+    pub files: Vec<RemoteFile>,
+    pub cursor: String,
+}
+
+impl Dropbox {
+    /// Creates a client authenticated with the given access token.
+    pub fn new(http: reqwest::Client, token: &str) -> Self {
+        Dropbox {
+            http,
+            token: token.to_string(),
+        }
+    }
+
+    /// Lists photos and videos below a folder. With a cursor from an earlier run, only
+    /// files added or changed since then are returned.
+    pub async fn list(&self, folder: &str, cursor: Option<&str>) -> Result<Listing> {
+        let mut page: ListFolderResult = match cursor {
+            Some(cursor) => self
+                .rpc("/files/list_folder/continue", json!({ "cursor": cursor }))
+                .await
+                .context(
+                    "Failed to continue from the saved Dropbox cursor (use --full to rescan)",
+                )?,
+            None => self
+                .rpc(
+                    "/files/list_folder",
+                    json!({ "path": folder, "recursive": true }),
+                )
+                .await
+                .with_context(|| format!("Failed to list Dropbox folder '{}'", folder))?,
+        };
+
+        let mut files = Vec::new();
+        loop {
+            for entry in page.entries {
+                if entry.tag != "file" || !is_image_or_video(Path::new(&entry.name)) {
+                    continue;
+                }
+                let modified_at = entry.server_modified.unwrap_or_else(Utc::now);
+                files.push(RemoteFile {
+                    id: entry.id,
+                    name: entry.name,
+                    size: entry.size,
+                    created_at: entry.client_modified.unwrap_or(modified_at),
+                    modified_at,
+                });
+            }
+            if !page.has_more {
+                break;
+            }
+            page = self
+                .rpc(
+                    "/files/list_folder/continue",
+                    json!({ "cursor": page.cursor }),
+                )
+                .await?;
+        }
+        Ok(Listing {
+            files,
+            cursor: page.cursor,
+        })
+    }
+
+    async fn rpc<T: serde::de::DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        body: serde_json::Value,
+    ) -> Result<T> {
+        let response = self
+            .http
+            .post(format!("{}{}", API, endpoint))
+            .bearer_auth(&self.token)
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Dropbox returned error {}: {}", status, body);
+        }
+        Ok(response.json().await?)
+    }
+}
+
+impl RemoteSource for Dropbox {
+    fn name(&self) -> &str {
+        "dropbox"
+    }
+
+    async fn open(&self, file: &RemoteFile) -> Result<reqwest::Response> {
+        Ok(self
+            .http
+            .post(format!("{}/files/download", CONTENT))
+            .bearer_auth(&self.token)
+            .header("Dropbox-API-Arg", json!({ "path": file.id }).to_string())
+            .send()
+            .await?)
+    }
+}
+
+/// Cursors from earlier runs, keyed by folder, stored in the state directory.
+pub struct CursorStore {
+    path: PathBuf,
+    cursors: HashMap<String, String>,
+}
+
+impl CursorStore {
+    /// Loads saved cursors, or starts empty on the first run.
+    pub fn load() -> Result<Self> {
+        let path = Config::state_dir()?.join("dropbox_cursors.json");
+        let cursors = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            HashMap::new()
+        };
+        Ok(CursorStore { path, cursors })
+    }
+
+    /// Returns the cursor saved for a folder.
+    pub fn get(&self, folder: &str) -> Option<&str> {
+        self.cursors.get(&folder.to_lowercase()).map(String::as_str)
+    }
+
+    /// Records the cursor for a folder and writes all cursors to disk.
+    pub fn save(&mut self, folder: &str, cursor: String) -> Result<()> {
+        self.cursors.insert(folder.to_lowercase(), cursor);
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.cursors)?)?;
+        Ok(())
+    }
+}
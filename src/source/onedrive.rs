@@ -0,0 +1,282 @@
+use super::{RemoteFile, RemoteSource};
+use crate::progress;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use url::Url;
+
+const AUTHORITY: &str = "https://login.microsoftonline.com";
+const GRAPH: &str = "https://graph.microsoft.com/v1.0";
+const SCOPE: &str = "Files.Read offline_access";
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Access tokens are refreshed this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(300);
+
+/// A signed-in OneDrive account.
+pub struct OneDrive {
+    http: reqwest::Client,
+    client_id: String,
+    tenant: String,
+    token: Mutex<Token>,
+}
+
+struct Token {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    message: String,
+    interval: u64,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DriveItemPage {
+    value: Vec<DriveItem>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DriveItem {
+    id: String,
+    name: String,
+    #[serde(default)]
+    size: u64,
+    file: Option<FileFacet>,
+    folder: Option<serde_json::Value>,
+    file_system_info: Option<FileSystemInfo>,
+    photo: Option<PhotoFacet>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileFacet {
+    mime_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileSystemInfo {
+    created_date_time: Option<DateTime<Utc>>,
+    last_modified_date_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PhotoFacet {
+    taken_date_time: Option<DateTime<Utc>>,
+}
+
+impl OneDrive {
+    /// Signs in with the OAuth device code flow: prints a code for the user to enter at
+    /// microsoft.com/devicelogin and waits until sign-in completes.
+    pub async fn sign_in(http: reqwest::Client, client_id: &str, tenant: &str) -> Result<Self> {
+        let code: DeviceCode = http
+            .post(format!("{}/{}/oauth2/v2.0/devicecode", AUTHORITY, tenant))
+            .form(&[("client_id", client_id), ("scope", SCOPE)])
+            .send()
+            .await?
+            .error_for_status()
+            .context("Failed to start OneDrive sign-in")?
+            .json()
+            .await?;
+        progress::note(&code.message);
+
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval.max(1));
+        loop {
+            if Instant::now() > deadline {
+                anyhow::bail!("OneDrive sign-in timed out");
+            }
+            tokio::time::sleep(interval).await;
+
+            let response: TokenResponse = http
+                .post(format!("{}/{}/oauth2/v2.0/token", AUTHORITY, tenant))
+                .form(&[
+                    ("grant_type", DEVICE_CODE_GRANT),
+                    ("client_id", client_id),
+                    ("device_code", &code.device_code),
+                ])
+                .send()
+                .await?
+                .json()
+                .await?;
+            match response.error.as_deref() {
+                Some("authorization_pending") => continue,
+                Some("slow_down") => interval += Duration::from_secs(5),
+                _ => {
+                    let token = Token::from_response(response, None)?;
+                    return Ok(OneDrive {
+                        http,
+                        client_id: client_id.to_string(),
+                        tenant: tenant.to_string(),
+                        token: Mutex::new(token),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns a valid access token, refreshing it when it is about to expire.
+    async fn access_token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if Instant::now() + REFRESH_MARGIN >= token.expires_at
+            && let Some(refresh_token) = token.refresh_token.clone()
+        {
+            let response: TokenResponse = self
+                .http
+                .post(format!("{}/{}/oauth2/v2.0/token", AUTHORITY, self.tenant))
+                .form(&[
+                    ("grant_type", "refresh_token"),
+                    ("client_id", &self.client_id),
+                    ("refresh_token", &refresh_token),
+                    ("scope", SCOPE),
+                ])
+                .send()
+                .await?
+                .json()
+                .await?;
+            *token = Token::from_response(response, Some(refresh_token))
+                .context("Failed to refresh OneDrive access token")?;
+        }
+        Ok(token.access_token.clone())
+    }
+
+    /// Lists photos and videos below a folder (recursively), or in the camera roll when no
+    /// folder is given.
+    pub async fn list(&self, folder: Option<&str>) -> Result<Vec<RemoteFile>> {
+        let mut start = Url::parse(GRAPH)?;
+        {
+            let mut segments = start
+                .path_segments_mut()
+                .map_err(|_| anyhow::anyhow!("Invalid Graph URL"))?;
+            segments.extend(["me", "drive"]);
+            match folder
+                .map(|f| f.trim_matches('/'))
+                .filter(|f| !f.is_empty())
+            {
+                // Path-based addressing: /me/drive/root:/Pictures/Camera Roll:/children
+                Some(folder) => {
+                    segments.push("root:");
+                    let parts: Vec<&str> = folder.split('/').collect();
+                    for (i, part) in parts.iter().enumerate() {
+                        if i + 1 == parts.len() {
+                            segments.push(&format!("{}:", part));
+                        } else {
+                            segments.push(part);
+                        }
+                    }
+                }
+                None => {
+                    segments.extend(["special", "cameraroll"]);
+                }
+            }
+            segments.push("children");
+        }
+
+        let mut files = Vec::new();
+        let mut pending = vec![start.to_string()];
+        while let Some(url) = pending.pop() {
+            let page: DriveItemPage = self
+                .http
+                .get(&url)
+                .bearer_auth(self.access_token().await?)
+                .send()
+                .await?
+                .error_for_status()
+                .context("Failed to list OneDrive folder")?
+                .json()
+                .await?;
+            if let Some(next) = page.next_link {
+                pending.push(next);
+            }
+
+            for item in page.value {
+                if item.folder.is_some() {
+                    pending.push(format!("{}/me/drive/items/{}/children", GRAPH, item.id));
+                    continue;
+                }
+                let is_media = item
+                    .file
+                    .as_ref()
+                    .and_then(|f| f.mime_type.as_deref())
+                    .is_some_and(|m| m.starts_with("image/") || m.starts_with("video/"));
+                if !is_media {
+                    continue;
+                }
+
+                let info = item.file_system_info.as_ref();
+                let modified_at = info
+                    .and_then(|i| i.last_modified_date_time)
+                    .unwrap_or_else(Utc::now);
+                let created_at = item
+                    .photo
+                    .and_then(|p| p.taken_date_time)
+                    .or_else(|| info.and_then(|i| i.created_date_time))
+                    .unwrap_or(modified_at);
+                files.push(RemoteFile {
+                    id: item.id,
+                    name: item.name,
+                    size: item.size,
+                    created_at,
+                    modified_at,
+                });
+            }
+        }
+        Ok(files)
+    }
+}
+
+impl RemoteSource for OneDrive {
+    fn name(&self) -> &str {
+        "onedrive"
+    }
+
+    async fn open(&self, file: &RemoteFile) -> Result<reqwest::Response> {
+        // Redirects to a pre-authenticated download URL; reqwest drops the
+        // Authorization header when following it to another host.
+        Ok(self
+            .http
+            .get(format!("{}/me/drive/items/{}/content", GRAPH, file.id))
+            .bearer_auth(self.access_token().await?)
+            .send()
+            .await?)
+    }
+}
+
+impl Token {
+    fn from_response(response: TokenResponse, previous_refresh: Option<String>) -> Result<Self> {
+        let Some(access_token) = response.access_token else {
+            anyhow::bail!(
+                "OneDrive sign-in failed: {}",
+                response
+                    .error_description
+                    .or(response.error)
+                    .unwrap_or_else(|| "no access token returned".to_string())
+            );
+        };
+        Ok(Token {
+            access_token,
+            refresh_token: response.refresh_token.or(previous_refresh),
+            expires_at: Instant::now() + Duration::from_secs(response.expires_in.unwrap_or(3600)),
+        })
+    }
+}
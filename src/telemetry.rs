@@ -0,0 +1,195 @@
+use anyhow::{Context, Result};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Value, json};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::{Context as LayerContext, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry};
+
+/// Collects finished spans of this run and sends them to an OTLP/HTTP collector.
+pub struct OtlpExporter {
+    endpoint: String,
+    spans: Arc<Mutex<Vec<SpanData>>>,
+}
+
+/// A span being recorded, kept in the span's extensions until it closes.
+struct SpanData {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: &'static str,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(String, String)>,
+}
+
+/// Records spans for `OtlpExporter`.
+struct OtlpLayer {
+    trace_id: String,
+    spans: Arc<Mutex<Vec<SpanData>>>,
+}
+
+/// Installs tracing for the run: `timings` prints each span's duration on stderr, and
+/// `otlp_endpoint` collects spans for export. Returns the exporter to flush at exit.
+pub fn init(timings: bool, otlp_endpoint: Option<String>) -> Option<OtlpExporter> {
+    if !timings && otlp_endpoint.is_none() {
+        return None;
+    }
+    let spans = Arc::new(Mutex::new(Vec::new()));
+    let otlp = otlp_endpoint.as_ref().map(|_| OtlpLayer {
+        trace_id: random_id::<16>(),
+        spans: Arc::clone(&spans),
+    });
+    let fmt = timings.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_span_events(FmtSpan::CLOSE)
+    });
+    // Only this crate's spans; the HTTP stack has its own, much noisier ones.
+    let filter = Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::TRACE);
+    let _ = Registry::default()
+        .with(otlp.with_filter(filter.clone()))
+        .with(fmt.with_filter(filter))
+        .try_init();
+
+    otlp_endpoint.map(|endpoint| OtlpExporter { endpoint, spans })
+}
+
+impl OtlpExporter {
+    /// Sends the collected spans to `<endpoint>/v1/traces` as OTLP JSON.
+    pub async fn export(self) -> Result<()> {
+        let spans: Vec<Value> = std::mem::take(&mut *self.spans.lock().unwrap())
+            .iter()
+            .map(SpanData::to_otlp)
+            .collect();
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let body = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [attribute("service.name", env!("CARGO_PKG_NAME"))],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        });
+        let url = format!("{}/v1/traces", self.endpoint.trim_end_matches('/'));
+        reqwest::Client::new()
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Failed to export traces to {}", url))?;
+        Ok(())
+    }
+}
+
+impl SpanData {
+    fn to_otlp(&self) -> Value {
+        json!({
+            "traceId": self.trace_id,
+            "spanId": self.span_id,
+            "parentSpanId": self.parent_span_id.as_deref().unwrap_or_default(),
+            "name": self.name,
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(self.start),
+            "endTimeUnixNano": unix_nanos(self.end),
+            "attributes": self
+                .attributes
+                .iter()
+                .map(|(key, value)| attribute(key, value))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent_span_id = span.parent().and_then(|parent| {
+            parent
+                .extensions()
+                .get::<SpanData>()
+                .map(|p| p.span_id.clone())
+        });
+        let mut data = SpanData {
+            trace_id: self.trace_id.clone(),
+            span_id: random_id::<8>(),
+            parent_span_id,
+            name: attrs.metadata().name(),
+            start: SystemTime::now(),
+            end: SystemTime::now(),
+            attributes: Vec::new(),
+        };
+        attrs.record(&mut FieldVisitor(&mut data.attributes));
+        span.extensions_mut().insert(data);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: LayerContext<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(data) = span.extensions_mut().get_mut::<SpanData>()
+        {
+            values.record(&mut FieldVisitor(&mut data.attributes));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: LayerContext<'_, S>) {
+        if let Some(span) = ctx.span(&id)
+            && let Some(mut data) = span.extensions_mut().remove::<SpanData>()
+        {
+            data.end = SystemTime::now();
+            self.spans.lock().unwrap().push(data);
+        }
+    }
+}
+
+/// Collects span fields as string attributes.
+struct FieldVisitor<'a>(&'a mut Vec<(String, String)>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// Nanoseconds since the Unix epoch, as the string OTLP JSON expects for 64-bit integers.
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// Random hex ID of `N` bytes; OTLP uses 16 for traces and 8 for spans.
+fn random_id<const N: usize>() -> String {
+    let mut bytes = [0u8; N];
+    // An all-zero ID only makes the collector drop the span.
+    let _ = SystemRandom::new().fill(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,1635 @@
+use crate::api::{ImmichClient, UploadResponse};
+use crate::assets::glob_match;
+use crate::checksums::{self, ChecksumCache};
+use crate::config::Hooks;
+use crate::controls::{self, Controls, KeyListener};
+use crate::existing::{self, DuplicatePolicy};
+use crate::exit::{self, ExitKind};
+use crate::failures::{self, ErrorClass, FailureStore};
+use crate::filename_date;
+use crate::hooks;
+use crate::jobs;
+use crate::journal::Journal;
+use crate::metadata::{self, EmbeddedMetadata};
+use crate::mime;
+use crate::order::{self, UploadOrder, UploadPriority};
+use crate::plan;
+use crate::progress::{self, Event, Progress, Transfer};
+use crate::screenshot::{self, ScreenshotAction};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use reqwest::multipart;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{Instrument, info_span};
+use walkdir::WalkDir;
+
+/// Device ID reported to Immich for every upload.
+pub const DEVICE_ID: &str = "rimmich-uploader";
+
+/// File names of downloads, syncs and edits in progress, lock files and OS metadata, which
+/// are never uploaded even when their extension looks like media.
+const PARTIAL_FILES: &[&str] = &[
+    "*.part",
+    "*.partial",
+    "*.crdownload",
+    "*.download",
+    "*.tmp",
+    "*.temp",
+    "*.!qB",
+    ".syncthing.*",
+    ".~*",
+    "~$*",
+    "._*",
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+];
+
+/// Per-run options controlling the metadata sent with each upload.
+#[derive(Default)]
+pub struct UploadOptions {
+    /// Minimum star rating for an asset to be marked as favorite.
+    pub favorite_rating: Option<i32>,
+    /// Whether to apply embedded keywords as tags.
+    pub import_tags: bool,
+    /// Whether to apply macOS Finder tags as tags, and the "Favorite" tag as favorite.
+    pub finder_tags: bool,
+    /// Whether to apply embedded titles as descriptions.
+    pub import_titles: bool,
+    /// Mirror the folder tree as albums, joining nested folder names with this separator.
+    pub album_mirror: Option<String>,
+    /// Album every uploaded file is added to.
+    pub album: Option<String>,
+    /// Users (email or name) that albums created during the run are shared with.
+    pub share_with: Vec<String>,
+    /// Permission given to those users.
+    pub share_role: ShareRole,
+    /// Where uploaded assets show up; the server default (timeline) when unset.
+    pub visibility: Option<Visibility>,
+    /// Compare the checksum and size stored by the server against the local file.
+    pub verify: bool,
+    /// Skip files the server already has, matched by checksum.
+    pub skip_existing: bool,
+    /// What to do with those the server has under another name or date.
+    pub on_duplicate: DuplicatePolicy,
+    /// Upload the video embedded in Motion Photos separately, linked as a Live Photo.
+    pub split_motion_photos: bool,
+    /// What to do with screenshots.
+    pub screenshots: Option<ScreenshotAction>,
+    /// Album screenshots go to instead of the run's albums.
+    pub screenshot_album: Option<String>,
+    /// Number of folder levels below the scanned directory to descend into at most.
+    pub max_depth: Option<usize>,
+    /// Don't descend into other filesystems mounted below the scanned directory.
+    pub one_file_system: bool,
+    /// Leave files modified more recently than this for a later run.
+    pub min_age: Option<Duration>,
+    /// Globs of file names to skip as temporary or partial files, besides `PARTIAL_FILES`.
+    pub partial_files: Vec<String>,
+    /// Order to upload files in; the order they were found in when unset.
+    pub order: Option<UploadOrder>,
+    /// Files to upload first, regardless of the order.
+    pub priority: Option<UploadPriority>,
+    /// Leave files another process has open for a later run (Linux only).
+    pub skip_open: bool,
+    /// Files were left out of the run, so mirrored albums must not be pruned.
+    pub incomplete: bool,
+    /// Show the plan and ask for confirmation before uploading.
+    pub interactive: bool,
+    /// Start even if the files don't fit in the user's quota or on the server's disk.
+    pub skip_quota_check: bool,
+    /// When the server becomes unreachable, wait for it to return instead of stopping.
+    pub wait_for_server: bool,
+    /// Get the server processing the new assets right away and wait for it to finish.
+    pub process: bool,
+    /// Wait at most this long for the server to finish thumbnails and metadata of the
+    /// new assets.
+    pub wait_for_processing: Option<Duration>,
+    /// Commands run before and after each file and after the run.
+    pub hooks: Hooks,
+    /// Continue the interrupted previous run instead of rescanning.
+    pub resume: bool,
+    /// Directory to write a `failed-<timestamp>.txt` list of failed files to.
+    pub failed_list: Option<PathBuf>,
+    /// Device ID reported to the server, `DEVICE_ID` when unset.
+    pub device_id: Option<String>,
+}
+
+/// Permission of users an album is shared with.
+#[derive(Clone, Copy, Default, Debug, clap::ValueEnum)]
+pub enum ShareRole {
+    /// Can view the album.
+    #[default]
+    Viewer,
+    /// Can add and remove assets.
+    Editor,
+}
+
+impl ShareRole {
+    fn as_str(self) -> &'static str {
+        match self {
+            ShareRole::Viewer => "viewer",
+            ShareRole::Editor => "editor",
+        }
+    }
+}
+
+/// Where an uploaded asset shows up in Immich.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// The main timeline.
+    Timeline,
+    /// The archive.
+    Archive,
+    /// Hidden from the timeline and search, only reachable through albums.
+    Hidden,
+    /// The PIN-protected locked folder.
+    Locked,
+}
+
+impl Visibility {
+    fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Timeline => "timeline",
+            Visibility::Archive => "archive",
+            Visibility::Hidden => "hidden",
+            Visibility::Locked => "locked",
+        }
+    }
+}
+
+/// A file queued for upload, with metadata supplied by the source it was found in.
+/// Fields left empty fall back to what can be read from the file itself.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct UploadItem {
+    /// Local path of the file to upload.
+    pub path: PathBuf,
+    /// Filename to report to the server instead of the on-disk name.
+    pub filename: Option<String>,
+    /// Capture date overriding the filesystem timestamps.
+    pub taken_at: Option<DateTime<Utc>>,
+    /// Favorite flag overriding the rating-based detection.
+    pub favorite: Option<bool>,
+    /// Star rating from the source catalog, used instead of the embedded rating.
+    pub rating: Option<i32>,
+    /// Tags from the source catalog, applied in addition to embedded keywords.
+    pub tags: Vec<String>,
+    /// Names of albums the asset should be added to.
+    pub albums: Vec<String>,
+    /// Description (caption) to set on the asset.
+    pub description: Option<String>,
+    /// Motion part of a Live Photo, uploaded first and linked to this still.
+    pub live_photo_video: Option<PathBuf>,
+    /// Visibility overriding the run's.
+    pub visibility: Option<Visibility>,
+    /// Type of the file, when the scan already looked into it.
+    #[serde(skip)]
+    pub detected: Option<mime::FileType>,
+}
+
+impl UploadItem {
+    /// Creates an item with no metadata overrides.
+    pub fn new(path: PathBuf) -> Self {
+        UploadItem {
+            path,
+            ..Default::default()
+        }
+    }
+
+    /// The file's type as found by the scan, or detected now for items from elsewhere.
+    pub fn file_type(&self) -> mime::FileType {
+        self.detected.clone().unwrap_or_else(|| {
+            let name = match &self.filename {
+                Some(filename) => filename.clone(),
+                None => self
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into(),
+            };
+            mime::detect(&self.path, &name)
+        })
+    }
+}
+
+/// Scans a directory for media files and uploads them concurrently.
+pub async fn upload_directory(
+    client: ImmichClient,
+    directory: &Path,
+    recursive: bool,
+    concurrent: usize,
+    mut options: UploadOptions,
+) -> Result<()> {
+    if !directory.is_dir() {
+        anyhow::bail!("Path {:?} is not a directory", directory);
+    }
+
+    let key = format!("upload:{}", directory.canonicalize()?.display());
+    let pending = Journal::pending(&key)?;
+    if options.resume {
+        match pending {
+            Some(mut pending) => {
+                progress::note(format!(
+                    "Resuming interrupted run: {} of {} files left.",
+                    pending.items.len(),
+                    pending.planned
+                ));
+                let journal = Journal::reopen(&key, &mut pending)?;
+                if pending.items.is_empty() {
+                    return journal.finish();
+                }
+                return upload_items(client, pending.items, concurrent, options, Some(journal))
+                    .await;
+            }
+            None => progress::note("No interrupted run to resume, starting from scratch."),
+        }
+    } else if let Some(pending) = pending.filter(|p| !p.items.is_empty()) {
+        progress::note(format!(
+            "A previous run of this directory was interrupted with {} of {} files left; pass --resume to continue it.",
+            pending.items.len(),
+            pending.planned
+        ));
+    }
+
+    let files = scan_directory(directory, recursive, &mut options);
+    if files.is_empty() {
+        progress::note(format!("No supported files found in {:?}", directory));
+        return Ok(());
+    }
+
+    // A read-only state directory only costs the ability to resume.
+    let journal = match Journal::create(&key, &files) {
+        Ok(journal) => Some(journal),
+        Err(e) => {
+            progress::note(format!("Not journaling this run: {:#}", e));
+            None
+        }
+    };
+    upload_items(client, files, concurrent, options, journal).await
+}
+
+/// Lists the media files below `directory` to upload, with the albums the options put
+/// them in. Files left for a later run mark the options as incomplete.
+pub fn scan_directory(
+    directory: &Path,
+    recursive: bool,
+    options: &mut UploadOptions,
+) -> Vec<UploadItem> {
+    progress::note(format!("Scanning directory: {:?}", directory));
+    progress::emit(Event::ScanStarted { path: directory });
+    let scan = info_span!("scan", path = %directory.display()).entered();
+    let mut files = Vec::new();
+    let walker = walk(
+        directory,
+        recursive,
+        options.max_depth,
+        options.one_file_system,
+    );
+
+    let open_files = if options.skip_open {
+        open_files()
+    } else {
+        HashSet::new()
+    };
+    let mut held_back = 0;
+    let mut partial = 0;
+    let mut not_media = 0;
+    // Filter files by mime type (images and videos).
+    for entry in walker.into_iter().filter_map(|e| e.ok()) {
+        if entry.file_type().is_file() {
+            let path = entry.path();
+            if is_partial_file(path, &options.partial_files) {
+                partial += 1;
+                continue;
+            }
+            // Files named like something else are passed over; those without a telling
+            // name are looked into.
+            let named_media = has_media_extension(path);
+            if named_media || !mime::has_known_extension(path) {
+                let Some(file_type) = media_type(path) else {
+                    if named_media {
+                        not_media += 1;
+                    }
+                    continue;
+                };
+                let too_young = options
+                    .min_age
+                    .is_some_and(|min_age| is_younger_than(&entry, min_age));
+                let is_open = !open_files.is_empty()
+                    && path.canonicalize().is_ok_and(|p| open_files.contains(&p));
+                if too_young || is_open {
+                    held_back += 1;
+                    continue;
+                }
+                let mut item = UploadItem::new(path.to_path_buf());
+                item.detected = Some(file_type);
+                item.albums.extend(options.album.clone());
+                if let Some(separator) = &options.album_mirror {
+                    item.albums
+                        .push(mirror_album_name(directory, path, separator));
+                }
+                files.push(item);
+            }
+        }
+    }
+    drop(scan);
+    if partial > 0 {
+        progress::note(format!("Skipping {} temporary or partial files.", partial));
+    }
+    if not_media > 0 {
+        progress::note(format!(
+            "Skipping {} files named like media whose contents are not images or videos.",
+            not_media
+        ));
+    }
+    if held_back > 0 {
+        progress::note(format!(
+            "Leaving {} files that may still be being written for the next run.",
+            held_back
+        ));
+        options.incomplete = true;
+    }
+    files
+}
+
+/// Uploads the files named in a list, one path per line (`-` reads the list from stdin).
+pub async fn upload_file_list(
+    client: ImmichClient,
+    list: &Path,
+    concurrent: usize,
+    options: UploadOptions,
+) -> Result<()> {
+    let items: Vec<UploadItem> = read_file_list(list)?
+        .into_iter()
+        .map(|path| UploadItem {
+            albums: options.album.iter().cloned().collect(),
+            ..UploadItem::new(path)
+        })
+        .collect();
+    if items.is_empty() {
+        progress::note(format!("No files listed in {:?}", list));
+        return Ok(());
+    }
+    upload_items(client, items, concurrent, options, None).await
+}
+
+/// Reads a list of paths, one per line, skipping blank lines.
+fn read_file_list(list: &Path) -> Result<Vec<PathBuf>> {
+    let content = if list == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(list).with_context(|| format!("Failed to read {:?}", list))?
+    };
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Writes the paths of failed files to `failed-<timestamp>.txt`, in the format read by
+/// `--files-from`, and returns the file's path.
+fn write_failed_list(directory: &Path, paths: &[PathBuf]) -> Result<PathBuf> {
+    let list = directory.join(format!(
+        "failed-{}.txt",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    let mut content = String::new();
+    for path in paths {
+        content.push_str(&path.to_string_lossy());
+        content.push('\n');
+    }
+    std::fs::write(&list, content).with_context(|| format!("Failed to write {:?}", list))?;
+    Ok(list)
+}
+
+/// Uploads again the files that failed in earlier runs, optionally only those below
+/// `directory`. Albums and metadata are those the files were originally queued with.
+pub async fn retry_failed(
+    client: ImmichClient,
+    directory: Option<&Path>,
+    concurrent: usize,
+    mut options: UploadOptions,
+) -> Result<()> {
+    let store = FailureStore::load()?;
+    let items: Vec<UploadItem> = store
+        .failures(directory)
+        .into_iter()
+        .map(|f| f.item.clone())
+        .collect();
+    if items.is_empty() {
+        progress::note("No failed uploads to retry.");
+        return Ok(());
+    }
+    progress::note(format!("Retrying {} failed uploads.", items.len()));
+
+    // Items already carry their albums, and pruning mirrored albums needs a full scan.
+    options.album = None;
+    options.album_mirror = None;
+    upload_items(client, items, concurrent, options, None).await
+}
+
+/// Names the album mirroring a file's folder: the folder path below the upload root with
+/// components joined by `separator`, or the root's own name for files directly inside it.
+fn mirror_album_name(root: &Path, path: &Path, separator: &str) -> String {
+    let relative = path
+        .parent()
+        .and_then(|p| p.strip_prefix(root).ok())
+        .map(|p| {
+            p.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(separator)
+        })
+        .unwrap_or_default();
+    if !relative.is_empty() {
+        return relative;
+    }
+    root.canonicalize()
+        .ok()
+        .and_then(|r| r.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_else(|| root.to_string_lossy().to_string())
+}
+
+/// Share of the free space above which a run is allowed but warned about.
+const SPACE_WARNING_SHARE: f64 = 0.9;
+
+/// Refuses to start uploading `total_bytes` when that is more than the user's remaining
+/// quota or the server's free disk space, so the run doesn't fail most of the way through.
+/// Space that can't be determined is not checked.
+async fn check_space(client: &ImmichClient, total_bytes: u64) -> Result<()> {
+    let mut limits = Vec::new();
+    match client.current_user().await {
+        Ok(user) => {
+            if let Some(quota) = user.quota_size_in_bytes {
+                let used = user.quota_usage_in_bytes.unwrap_or(0);
+                limits.push(("of quota left", quota.saturating_sub(used)));
+            }
+        }
+        Err(e) => progress::note(format!("Could not check the storage quota: {:#}", e)),
+    }
+    match client.server_storage().await {
+        Ok(storage) => limits.push(("free on the server", storage.disk_available_raw)),
+        Err(e) => progress::note(format!("Could not check the server's free space: {:#}", e)),
+    }
+
+    for (what, available) in limits {
+        if total_bytes > available {
+            bail!(
+                "The files to upload ({}) don't fit: only {} {}. Free up space or upload \
+                 fewer files; --no-quota-check starts anyway",
+                indicatif::HumanBytes(total_bytes),
+                indicatif::HumanBytes(available),
+                what
+            );
+        }
+        if total_bytes as f64 > available as f64 * SPACE_WARNING_SHARE {
+            progress::note(format!(
+                "Warning: the files to upload ({}) will use up almost all of the {} {}.",
+                indicatif::HumanBytes(total_bytes),
+                indicatif::HumanBytes(available),
+                what
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Uploads a list of prepared items concurrently, then adds them to their albums.
+/// Completed files are recorded in the journal, which is removed when nothing failed.
+pub async fn upload_items(
+    client: ImmichClient,
+    mut items: Vec<UploadItem>,
+    concurrent: usize,
+    mut options: UploadOptions,
+    journal: Option<Journal>,
+) -> Result<()> {
+    if client.is_shared_link() {
+        strip_for_shared_link(&mut items, &mut options);
+    }
+
+    if options.screenshots.is_some() || options.screenshot_album.is_some() {
+        let before = items.len();
+        let found = screenshot::route(
+            &mut items,
+            options.screenshots,
+            options.screenshot_album.as_deref(),
+        );
+        if before > items.len() {
+            progress::note(format!("Skipping {} screenshots.", before - items.len()));
+        } else if found > 0 {
+            progress::note(format!("Found {} screenshots.", found));
+        }
+    }
+
+    let filtered = hooks::filter(&options.hooks, &mut items).await?;
+    if filtered > 0 {
+        progress::note(format!("The filter hook left out {} files.", filtered));
+        // Files left out would look like files that left their folder.
+        options.incomplete = true;
+    }
+
+    let mut checksums = ChecksumCache::load();
+    let existing = if options.skip_existing {
+        // Updating existing assets needs to know them before uploading; otherwise files not
+        // hashed before are hashed as they upload, reading them once.
+        let hash_all = options.on_duplicate != DuplicatePolicy::Skip;
+        match skip_existing(&client, &mut items, &mut checksums, hash_all).await {
+            Ok(existing) => existing,
+            Err(e) => {
+                progress::note(format!("Could not check for existing files: {:#}", e));
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    if !existing.is_empty() {
+        progress::note(format!(
+            "Skipping {} files already on the server.",
+            existing.len()
+        ));
+    }
+    let differences = existing::differences(&client, &existing, &options).await?;
+
+    if let Some(upload_order) = options.order {
+        order::sort(&mut items, upload_order);
+    }
+    if let Some(priority) = options.priority {
+        order::prioritize(&mut items, priority);
+    }
+
+    let sizes: Vec<u64> = items.iter().map(item_size).collect();
+    let total_bytes = sizes.iter().sum();
+    if !options.skip_quota_check && !client.is_shared_link() && total_bytes > 0 {
+        check_space(&client, total_bytes).await?;
+    }
+
+    // Existing assets are only changed once the plan is confirmed.
+    if options.interactive
+        && (!items.is_empty() || !differences.is_empty())
+        && !plan::confirm(&client, &items, &differences, &options).await?
+    {
+        progress::note("Upload cancelled.");
+        // The journal of a fresh run would otherwise look like an interrupted one.
+        if !options.resume
+            && let Some(journal) = journal
+        {
+            journal.finish()?;
+        }
+        return Ok(());
+    }
+    existing::resolve(&client, &differences, options.on_duplicate).await?;
+
+    progress::note(format!(
+        "Found {} files ({}) to upload. Starting upload with concurrency {}...",
+        items.len(),
+        indicatif::HumanBytes(total_bytes),
+        concurrent
+    ));
+
+    // Resolve recipients up front so a typo doesn't surface only after a long upload.
+    let share_user_ids = resolve_users(&client, &options.share_with).await?;
+
+    let run_span = info_span!("upload_run", files = items.len(), concurrent);
+    let pb = Progress::bytes(items.len(), total_bytes)?;
+    for item in &items {
+        progress::emit(Event::FileQueued { path: &item.path });
+    }
+
+    let client = Arc::new(client);
+    let options = Arc::new(options);
+    let journal = journal.map(Arc::new);
+    let failures = Arc::new(Mutex::new(FailureStore::load()?));
+    let device_id = options
+        .device_id
+        .as_deref()
+        .unwrap_or(DEVICE_ID)
+        .to_string();
+    // Album name -> IDs of assets to add once all uploads are done.
+    let album_assets: Arc<Mutex<HashMap<String, Vec<String>>>> = Arc::new(Mutex::new(
+        journal
+            .as_ref()
+            .map(|j| j.album_assets().clone())
+            .unwrap_or_default(),
+    ));
+
+    if !existing.is_empty() {
+        let mut album_assets = album_assets.lock().unwrap();
+        let mut failures = failures.lock().unwrap();
+        for (item, asset_id) in &existing {
+            if let Some(journal) = &journal {
+                journal.record_done(&item.path, asset_id.as_deref())?;
+            }
+            failures.clear(&item.path);
+            // Existing assets still join the albums the run puts files in.
+            if let Some(asset_id) = asset_id {
+                for album in &item.albums {
+                    album_assets
+                        .entry(album.clone())
+                        .or_default()
+                        .push(asset_id.clone());
+                }
+            }
+        }
+    }
+    let checksums = Arc::new(Mutex::new(checksums));
+    // IDs of the assets the run created, as opposed to matched.
+    let created: Arc<Mutex<Vec<String>>> = Arc::default();
+    let duplicates = Arc::new(AtomicUsize::new(0));
+
+    // On Ctrl-C/SIGTERM, stop starting new uploads and let in-flight ones finish.
+    let controls = Controls::new(concurrent);
+    let watcher = tokio::spawn(stop_on_interrupt(Arc::clone(&controls), pb.clone()));
+    let keys = KeyListener::start(&controls, &pb);
+    let outage = Arc::new(Outage::default());
+    let total = items.len();
+    let mut started = 0;
+
+    // Use a stream to process uploads concurrently; each waits for a slot, so pausing and
+    // concurrency changes apply to the files that haven't started yet.
+    let mut requests = Box::pin(
+        futures::stream::iter(items.into_iter().zip(sizes))
+            .then(|(item, size)| {
+                let controls = Arc::clone(&controls);
+                async move { (controls.acquire().await, item, size) }
+            })
+            .take_while(|(slot, _, _)| std::future::ready(slot.is_some()))
+            .inspect(|_| started += 1)
+            .map(|(slot, item, size)| {
+                let client = Arc::clone(&client);
+                let options = Arc::clone(&options);
+                let album_assets = Arc::clone(&album_assets);
+                let created = Arc::clone(&created);
+                let duplicates = Arc::clone(&duplicates);
+                let journal = journal.clone();
+                let device_id = device_id.clone();
+                let failures = Arc::clone(&failures);
+                let checksums = Arc::clone(&checksums);
+                let controls = Arc::clone(&controls);
+                let outage = Arc::clone(&outage);
+                let pb = pb.clone();
+                let span =
+                    info_span!(parent: &run_span, "upload_file", path = %item.path.display());
+                let name = item.path.file_name().unwrap_or_default().to_string_lossy();
+                let transfer = pb.transfer(size, &name);
+                async move {
+                    let result = match hooks::pre_file(&options.hooks, &item.path).await {
+                        Err(e) => Err(e),
+                        Ok(()) => loop {
+                            let attempt = Instant::now();
+                            let result = upload_file(
+                                &client, &item, &device_id, &options, &checksums, &transfer,
+                            )
+                            .await;
+                            match &result {
+                                Err(e)
+                                    if ErrorClass::of(e) == ErrorClass::Network
+                                        && outage
+                                            .handle(
+                                                &client,
+                                                &controls,
+                                                &pb,
+                                                attempt,
+                                                options.wait_for_server,
+                                            )
+                                            .await => {}
+                                _ => break result,
+                            }
+                        },
+                    };
+                    drop(transfer);
+                    drop(slot);
+                    match &result {
+                        Ok(Some(asset)) if asset.is_created() => {
+                            created.lock().unwrap().push(asset.id.clone());
+                        }
+                        Ok(_) => {
+                            duplicates.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(_) => {}
+                    }
+                    let result = result.map(|asset| asset.map(|asset| asset.id));
+                    if let Err(e) = hooks::post_file(&options.hooks, &item.path, &result).await {
+                        pb.warn(format!("{:#}", e));
+                    }
+                    let ok = result.is_ok();
+                    progress::emit(Event::FileDone {
+                        path: &item.path,
+                        ok,
+                        asset_id: result.as_ref().ok().and_then(|id| id.as_deref()),
+                        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+                    });
+                    match result {
+                        Ok(asset_id) => {
+                            if let Some(journal) = &journal
+                                && let Err(e) = journal.record_done(&item.path, asset_id.as_deref())
+                            {
+                                pb.warn(format!("Failed to update journal: {}", e));
+                            }
+                            failures.lock().unwrap().clear(&item.path);
+                            if let Some(asset_id) = asset_id {
+                                let mut album_assets = album_assets.lock().unwrap();
+                                for album in &item.albums {
+                                    album_assets
+                                        .entry(album.clone())
+                                        .or_default()
+                                        .push(asset_id.clone());
+                                }
+                            }
+                            pb.inc();
+                            None
+                        }
+                        Err(e) => {
+                            failures.lock().unwrap().record(&item, &e);
+                            pb.warn(format!("Failed to upload {:?}: {}", item.path, e));
+                            pb.inc(); // Still increment but mark failure in output
+                            Some((item.path, ErrorClass::of(&e), format!("{:#}", e)))
+                        }
+                    }
+                }
+                .instrument(span)
+            })
+            .buffer_unordered(controls.capacity()),
+    );
+
+    // Consume the stream.
+    let mut failed_uploads = Vec::new();
+    while let Some(failed_upload) = requests.next().await {
+        failed_uploads.extend(failed_upload);
+    }
+    let failed = failed_uploads.len();
+
+    drop(requests);
+    watcher.abort();
+    if let Some(keys) = keys {
+        keys.finish();
+    }
+    if let Err(e) = failures.lock().unwrap().save() {
+        progress::note(format!("Failed to save failed uploads: {:#}", e));
+    }
+    if let Err(e) = checksums.lock().unwrap().save() {
+        progress::note(format!("Failed to save checksum cache: {:#}", e));
+    }
+    if failed > 0 {
+        progress::note(failures::summary(&failed_uploads));
+        progress::note(format!(
+            "{} uploads failed; run `upload --retry-failed` to try them again.",
+            failed
+        ));
+        if let Some(directory) = &options.failed_list {
+            let failed_paths: Vec<PathBuf> = failed_uploads
+                .into_iter()
+                .map(|(path, _, _)| path)
+                .collect();
+            let list = write_failed_list(directory, &failed_paths)?;
+            progress::note(format!(
+                "Failed files written to {:?} (usable with --files-from).",
+                list
+            ));
+        }
+    }
+    let interrupted = started < total;
+    if interrupted {
+        pb.abandon("Interrupted");
+        progress::note(format!(
+            "Interrupted: {} uploaded, {} failed, {} not started.",
+            started - failed,
+            failed,
+            total - started
+        ));
+    } else {
+        pb.finish("Upload complete");
+    }
+    let duplicates = duplicates.load(Ordering::Relaxed);
+    if duplicates > 0 {
+        progress::note(format!(
+            "{} of the uploaded files were already on the server; nothing new was stored \
+             for them.",
+            duplicates
+        ));
+    }
+    progress::emit(Event::RunSummary {
+        total,
+        uploaded: started - failed,
+        duplicates,
+        failed,
+        not_started: total - started,
+        elapsed_secs: pb.elapsed().as_secs(),
+    });
+    if let Err(e) = hooks::post_run(
+        &options.hooks,
+        total,
+        started - failed,
+        failed,
+        total - started,
+    )
+    .await
+    {
+        progress::note(format!("{:#}", e));
+    }
+
+    let album_assets = std::mem::take(&mut *album_assets.lock().unwrap());
+    if !album_assets.is_empty() {
+        let mut album_ids =
+            assign_albums(&client, &album_assets, &share_user_ids, options.share_role).await?;
+        if options.album_mirror.is_some() {
+            // A failed or skipped upload would look like a file that left its folder.
+            if failed == 0 && !interrupted && !options.incomplete {
+                // The --album target collects files from everywhere and is never pruned.
+                if let Some(album) = &options.album {
+                    album_ids.remove(album);
+                }
+                prune_mirrored_albums(&client, &album_ids, &album_assets, &device_id).await?;
+            } else {
+                progress::note(
+                    "Skipping album cleanup because some uploads failed or were skipped.",
+                );
+            }
+        }
+    }
+
+    let created = std::mem::take(&mut *created.lock().unwrap());
+    if options.process
+        && !interrupted
+        && let Err(e) = jobs::process(&client, &created).await
+    {
+        progress::note(format!("Could not start processing: {:#}", e));
+    }
+
+    // Failed and skipped files stay in the journal so that --resume retries them.
+    let journal = journal.and_then(Arc::into_inner);
+    if interrupted {
+        if journal.is_some() {
+            progress::note("Run again with --resume to continue where this run stopped.");
+        }
+        if outage.stopped.load(Ordering::SeqCst) {
+            return Err(anyhow::anyhow!("Server unreachable").context(ExitKind::Connection));
+        }
+        return Err(anyhow::anyhow!("Upload interrupted").context(ExitKind::Interrupted));
+    }
+    if failed == 0
+        && let Some(journal) = journal
+    {
+        journal.finish()?;
+    }
+    if let Some(timeout) = options.wait_for_processing {
+        jobs::wait_for_assets(&client, &created, timeout).await?;
+    }
+
+    exit::check_failures(failed, total)
+}
+
+/// Removes the items the server already has (matched by checksum) and returns them with
+/// the IDs of the existing assets.
+async fn skip_existing(
+    client: &ImmichClient,
+    items: &mut Vec<UploadItem>,
+    checksums: &mut ChecksumCache,
+    hash_all: bool,
+) -> Result<Vec<(UploadItem, Option<String>)>> {
+    progress::note(format!(
+        "Checking {} files against the server...",
+        items.len()
+    ));
+    // Unreadable files are left in; their upload fails and is reported as usual. So are
+    // files with no cached checksum unless `hash_all`: the server recognizes duplicates
+    // among them when they are uploaded.
+    let files: Vec<(String, String)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            let checksum = if hash_all {
+                checksums.checksum(&item.path).ok()
+            } else {
+                checksums.cached(&item.path)
+            };
+            Some((index.to_string(), checksum?))
+        })
+        .collect();
+    if files.len() < items.len() && !hash_all {
+        progress::note(format!(
+            "{} files not seen before are checked as they upload.",
+            items.len() - files.len()
+        ));
+    }
+    if let Err(e) = checksums.save() {
+        progress::note(format!("Failed to save checksum cache: {:#}", e));
+    }
+
+    let mut existing: HashMap<usize, Option<String>> = HashMap::new();
+    for batch in files.chunks(1000) {
+        for result in client.bulk_upload_check(batch).await? {
+            if result.action == "reject"
+                && result.reason.as_deref() == Some("duplicate")
+                && let Ok(index) = result.id.parse()
+            {
+                existing.insert(index, result.asset_id);
+            }
+        }
+    }
+
+    let mut skipped = Vec::new();
+    for (index, item) in std::mem::take(items).into_iter().enumerate() {
+        match existing.remove(&index) {
+            Some(asset_id) => skipped.push((item, asset_id)),
+            None => items.push(item),
+        }
+    }
+    Ok(skipped)
+}
+
+/// Waits for Ctrl-C (or SIGTERM) and stops the run; a second signal aborts immediately.
+async fn stop_on_interrupt(controls: Arc<Controls>, pb: Progress) {
+    if interrupt_signal().await.is_err() {
+        return;
+    }
+    controls.stop();
+    pb.println(
+        "Stopping: waiting for uploads in progress to finish (press Ctrl-C again to abort).",
+    );
+    if interrupt_signal().await.is_ok() {
+        controls::restore_terminal();
+        std::process::exit(130);
+    }
+}
+
+/// Seconds between checks whether an unreachable server is back.
+const SERVER_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Handling of the server becoming unreachable during a run, shared by all uploads so
+/// that one of them checks on the server while the others wait for the outcome.
+#[derive(Default)]
+struct Outage {
+    /// When the server was last found to be back after an outage.
+    recovered: tokio::sync::Mutex<Option<Instant>>,
+    /// Whether the run was stopped because the server went away.
+    stopped: AtomicBool,
+}
+
+impl Outage {
+    /// Called after a network error in an upload started at `started`; returns whether
+    /// it should be tried again. When the server can't be reached either, it pauses the
+    /// run and waits for the server with `wait`, or stops the run otherwise so the
+    /// remaining files are left for `--resume` rather than failing one by one.
+    async fn handle(
+        &self,
+        client: &ImmichClient,
+        controls: &Controls,
+        pb: &Progress,
+        started: Instant,
+        wait: bool,
+    ) -> bool {
+        let mut recovered = self.recovered.lock().await;
+        // The outage that broke this upload is over already.
+        if recovered.is_some_and(|at| at > started) {
+            return wait;
+        }
+        if self.stopped.load(Ordering::SeqCst) || client.ping().await.is_ok() {
+            return false;
+        }
+        if !wait {
+            self.stopped.store(true, Ordering::SeqCst);
+            if controls.stop() {
+                pb.warn("Server unreachable: not starting the remaining files.");
+            }
+            return false;
+        }
+        controls.set_paused(true);
+        pb.warn(format!(
+            "Server unreachable: waiting for it to come back, checking every {}s.",
+            SERVER_POLL_INTERVAL.as_secs()
+        ));
+        loop {
+            tokio::time::sleep(SERVER_POLL_INTERVAL).await;
+            if controls.is_stopped() {
+                return false;
+            }
+            if client.ping().await.is_ok() {
+                break;
+            }
+        }
+        *recovered = Some(Instant::now());
+        controls.set_paused(false);
+        pb.println("Server is back; continuing.");
+        true
+    }
+}
+
+/// Resolves on the next SIGINT or, on Unix, SIGTERM.
+async fn interrupt_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut term = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = term.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
+
+/// Drops everything a shared link cannot do: it may only upload into its own album,
+/// so albums, tags, descriptions and sharing are skipped with a note.
+fn strip_for_shared_link(items: &mut [UploadItem], options: &mut UploadOptions) {
+    let mut skipped = options.import_tags
+        || options.finder_tags
+        || options.import_titles
+        || options.album_mirror.is_some()
+        || options.album.is_some()
+        || !options.share_with.is_empty();
+    options.import_tags = false;
+    options.finder_tags = false;
+    options.import_titles = false;
+    options.album_mirror = None;
+    options.album = None;
+    options.share_with.clear();
+    for item in items {
+        skipped |= !item.albums.is_empty() || !item.tags.is_empty() || item.description.is_some();
+        item.albums.clear();
+        item.tags.clear();
+        item.description = None;
+    }
+    if skipped {
+        progress::note(
+            "Uploading through a shared link: albums, tags and descriptions are skipped; files go to the link's album.",
+        );
+    }
+}
+
+/// Finds the IDs of users given by email or name.
+async fn resolve_users(client: &ImmichClient, names: &[String]) -> Result<Vec<String>> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let users = client.list_users().await.context("Failed to list users")?;
+    names
+        .iter()
+        .map(|name| {
+            users
+                .iter()
+                .find(|u| u.email.eq_ignore_ascii_case(name) || u.name == *name)
+                .map(|u| u.id.clone())
+                .with_context(|| format!("No Immich user with email or name '{}'", name))
+        })
+        .collect()
+}
+
+/// Adds uploaded assets to albums by name, creating albums that don't exist yet and
+/// sharing newly created ones with `share_user_ids`.
+/// Returns the IDs of the albums by name.
+async fn assign_albums(
+    client: &ImmichClient,
+    album_assets: &HashMap<String, Vec<String>>,
+    share_user_ids: &[String],
+    share_role: ShareRole,
+) -> Result<HashMap<String, String>> {
+    let mut album_ids = HashMap::new();
+    let mut existing: HashMap<String, String> = client
+        .list_albums()
+        .await
+        .context("Failed to list albums")?
+        .into_iter()
+        .map(|a| (a.album_name, a.id))
+        .collect();
+
+    for (name, asset_ids) in album_assets {
+        let album_id = match existing.get(name) {
+            Some(id) => id.clone(),
+            None => {
+                let album = client
+                    .create_album(name, None)
+                    .await
+                    .with_context(|| format!("Failed to create album '{}'", name))?;
+                if !share_user_ids.is_empty() {
+                    client
+                        .share_album(&album.id, share_user_ids, share_role.as_str())
+                        .await
+                        .with_context(|| format!("Failed to share album '{}'", name))?;
+                    progress::note(format!(
+                        "Shared new album '{}' with {} users.",
+                        name,
+                        share_user_ids.len()
+                    ));
+                }
+                existing.insert(name.clone(), album.id.clone());
+                album.id
+            }
+        };
+        client
+            .add_assets_to_album(&album_id, asset_ids)
+            .await
+            .with_context(|| format!("Failed to add assets to album '{}'", name))?;
+        progress::note(format!(
+            "Added {} assets to album '{}'.",
+            asset_ids.len(),
+            name
+        ));
+        album_ids.insert(name.clone(), album_id);
+    }
+    Ok(album_ids)
+}
+
+/// Removes assets from mirrored albums that this tool uploaded but that are no longer in
+/// the matching folder (moved or deleted locally). Assets added by other means are kept.
+async fn prune_mirrored_albums(
+    client: &ImmichClient,
+    album_ids: &HashMap<String, String>,
+    album_assets: &HashMap<String, Vec<String>>,
+    device_id: &str,
+) -> Result<()> {
+    for (name, album_id) in album_ids {
+        let current = &album_assets[name];
+        let album = client.get_album(album_id).await?;
+        let stale: Vec<String> = album
+            .assets
+            .into_iter()
+            .filter(|a| a.device_id.as_deref() == Some(device_id) && !current.contains(&a.id))
+            .map(|a| a.id)
+            .collect();
+        if !stale.is_empty() {
+            client
+                .remove_assets_from_album(album_id, &stale)
+                .await
+                .with_context(|| format!("Failed to update album '{}'", name))?;
+            progress::note(format!(
+                "Removed {} assets from album '{}'.",
+                stale.len(),
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Walks `directory`, only its top level unless `recursive`, at most `max_depth` folder
+/// levels down, and without crossing into other mounts with `one_file_system`.
+pub fn walk(
+    directory: &Path,
+    recursive: bool,
+    max_depth: Option<usize>,
+    one_file_system: bool,
+) -> WalkDir {
+    let walker = WalkDir::new(directory).same_file_system(one_file_system);
+    // The directory itself is depth 0 and the files directly inside it depth 1.
+    match (recursive, max_depth) {
+        (false, _) => walker.max_depth(1),
+        (true, Some(levels)) => walker.max_depth(levels + 1),
+        (true, None) => walker,
+    }
+}
+
+/// Whether a file name marks a temporary or partial file, by the built-in patterns or
+/// the `extra` globs.
+pub fn is_partial_file(path: &Path, extra: &[String]) -> bool {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+        return false;
+    };
+    PARTIAL_FILES
+        .iter()
+        .copied()
+        .chain(extra.iter().map(String::as_str))
+        .any(|pattern| glob_match(pattern.as_bytes(), name.as_bytes()))
+}
+
+/// Whether a file was modified less than `age` ago. Files with an mtime in the future
+/// count as young too, as clock skew on a network share can't be ruled out.
+fn is_younger_than(entry: &walkdir::DirEntry, age: Duration) -> bool {
+    entry
+        .metadata()
+        .ok()
+        .and_then(|m| m.modified().ok())
+        .is_some_and(|modified| {
+            SystemTime::now()
+                .duration_since(modified)
+                .map_or(true, |elapsed| elapsed < age)
+        })
+}
+
+/// Files currently open in other processes, found through `/proc/<pid>/fd`. Processes of
+/// other users can't be inspected without root, so their files aren't detected.
+#[cfg(target_os = "linux")]
+fn open_files() -> HashSet<PathBuf> {
+    let own_pid = std::process::id().to_string();
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return HashSet::new();
+    };
+    processes
+        .filter_map(|p| p.ok())
+        .filter(|p| {
+            let name = p.file_name();
+            name != own_pid.as_str() && name.to_string_lossy().bytes().all(|b| b.is_ascii_digit())
+        })
+        .filter_map(|p| std::fs::read_dir(p.path().join("fd")).ok())
+        .flat_map(|fds| fds.filter_map(|fd| std::fs::read_link(fd.ok()?.path()).ok()))
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_files() -> HashSet<PathBuf> {
+    progress::note("--skip-open is only supported on Linux; checking no files.");
+    HashSet::new()
+}
+
+/// Checks if a file is an image or video: named like one and with matching contents, or
+/// with contents recognized as one when its name doesn't tell.
+pub fn is_image_or_video(path: &Path) -> bool {
+    (has_media_extension(path) || !mime::has_known_extension(path)) && media_type(path).is_some()
+}
+
+/// Whether a file is named like an image or video.
+fn has_media_extension(path: &Path) -> bool {
+    mime::is_media(&mime_guess::from_path(path).first_or_octet_stream())
+}
+
+/// The type of a file whose contents are an image or video, as far as they can be
+/// recognized.
+fn media_type(path: &Path) -> Option<mime::FileType> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    Some(mime::detect(path, &name)).filter(|file_type| mime::is_media(&file_type.mime))
+}
+
+/// Determines the creation and modification dates to report for a file.
+/// Uses the given capture date if any, otherwise the date in the file name or the file
+/// creation time, falling back to modification time or current time.
+fn file_dates(
+    path: &Path,
+    taken_at: Option<DateTime<Utc>>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let metadata = std::fs::metadata(path)?;
+    let created_at: DateTime<Utc> = match taken_at {
+        Some(taken_at) => taken_at,
+        None => filename_date::capture_date(
+            path,
+            metadata
+                .created()
+                .or_else(|_| metadata.modified())
+                .unwrap_or_else(|_| SystemTime::now())
+                .into(),
+        ),
+    };
+    let modified_at: DateTime<Utc> = metadata
+        .modified()
+        .unwrap_or_else(|_| SystemTime::now())
+        .into();
+    Ok((created_at, modified_at))
+}
+
+/// Creates a stable deviceAssetId from path hash to avoid duplicate uploads in some contexts.
+fn device_asset_id(path: &Path, device_id: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    format!("{}-{}", device_id, hasher.finish())
+}
+
+/// Size of the pieces upload bodies are sent in.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// The SHA-1 of the bytes of an upload body sent so far.
+#[derive(Default)]
+struct SentDigest {
+    hasher: sha1_smol::Sha1,
+    bytes: u64,
+}
+
+impl SentDigest {
+    fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.bytes += data.len() as u64;
+    }
+
+    /// The checksum of the file, if all of its `size` bytes went out. The server may
+    /// answer before reading the whole body, e.g. when the announced checksum is known.
+    fn checksum(&self, size: u64) -> Option<String> {
+        (self.bytes == size).then(|| checksums::encode(&self.hasher))
+    }
+}
+
+/// Opens a file as an upload part that is streamed from disk and hashed as it is sent,
+/// so the checksum of exactly what was uploaded is known without reading the file twice.
+/// `range` limits the part to some of the file's bytes, e.g. one half of a Motion Photo.
+async fn streamed_part(
+    path: &Path,
+    range: Option<Range<u64>>,
+    transfer: &Transfer,
+) -> Result<(multipart::Part, u64, Arc<Mutex<SentDigest>>)> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let range = match range {
+        Some(range) => range,
+        None => 0..file.metadata().await?.len(),
+    };
+    file.seek(SeekFrom::Start(range.start)).await?;
+    let size = range.end - range.start;
+    let reader = file.take(size);
+    let hasher = Arc::new(Mutex::new(SentDigest::default()));
+    let chunks = futures::stream::unfold(
+        (reader, Arc::clone(&hasher), transfer.clone()),
+        |(mut reader, hasher, transfer)| async move {
+            let mut buffer = vec![0u8; CHUNK_SIZE];
+            match reader.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(read) => {
+                    buffer.truncate(read);
+                    hasher.lock().unwrap().update(&buffer);
+                    transfer.add(read as u64);
+                    Some((Ok(buffer), (reader, hasher, transfer)))
+                }
+                Err(e) => Some((Err(e), (reader, hasher, transfer))),
+            }
+        },
+    );
+    let part = multipart::Part::stream_with_length(reqwest::Body::wrap_stream(chunks), size);
+    Ok((part, size, hasher))
+}
+
+/// Builds the multipart form shared by all uploads.
+fn asset_form(
+    part: multipart::Part,
+    filename: &str,
+    mime: &mime_guess::Mime,
+    path: &Path,
+    device_id: &str,
+    (created_at, modified_at): (DateTime<Utc>, DateTime<Utc>),
+) -> Result<multipart::Form> {
+    let part = part
+        .file_name(filename.to_string())
+        .mime_str(mime.as_ref())?;
+
+    Ok(multipart::Form::new()
+        .part("assetData", part)
+        .text("deviceAssetId", device_asset_id(path, device_id))
+        .text("deviceId", device_id.to_string())
+        .text("fileCreatedAt", created_at.to_rfc3339())
+        .text("fileModifiedAt", modified_at.to_rfc3339()))
+}
+
+/// Metadata a local file gives its asset besides its contents and dates.
+pub struct CarriedMetadata {
+    /// Whether the asset is a favorite, when the file says either way.
+    pub favorite: Option<bool>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+}
+
+/// The favorite flag, tags and description of an item: those it was given, and as the
+/// options ask, those from its embedded rating, keywords and title and its Finder tags.
+pub fn carried_metadata(item: &UploadItem, options: &UploadOptions) -> CarriedMetadata {
+    let path = item.path.as_path();
+    // Carry over curation done in Lightroom/digiKam: ratings as favorites, keywords as tags.
+    let embedded =
+        if options.favorite_rating.is_some() || options.import_tags || options.import_titles {
+            info_span!("metadata").in_scope(|| EmbeddedMetadata::from_file(path))
+        } else {
+            EmbeddedMetadata::default()
+        };
+    let rating = item.rating.or(embedded.rating);
+    let mut finder_tags = if options.finder_tags {
+        metadata::finder_tags(path)
+    } else {
+        Vec::new()
+    };
+    let finder_favorite = finder_tags.iter().any(|tag| is_favorite_tag(tag));
+    finder_tags.retain(|tag| !is_favorite_tag(tag));
+    let favorite = item.favorite.or_else(|| {
+        let rated = options
+            .favorite_rating
+            .is_some_and(|min| rating.is_some_and(|rating| rating >= min));
+        (finder_favorite || rated).then_some(true)
+    });
+
+    let mut tags = item.tags.clone();
+    if options.import_tags {
+        tags.extend(embedded.keywords);
+    }
+    tags.extend(finder_tags);
+    tags.sort();
+    tags.dedup();
+
+    let description = item
+        .description
+        .clone()
+        .or(embedded.title.filter(|_| options.import_titles));
+    CarriedMetadata {
+        favorite,
+        tags,
+        description,
+    }
+}
+
+/// Whether a Finder tag marks a favorite, as Finder has no favorite flag of its own.
+fn is_favorite_tag(tag: &str) -> bool {
+    tag.eq_ignore_ascii_case("favorite") || tag.eq_ignore_ascii_case("favorites")
+}
+
+/// Checks that the server stored exactly the bytes that were sent, catching corruption
+/// by proxies along the way.
+async fn verify_upload(
+    client: &ImmichClient,
+    asset_id: &str,
+    checksum: &str,
+    size: u64,
+) -> Result<()> {
+    let asset = client
+        .get_asset(asset_id)
+        .await
+        .context("Failed to fetch asset for verification")?;
+    if asset.checksum.as_deref() != Some(checksum) {
+        anyhow::bail!(
+            "Checksum mismatch for asset {}: local {}, server {}",
+            asset_id,
+            checksum,
+            asset.checksum.as_deref().unwrap_or("none")
+        );
+    }
+    // Older servers may not report the size; the checksum already covers it.
+    if let Some(server_size) = asset.exif_info.and_then(|e| e.file_size_in_byte)
+        && server_size != size
+    {
+        anyhow::bail!(
+            "Size mismatch for asset {}: local {} bytes, server {} bytes",
+            asset_id,
+            size,
+            server_size
+        );
+    }
+    Ok(())
+}
+
+/// Uploads the motion part of a Live Photo and returns its asset ID.
+async fn upload_live_photo_video(
+    client: &ImmichClient,
+    path: &Path,
+    taken_at: Option<DateTime<Utc>>,
+    device_id: &str,
+    transfer: &Transfer,
+) -> Result<Option<String>> {
+    let dates = file_dates(path, taken_at)?;
+    let filename = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("Invalid filename")?;
+    let (part, _, _) = streamed_part(path, None, transfer).await?;
+    let mime = mime::detect(path, filename).mime;
+    let form = asset_form(part, filename, &mime, path, device_id, dates)?;
+    Ok(client.upload_asset(form).await?.map(|asset| asset.id))
+}
+
+/// Bytes uploading an item sends: the file and its Live Photo video, if any.
+fn item_size(item: &UploadItem) -> u64 {
+    std::iter::once(&item.path)
+        .chain(&item.live_photo_video)
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Uploads a single file to the Immich server with appropriate metadata.
+/// Returns the asset when the server reports it.
+async fn upload_file(
+    client: &ImmichClient,
+    item: &UploadItem,
+    device_id: &str,
+    options: &UploadOptions,
+    checksums: &Mutex<ChecksumCache>,
+    transfer: &Transfer,
+) -> Result<Option<UploadResponse>> {
+    let path = item.path.as_path();
+    let dates = file_dates(path, item.taken_at)?;
+
+    let mut filename = match &item.filename {
+        Some(filename) => filename.clone(),
+        None => path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("Invalid filename")?
+            .to_string(),
+    };
+    let file_type = item.file_type();
+    // The server goes by the extension, so media found by its contents is uploaded under
+    // a name with the usual one, e.g. "IMG_0001" as "IMG_0001.heic".
+    if !has_media_extension(Path::new(&filename))
+        && mime::is_media(&file_type.mime)
+        && let Some(extension) = file_type.extension
+    {
+        filename = format!("{}.{}", filename, extension);
+    }
+    let filename = filename.as_str();
+
+    let carried = carried_metadata(item, options);
+    let motion_offset = (options.split_motion_photos
+        && file_type.mime == mime_guess::mime::IMAGE_JPEG)
+        .then(|| info_span!("motion_photo").in_scope(|| metadata::motion_photo_video_offset(path)))
+        .flatten();
+    let (part, size, hasher) = streamed_part(path, motion_offset.map(|end| 0..end), transfer)
+        .instrument(info_span!("open"))
+        .await?;
+    // Files are only hashed as they are sent, so only a cached checksum can be announced,
+    // and only for the whole file.
+    let announced = motion_offset
+        .is_none()
+        .then(|| checksums.lock().unwrap().cached(path))
+        .flatten();
+
+    let mut form = asset_form(part, filename, &file_type.mime, path, device_id, dates)?
+        .text("isFavorite", carried.favorite.unwrap_or(false).to_string());
+    if let Some(visibility) = item.visibility.or(options.visibility) {
+        form = form.text("visibility", visibility.as_str());
+    }
+
+    // The motion part must exist before the still can be linked to it.
+    let split = motion_offset.is_some();
+    if let Some(offset) = motion_offset {
+        let video_path = path.with_extension("mp4");
+        let video_name = video_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("Invalid filename")?;
+        let file_size = std::fs::metadata(path)?.len();
+        let (video, _, _) = streamed_part(path, Some(offset..file_size), transfer).await?;
+        let video_mime = mime_guess::from_path(&video_path).first_or_octet_stream();
+        let video_form = asset_form(
+            video,
+            video_name,
+            &video_mime,
+            &video_path,
+            device_id,
+            dates,
+        )?;
+        let video_id = client
+            .upload_asset(video_form)
+            .await
+            .context("Failed to upload Motion Photo video")?;
+        if let Some(video) = video_id {
+            form = form.text("livePhotoVideoId", video.id);
+        }
+    } else if let Some(video) = &item.live_photo_video {
+        let video_id = upload_live_photo_video(client, video, item.taken_at, device_id, transfer)
+            .await
+            .with_context(|| format!("Failed to upload Live Photo video {:?}", video))?;
+        if let Some(video_id) = video_id {
+            form = form.text("livePhotoVideoId", video_id);
+        }
+    }
+
+    let Some(asset) = client
+        .upload_asset_with_checksum(form, announced.as_deref())
+        .instrument(info_span!("send", bytes = size))
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    // When the whole file went out, this is the checksum of what the server received. A
+    // split Motion Photo was sent without its video, which isn't the file's checksum. A
+    // duplicate recognized by its announced checksum is answered before the body is read,
+    // leaving nothing new to cache or verify.
+    let sent = hasher.lock().unwrap().checksum(size);
+    if let Some(checksum) = sent {
+        if !split {
+            checksums.lock().unwrap().insert(path, checksum.clone());
+        }
+        if options.verify {
+            verify_upload(client, &asset.id, &checksum, size)
+                .instrument(info_span!("verify"))
+                .await?;
+        }
+    }
+
+    if !carried.tags.is_empty() {
+        let tags = client
+            .upsert_tags(&carried.tags)
+            .await
+            .context("Failed to create tags")?;
+        let tag_ids: Vec<String> = tags.into_iter().map(|t| t.id).collect();
+        client
+            .tag_assets(&tag_ids, std::slice::from_ref(&asset.id))
+            .await
+            .context("Failed to tag asset")?;
+    }
+
+    if let Some(description) = &carried.description {
+        client
+            .update_asset_description(&asset.id, description)
+            .await
+            .context("Failed to set description")?;
+    }
+
+    Ok(Some(asset))
+}